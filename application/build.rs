@@ -14,6 +14,4 @@ fn main() {
 
 
 #[cfg(not(windows))]
-fn main() {
-	panic!("Non Windows not implemented.")
-}
\ No newline at end of file
+fn main() {}
\ No newline at end of file
@@ -0,0 +1,150 @@
+use anyhow::Result;
+use chrono::{TimeZone, Utc};
+use eframe::egui;
+use log::error;
+
+
+use crate::{Config, DataRevision, StorageContainer};
+
+
+/// Window for editing the title, note and text of a copy. Shared by the tabs which list copies.
+#[derive(Default)]
+pub struct Editor {
+	editing: Option<EditingItem>,
+}
+
+struct EditingItem {
+	data_id: usize,
+	title: String,
+	note: String,
+	/// Only text can be edited.
+	text: Option<String>,
+	html: String,
+	revisions: Vec<DataRevision>,
+	/// Why it couldn't be saved.
+	error: Option<String>,
+}
+
+
+impl Editor {
+	pub fn open(&mut self, data_id: usize, store: &StorageContainer) {
+		let data = match store.get_data(data_id) {
+			Ok(v) => v,
+			Err(e) => {
+				error!(target: "clipboard_gui", "{:?}", e);
+				return;
+			}
+		};
+
+		let revisions = match store.get_revisions(data_id) {
+			Ok(v) => v,
+			Err(e) => {
+				error!(target: "clipboard_gui", "{:?}", e);
+				Vec::new()
+			}
+		};
+
+		self.editing = Some(EditingItem {
+			data_id,
+			title: data.title.unwrap_or_default(),
+			note: data.note.unwrap_or_default(),
+			text: if data.type_of == 0 { Some(data.text_data.unwrap_or_default()) } else { None },
+			html: data.html_data.unwrap_or_default(),
+			revisions,
+			error: None,
+		});
+	}
+
+	/// Returns true once something was saved so the list can be reloaded.
+	pub fn show(&mut self, ctx: &egui::CtxRef, store: &StorageContainer, config: &Config) -> bool {
+		let editing = match self.editing.as_mut() {
+			Some(v) => v,
+			None => return false
+		};
+
+		let mut saved = false;
+		let mut cancelled = false;
+
+		egui::Window::new("Edit")
+		.collapsible(false)
+		.show(ctx, |ui| {
+			ui.label("Title");
+			ui.add(egui::TextEdit::singleline(&mut editing.title).hint_text("Shown instead of the text").desired_width(f32::INFINITY));
+
+			ui.label("Note");
+			ui.add(egui::TextEdit::multiline(&mut editing.note).desired_rows(2).desired_width(f32::INFINITY));
+
+			if let Some(text) = editing.text.as_mut() {
+				ui.label("Text");
+				ui.add(egui::TextEdit::multiline(text).code_editor().desired_rows(8).desired_width(f32::INFINITY));
+
+				ui.collapsing("HTML", |ui| {
+					ui.add(egui::TextEdit::multiline(&mut editing.html).code_editor().hint_text("None").desired_rows(4).desired_width(f32::INFINITY));
+				});
+
+				if !editing.revisions.is_empty() {
+					ui.collapsing(format!("Revisions ({})", editing.revisions.len()), |ui| {
+						egui::ScrollArea::vertical()
+						.max_height(150.0)
+						.show(ui, |ui| {
+							for revision in &editing.revisions {
+								let date = Utc.timestamp_millis_opt(revision.date as i64)
+									.single()
+									.map(|v| v.with_timezone(&chrono::offset::Local).format(&config.app.timedate_format).to_string())
+									.unwrap_or_default();
+
+								let revision_text = revision.text_data.as_deref().unwrap_or_default();
+
+								ui.horizontal(|ui| {
+									if ui.small_button("Load").on_hover_text(revision_text).clicked() {
+										*text = revision_text.to_string();
+										editing.html = revision.html_data.clone().unwrap_or_default();
+									}
+
+									ui.add(egui::Label::new(format!("{} - {}", date, revision_text.replace('\n', " "))).wrap(false));
+								});
+							}
+						});
+					});
+				}
+			}
+
+			if let Some(error) = editing.error.as_deref() {
+				ui.colored_label(egui::Color32::RED, error);
+			}
+
+			ui.horizontal(|ui| {
+				saved = ui.button("Save").clicked();
+				cancelled = ui.button("Cancel").clicked();
+			});
+		});
+
+		if saved {
+			match save(editing, store, config) {
+				Ok(()) => self.editing = None,
+				Err(e) => editing.error = Some(e.to_string()),
+			}
+		} else if cancelled {
+			self.editing = None;
+		}
+
+		saved && self.editing.is_none()
+	}
+}
+
+
+fn save(editing: &EditingItem, store: &StorageContainer, config: &Config) -> Result<()> {
+	let mut data_id = editing.data_id;
+
+	// Editing can merge it into other data, so the title and note are set on whatever is left.
+	if let Some(text) = editing.text.clone() {
+		let html = Some(editing.html.clone()).filter(|v| !v.is_empty());
+
+		data_id = store.edit_text(data_id, text, html, config)?;
+	}
+
+	store.set_title(data_id, &editing.title)?;
+	store.set_note(data_id, &editing.note)?;
+
+	Ok(())
+}
@@ -1,21 +1,36 @@
 use std::sync::{RwLock, Arc};
+use std::time::{Duration, Instant};
 
 use eframe::{egui::{self, TextureId}, epi};
 use log::error;
 
-use crate::{Config, StorageContainer, ReturnedItem, item_time_ago, ReturnedItemType};
+use crate::{Config, StorageContainer, ReturnedItem, item_time_ago, ReturnedItemType, SNIPPET_MATCH_START, SNIPPET_MATCH_END};
 
 
+mod editor;
 mod recent;
 mod search;
 mod settings;
 mod starred;
+mod trash;
+
+
+/// Tag names shown on each row.
+const TAG_COLOR: egui::Color32 = egui::Color32::from_rgb(140, 190, 255);
+
+/// How long the Undo button is shown after deleting.
+const UNDO_DURATION: Duration = Duration::from_secs(6);
 
 
 pub trait Tab {
 	fn on_open(&mut self, _frame: &epi::Frame, _store: &StorageContainer, _config: &mut Config) {}
 	fn on_close(&mut self, _frame: &epi::Frame) {}
 
+	/// Data moved to the trash since it was last called. Shown with an Undo button.
+	fn take_deleted(&mut self) -> Option<usize> {
+		None
+	}
+
 	fn update(&mut self, ctx: &egui::CtxRef, frame: &epi::Frame, store: &StorageContainer, config: &mut Config);
 }
 
@@ -26,6 +41,8 @@ pub struct App {
 	tabs: Vec<Box<dyn Tab>>,
 	store: StorageContainer,
 	config: Arc<RwLock<Config>>,
+	/// Last deleted data and when.
+	undo: Option<(usize, Instant)>,
 }
 
 impl App {
@@ -34,10 +51,12 @@ impl App {
 			config,
 			store,
 			viewing_tab: 0,
+			undo: None,
 			tabs: vec![
 				Box::new(recent::RecentTab::default()),
 				Box::new(starred::StarredTab::default()),
 				Box::new(search::SearchTab::default()),
+				Box::new(trash::TrashTab::default()),
 				Box::new(settings::SettingsTab::default()),
 			]
 		}
@@ -60,7 +79,7 @@ impl epi::App for App {
 		egui::TopBottomPanel::top("top_panel")
 		.show(ctx, |ui| {
 			egui::menu::bar(ui, |ui| {
-				let buttons = ["Recent", "Starred", "Search", "Settings"];
+				let buttons = ["Recent", "Starred", "Search", "Trash", "Settings"];
 
 				for (index, text) in buttons.into_iter().enumerate() {
 					if ui.selectable_label(self.viewing_tab == index, text).clicked() {
@@ -77,7 +96,44 @@ impl epi::App for App {
 			});
 		});
 
+		if let Some((data_id, deleted)) = self.undo {
+			if deleted.elapsed() < UNDO_DURATION {
+				let mut clicked_undo = false;
+
+				egui::TopBottomPanel::bottom("undo_panel")
+				.show(ctx, |ui| {
+					ui.horizontal(|ui| {
+						ui.label("Moved to the trash.");
+						clicked_undo = ui.button("Undo").clicked();
+					});
+				});
+
+				if clicked_undo {
+					self.undo = None;
+
+					match self.store.restore(data_id) {
+						Ok(_) => {
+							// Reload the tab so it shows up again.
+							self.tabs[self.viewing_tab].on_close(frame);
+							self.tabs[self.viewing_tab].on_open(frame, &self.store, config);
+						}
+
+						Err(e) => error!(target: "clipboard_gui", "{:?}", e),
+					}
+				}
+
+				// Hide it once it's expired.
+				ctx.request_repaint();
+			} else {
+				self.undo = None;
+			}
+		}
+
 		self.tabs[self.viewing_tab].update(ctx, frame, &self.store, config);
+
+		if let Some(data_id) = self.tabs[self.viewing_tab].take_deleted() {
+			self.undo = Some((data_id, Instant::now()));
+		}
 	}
 
 	fn name(&self) -> &str {
@@ -119,15 +175,24 @@ impl epi::App for App {
 
 
 
+/// Clicked on a row, for its tab to carry out.
+pub enum RowAction {
+	/// Move it to the trash.
+	Delete,
+	/// Open it in the editor.
+	Edit,
+}
+
 pub fn display_scroll_row(
 	ui: &mut egui::Ui,
 	desired_size: egui::Vec2,
 	item: &mut ReturnedItem,
 	config: &mut Config,
 	store: &StorageContainer,
-	removed_data_index: &mut Option<usize>,
 	now: chrono::DateTime<chrono::Utc>
-) {
+) -> Option<RowAction> {
+	let mut row_action = None;
+
 	ui.allocate_ui_with_layout(desired_size, egui::Layout::right_to_left(), |ui| {
 		ui.set_height(desired_size.y);
 
@@ -138,49 +203,232 @@ pub fn display_scroll_row(
 			}
 
 			if ui.add(egui::Button::new("❌")).on_hover_text("Delete").clicked() {
-				*removed_data_index = Some(item.data_id);
+				row_action = Some(RowAction::Delete);
 			}
 		});
 
+		let mut action = None;
+
 		match &item.value {
 			ReturnedItemType::Text(text_data) => {
 				ui.allocate_ui_with_layout(ui.available_size(), egui::Layout::top_down(egui::Align::LEFT), |ui| {
 					ui.set_clip_rect(ui.available_rect_before_wrap());
 
+					let text_hover = if item.is_masked { "Hidden by a privacy rule" } else { text_data.as_str() };
+
+					let (label_text, hover_text): (egui::WidgetText, _) = if let Some(title) = item.title.as_deref() {
+						let hover_text = match item.note.as_deref() {
+							Some(note) => format!("{}\n\n{}", note, text_hover),
+							None => text_hover.to_string()
+						};
+
+						(egui::RichText::new(title).color(egui::Rgba::from_rgb(1.0, 1.0, 1.0)).strong().into(), hover_text)
+					} else if item.is_masked {
+						(egui::RichText::new("********").color(egui::Rgba::from_rgb(1.0, 1.0, 1.0)).into(), text_hover.to_string())
+					} else if let Some(snippet) = item.snippet.as_deref() {
+						(snippet_layout(snippet).into(), text_hover.to_string())
+					} else {
+						(egui::RichText::new(text_data.replace(['\n', '\t'], " ")).color(egui::Rgba::from_rgb(1.0, 1.0, 1.0)).into(), text_hover.to_string())
+					};
+
 					let clicked_label = ui.add(
-						egui::Label::new(
-							egui::RichText::new(text_data.replace('\n', " ").replace('\t', " "))
-								.color(egui::Rgba::from_rgb(1.0, 1.0, 1.0))
-						)
+						egui::Label::new(label_text)
 						.wrap(false)
 						.sense(egui::Sense::click())
-					).on_hover_text(text_data.as_str()).on_hover_cursor(egui::CursorIcon::PointingHand).clicked();
+					).on_hover_text(hover_text).on_hover_cursor(egui::CursorIcon::PointingHand)
+						.context_menu(|ui| row_menu(ui, item, store, &mut action))
+						.clicked();
 
 					if clicked_label {
-						ui.output().copied_text = text_data.clone();
+						if let Err(e) = clipboard_common::set_clipboard(item.data_id, store) {
+							error!(target: "clipboard_gui", "Copy Text Error: {:?}", e);
+						}
 					}
 
-					ui.add(egui::Label::new(egui::RichText::new(item_time_ago(item.timestamp, now))).wrap(false))
-						.on_hover_text(item.timestamp.with_timezone(&chrono::offset::Local).format(&config.app.timedate_format).to_string());
+					display_item_time(ui, item, config, now);
+				});
+			}
+
+			ReturnedItemType::Files(paths) => {
+				ui.allocate_ui_with_layout(ui.available_size(), egui::Layout::top_down(egui::Align::LEFT), |ui| {
+					ui.set_clip_rect(ui.available_rect_before_wrap());
+
+					let file_names = paths.iter()
+						.map(|path| std::path::Path::new(path).file_name().map(|v| v.to_string_lossy().into_owned()).unwrap_or_else(|| path.clone()))
+						.collect::<Vec<_>>()
+						.join(", ");
+
+					let label_text = match item.title.as_deref() {
+						Some(title) => egui::RichText::new(format!("📄 {}", title)).strong(),
+						None => egui::RichText::new(format!("📄 {}", file_names))
+					};
+
+					let clicked_label = ui.add(
+						egui::Label::new(label_text.color(egui::Rgba::from_rgb(1.0, 1.0, 1.0)))
+						.wrap(false)
+						.sense(egui::Sense::click())
+					).on_hover_text(paths.join("\n")).on_hover_cursor(egui::CursorIcon::PointingHand)
+						.context_menu(|ui| row_menu(ui, item, store, &mut action))
+						.clicked();
+
+					if clicked_label {
+						if let Err(e) = clipboard_common::set_clipboard(item.data_id, store) {
+							error!(target: "clipboard_gui", "Copy Files Error: {:?}", e);
+						}
+					}
+
+					display_item_time(ui, item, config, now);
 				});
 			}
 
 			&ReturnedItemType::ThumbTextureId(texture_id) => {
 				ui.with_layout(egui::Layout::from_main_dir_and_cross_align(egui::Direction::LeftToRight, egui::Align::BOTTOM), |ui| {
-					if ui.add(egui::ImageButton::new(TextureId::User(texture_id), [32.0, 32.0]).frame(false)).clicked() {
-						if let Err(e) = clipboard_common::set_clipboard_image(item.data_id, store) {
+					let mut image = ui.add(egui::ImageButton::new(TextureId::User(texture_id), [32.0, 32.0]).frame(false));
+
+					if let Some(title) = item.title.as_deref() {
+						image = image.on_hover_text(title);
+					}
+
+					let clicked_image = image
+						.context_menu(|ui| row_menu(ui, item, store, &mut action))
+						.clicked();
+
+					if clicked_image {
+						if let Err(e) = clipboard_common::set_clipboard(item.data_id, store) {
 							error!(target: "clipboard_gui", "Copy Image Error: {:?}", e);
 						}
 					}
 
-					ui.add(egui::Label::new(egui::RichText::new(item_time_ago(item.timestamp, now))).wrap(false))
-						.on_hover_text(item.timestamp.with_timezone(&chrono::offset::Local).format(&config.app.timedate_format).to_string());
+					display_item_time(ui, item, config, now);
 				});
 			}
 
 			ReturnedItemType::Thumb(_) => {} // Should never be ran.
 		}
+
+		match action {
+			Some(MenuAction::Tagged(tags)) => item.tags = tags,
+
+			Some(MenuAction::Pinned(is_pinned)) => item.is_pinned = is_pinned,
+
+			Some(MenuAction::Edit) => row_action = Some(RowAction::Edit),
+
+			None => ()
+		}
 	});
+
+	row_action
+}
+
+
+fn display_item_time(ui: &mut egui::Ui, item: &ReturnedItem, config: &Config, now: chrono::DateTime<chrono::Utc>) {
+	let mut time_ago = item_time_ago(item.timestamp, now);
+	let mut hover_text = item.timestamp.with_timezone(&chrono::offset::Local).format(&config.app.timedate_format).to_string();
+
+	if let Some(source_app) = item.source_app.as_deref() {
+		time_ago += &format!(" - {}", source_app);
+		hover_text += &format!("\nCopied from {}", source_app);
+	}
+
+	ui.horizontal(|ui| {
+		if item.is_pinned {
+			ui.label("📌").on_hover_text("Pinned");
+		}
+
+		ui.add(egui::Label::new(egui::RichText::new(time_ago)).wrap(false))
+			.on_hover_text(hover_text);
+
+		for tag in &item.tags {
+			ui.add(egui::Label::new(egui::RichText::new(tag).small().color(TAG_COLOR)).wrap(false));
+		}
+	});
+}
+
+/// Chosen from the right click menu of a row.
+enum MenuAction {
+	/// The item's tags now.
+	Tagged(Vec<String>),
+	/// Whether the item is pinned now.
+	Pinned(bool),
+	/// Open it in the editor.
+	Edit,
+}
+
+/// Right click menu to edit, pin or tag the item. Tags are created in the Starred tab.
+fn row_menu(ui: &mut egui::Ui, item: &ReturnedItem, store: &StorageContainer, action: &mut Option<MenuAction>) {
+	if ui.button("✏ Edit").clicked() {
+		*action = Some(MenuAction::Edit);
+	}
+
+	let pin_text = if item.is_pinned { "📌 Unpin" } else { "📌 Pin to Top" };
+
+	if ui.button(pin_text).on_hover_text("Pinned copies are kept above the Recent list").clicked() {
+		match store.set_pinned(item.data_id, !item.is_pinned) {
+			Ok(_) => *action = Some(MenuAction::Pinned(!item.is_pinned)),
+			Err(e) => error!(target: "clipboard_gui", "{:?}", e),
+		}
+	}
+
+	ui.separator();
+
+	let tags = match store.get_tags() {
+		Ok(v) => v,
+		Err(e) => {
+			error!(target: "clipboard_gui", "{:?}", e);
+			return;
+		}
+	};
+
+	if tags.is_empty() {
+		ui.label("No tags yet. Create them in the Starred tab.");
+		return;
+	}
+
+	for tag in tags {
+		let mut is_tagged = item.tags.iter().any(|v| v.eq_ignore_ascii_case(&tag.name));
+
+		if ui.checkbox(&mut is_tagged, tag.name.as_str()).changed() {
+			let result = if is_tagged {
+				store.add_tag(item.data_id, &tag.name)
+			} else {
+				store.remove_tag(item.data_id, &tag.name)
+			};
+
+			match result.and_then(|_| store.get_data_tags(item.data_id)) {
+				Ok(tags) => *action = Some(MenuAction::Tagged(tags)),
+				Err(e) => error!(target: "clipboard_gui", "{:?}", e),
+			}
+		}
+	}
+}
+
+/// Search result snippet with the matches highlighted.
+fn snippet_layout(snippet: &str) -> egui::text::LayoutJob {
+	let plain = egui::text::TextFormat {
+		color: egui::Color32::WHITE,
+		..Default::default()
+	};
+
+	let highlighted = egui::text::TextFormat {
+		color: egui::Color32::BLACK,
+		background: egui::Color32::from_rgb(255, 210, 80),
+		..Default::default()
+	};
+
+	let mut job = egui::text::LayoutJob::default();
+
+	for (index, part) in snippet.replace(['\n', '\t'], " ").split(SNIPPET_MATCH_START).enumerate() {
+		if index == 0 {
+			job.append(part, 0.0, plain);
+		} else {
+			let (matched, rest) = part.split_once(SNIPPET_MATCH_END).unwrap_or((part, ""));
+
+			job.append(matched, 0.0, highlighted);
+			job.append(rest, 0.0, plain);
+		}
+	}
+
+	job
 }
 
 
@@ -3,21 +3,30 @@ use std::time::{Instant, Duration};
 use chrono::Utc;
 use clipboard_common::config::Config;
 use eframe::{egui, epi};
+use log::error;
 
 
 use crate::{Tab, ReturnedItem, StorageContainer, StorageQuery};
 
+use super::RowAction;
+use super::editor::Editor;
+
 
 
 
 pub struct RecentTab {
 	items: Vec<ReturnedItem>,
+	/// Kept above the recents.
+	pinned: Vec<ReturnedItem>,
+	editor: Editor,
 	// Scroll
 	loading_more_items: bool,
 	last_called_load_scroll: Instant,
 	can_load_more_data: bool, // Can we continue scrolling.
 	// Auto-load new data
 	last_auto_recent_check: Instant,
+	/// Moved to the trash since the App last asked.
+	deleted: Option<usize>,
 }
 
 impl Default for RecentTab {
@@ -27,19 +36,54 @@ impl Default for RecentTab {
 			last_auto_recent_check: Instant::now(),
 			loading_more_items: false,
 			can_load_more_data: true,
-			items: Vec::new()
+			items: Vec::new(),
+			pinned: Vec::new(),
+			editor: Editor::default(),
+			deleted: None
 		}
 	}
 }
 
 
+impl RecentTab {
+	fn fetch_pinned(&mut self, frame: &epi::Frame, store: &StorageContainer) {
+		self.pinned.clear();
+
+		match store.query(StorageQuery::Pinned) {
+			Ok(new_items) => super::prepend_new_items_into_existing(&mut self.pinned, new_items, frame),
+			Err(e) => error!(target: "clipboard_gui", "{:?}", e),
+		}
+
+		for item in &mut self.items {
+			item.is_pinned = self.pinned.iter().any(|v| v.data_id == item.data_id);
+		}
+	}
+
+	/// Pinned or unpinned from the right click menu since they were fetched.
+	fn have_pins_changed(&self) -> bool {
+		self.pinned.iter().any(|v| !v.is_pinned) ||
+		self.items.iter().any(|item| item.is_pinned != self.pinned.iter().any(|v| v.data_id == item.data_id))
+	}
+}
+
 impl Tab for RecentTab {
 	fn on_close(&mut self, _frame: &epi::Frame) {
 		self.items.clear();
+		self.pinned.clear();
 		self.loading_more_items = false;
 	}
 
+	fn take_deleted(&mut self) -> Option<usize> {
+		self.deleted.take()
+	}
+
 	fn update(&mut self, ctx: &egui::CtxRef, frame: &epi::Frame, store: &StorageContainer, config: &mut Config) {
+		// Reloaded below since editing can change or merge items.
+		if self.editor.show(ctx, store, config) {
+			self.items.clear();
+			self.can_load_more_data = true;
+		}
+
 		// Initial load of items.
 		if self.items.is_empty() && !self.loading_more_items {
 			self.loading_more_items = true;
@@ -51,6 +95,8 @@ impl Tab for RecentTab {
 
 			super::prepend_new_items_into_existing(&mut self.items, new_items, frame);
 
+			self.fetch_pinned(frame, store);
+
 			self.loading_more_items = false;
 		}
 
@@ -71,33 +117,66 @@ impl Tab for RecentTab {
 		}
 
 
+		let mut edited_data_index: Option<usize> = None;
+
 		egui::CentralPanel::default()
 		.show(ctx, |ui| {
+			if !self.pinned.is_empty() {
+				let mut removed_data_index: Option<usize> = None;
+
+				let desired_size = egui::vec2(ui.available_width(), 40.0);
+
+				let now = Utc::now();
+
+				for item in &mut self.pinned {
+					match super::display_scroll_row(ui, desired_size, item, config, store, now) {
+						Some(RowAction::Delete) => removed_data_index = Some(item.data_id),
+						Some(RowAction::Edit) => edited_data_index = Some(item.data_id),
+						None => ()
+					}
+					ui.separator();
+				}
+
+				if let Some(data_id) = removed_data_index {
+					self.pinned.retain(|v| v.data_id != data_id);
+					self.items.retain(|v| v.data_id != data_id);
+
+					store.delete(data_id).unwrap();
+
+					self.deleted = Some(data_id);
+				}
+
+				ui.separator();
+			}
+
 			egui::ScrollArea::vertical()
 			.show_viewport(ui, |ui, rect| {
 				let mut removed_data_index: Option<usize> = None;
 
 				let desired_size = egui::vec2(ui.available_width(), 40.0);
 
-				let total_height = self.items.len() as f32 * desired_size.y;
+				// Pinned copies are already shown above. They're kept in the list so paging by recents still lines up.
+				let total_height = self.items.iter().filter(|v| !v.is_pinned).count() as f32 * desired_size.y;
 
 				let now = Utc::now();
 
-				for item in &mut self.items {
-					super::display_scroll_row(ui, desired_size, item, config, store, &mut removed_data_index, now);
+				for item in self.items.iter_mut().filter(|v| !v.is_pinned) {
+					match super::display_scroll_row(ui, desired_size, item, config, store, now) {
+						Some(RowAction::Delete) => removed_data_index = Some(item.data_id),
+						Some(RowAction::Edit) => edited_data_index = Some(item.data_id),
+						None => ()
+					}
 					ui.separator();
 				}
 
 				// If you clicked the Remove Button
 				if let Some(data_id) = removed_data_index {
 					// Remove all items in view list.
-					for index in self.items.len() - 1..=0 {
-						if self.items[index].data_id == data_id {
-							self.items.remove(index);
-						}
-					}
+					self.items.retain(|v| v.data_id != data_id);
 
 					store.delete(data_id).unwrap();
+
+					self.deleted = Some(data_id);
 				}
 
 
@@ -132,5 +211,13 @@ impl Tab for RecentTab {
 				}
 			});
 		});
+
+		if self.have_pins_changed() {
+			self.fetch_pinned(frame, store);
+		}
+
+		if let Some(data_id) = edited_data_index {
+			self.editor.open(data_id, store);
+		}
 	}
 }
\ No newline at end of file
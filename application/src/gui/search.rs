@@ -3,14 +3,46 @@ use eframe::{egui, epi};
 use log::error;
 
 
-use crate::{Tab, ReturnedItem, StorageContainer, StorageQuery, Config};
+use crate::{Tab, ReturnedItem, StorageContainer, StorageQuery, SearchQuery, SearchTerm, Config};
+
+use super::RowAction;
+use super::editor::Editor;
+
+
+static QUERY_HELP: &str = r#"Every term has to match. Start a term with - to exclude it.
+
+word  "exact phrase"
+type:text  type:image  type:files
+starred:yes  starred:no
+app:firefox  app:"Google Chrome"
+after:2026-01-01  before:2026-02-01
+size:>10kb  size:<=2mb"#;
+
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+enum SearchMode {
+	/// The search query language.
+	#[default]
+	Query,
+	Fuzzy,
+	Regex,
+}
 
 
 #[derive(Default)]
 pub struct SearchTab {
 	search: String,
+	mode: SearchMode,
+	/// Only show copies from this application.
+	source_app: Option<String>,
+	source_apps: Vec<String>,
+	/// Why the query couldn't be parsed.
+	error: Option<String>,
 	items: Vec<ReturnedItem>,
-	fetching_items: bool
+	editor: Editor,
+	fetching_items: bool,
+	/// Moved to the trash since the App last asked.
+	deleted: Option<usize>
 }
 
 impl SearchTab {
@@ -20,15 +52,48 @@ impl SearchTab {
 		}
 
 		self.items.clear();
+		self.error = None;
+
+		let query = match self.mode {
+			SearchMode::Query => {
+				let mut query = match SearchQuery::parse(&self.search) {
+					Ok(v) => v,
+					Err(e) => {
+						self.error = Some(e.to_string());
+						return;
+					}
+				};
 
-		if self.search.is_empty() {
-			return;
-		}
+				if let Some(source_app) = self.source_app.clone() {
+					query.terms.push(SearchTerm::App(source_app));
+				}
+
+				if query.is_empty() {
+					return;
+				}
+
+				StorageQuery::Advanced(query)
+			}
+
+			_ if self.search.trim().is_empty() => return,
+
+			SearchMode::Fuzzy => StorageQuery::Fuzzy {
+				pattern: self.search.clone(),
+				source_app: self.source_app.clone(),
+			},
+
+			SearchMode::Regex => StorageQuery::Regex {
+				pattern: self.search.clone(),
+				source_app: self.source_app.clone(),
+			},
+		};
 
 		self.fetching_items = true;
 
-		match store.query(StorageQuery::Search { value: self.search.clone() }) {
+		match store.query(query) {
 			Ok(new_items) => super::prepend_new_items_into_existing(&mut self.items, new_items, frame),
+			// Most likely an invalid regex.
+			Err(e) if self.mode == SearchMode::Regex => self.error = Some(e.to_string()),
 			Err(e) => error!(target: "clipboard_gui", "{:?}", e),
 		}
 
@@ -37,17 +102,70 @@ impl SearchTab {
 }
 
 impl Tab for SearchTab {
+	fn on_open(&mut self, _frame: &epi::Frame, store: &StorageContainer, _config: &mut Config) {
+		match store.get_source_apps() {
+			Ok(source_apps) => self.source_apps = source_apps,
+			Err(e) => error!(target: "clipboard_gui", "{:?}", e),
+		}
+	}
+
+	fn take_deleted(&mut self) -> Option<usize> {
+		self.deleted.take()
+	}
+
 	fn update(&mut self, ctx: &egui::CtxRef, frame: &epi::Frame, store: &StorageContainer, config: &mut Config) {
+		if self.editor.show(ctx, store, config) {
+			self.fetch(frame, store);
+		}
+
+		let mut edited_data_index: Option<usize> = None;
+
 		egui::CentralPanel::default()
 		.show(ctx, |ui| {
 			ui.label("Type in your query below");
 
-			let text_edit = ui.add(egui::TextEdit::singleline(&mut self.search).desired_width(f32::INFINITY));
+			let previous_mode = self.mode;
+
+			ui.horizontal(|ui| {
+				ui.radio_value(&mut self.mode, SearchMode::Query, "Query");
+				ui.radio_value(&mut self.mode, SearchMode::Fuzzy, "Fuzzy");
+				ui.radio_value(&mut self.mode, SearchMode::Regex, "Regex");
+			});
+
+			if self.mode != previous_mode {
+				self.fetch(frame, store);
+			}
+
+			let mut text_edit = ui.add(egui::TextEdit::singleline(&mut self.search).desired_width(f32::INFINITY));
+
+			if self.mode == SearchMode::Query {
+				text_edit = text_edit.on_hover_text(QUERY_HELP);
+			}
 
 			if text_edit.lost_focus() && ui.input().key_pressed(egui::Key::Enter) {
 				self.fetch(frame, store);
 			}
 
+			if let Some(error) = self.error.as_deref() {
+				ui.colored_label(egui::Color32::RED, error);
+			}
+
+			let previous_source_app = self.source_app.clone();
+
+			egui::ComboBox::from_label("Application")
+				.selected_text(self.source_app.clone().unwrap_or_else(|| String::from("All")))
+				.show_ui(ui, |ui| {
+					ui.selectable_value(&mut self.source_app, None, "All");
+
+					for source_app in &self.source_apps {
+						ui.selectable_value(&mut self.source_app, Some(source_app.clone()), source_app.as_str());
+					}
+				});
+
+			if self.source_app != previous_source_app {
+				self.fetch(frame, store);
+			}
+
 			egui::ScrollArea::vertical()
 			.show_rows(ui, 40.0, self.items.len(), |ui, viewing| {
 				let mut removed_data_index: Option<usize> = None;
@@ -57,7 +175,11 @@ impl Tab for SearchTab {
 				let now = Utc::now();
 
 				for item in &mut self.items[viewing] {
-					super::display_scroll_row(ui, desired_size, item, config, store, &mut removed_data_index, now);
+					match super::display_scroll_row(ui, desired_size, item, config, store, now) {
+						Some(RowAction::Delete) => removed_data_index = Some(item.data_id),
+						Some(RowAction::Edit) => edited_data_index = Some(item.data_id),
+						None => ()
+					}
 
 					ui.separator();
 				}
@@ -65,13 +187,11 @@ impl Tab for SearchTab {
 				// If you clicked the Remove Button
 				if let Some(data_id) = removed_data_index {
 					// Remove all items in view list.
-					for index in self.items.len() - 1..=0 {
-						if self.items[index].data_id == data_id {
-							self.items.remove(index);
-						}
-					}
+					self.items.retain(|v| v.data_id != data_id);
 
 					store.delete(data_id).unwrap();
+
+					self.deleted = Some(data_id);
 				}
 
 				if self.fetching_items {
@@ -81,5 +201,9 @@ impl Tab for SearchTab {
 				}
 			});
 		});
+
+		if let Some(data_id) = edited_data_index {
+			self.editor.open(data_id, store);
+		}
 	}
 }
\ No newline at end of file
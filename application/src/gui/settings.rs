@@ -2,20 +2,33 @@ use anyhow::Result;
 use eframe::{egui, epi};
 
 
-use crate::{Tab, StorageContainer, Config};
+use clipboard_common::config::SensitiveAction;
+
+use crate::{Tab, StorageContainer, Config, RetentionReport};
 
 
 #[derive(Default)]
 pub struct SettingsTab {
-	database_size: Option<Result<u64>> // read File size.
+	database_size: Option<Result<u64>>, // read File size.
+	/// Size of everything stored, from the database.
+	stored_size: Option<Result<usize>>,
+	/// Result of the last "Clean Up Now".
+	retention_report: Option<Result<RetentionReport>>,
+	// One application per line.
+	ignored_apps: String,
+	allowed_apps: String,
 }
 
 impl Tab for SettingsTab {
-	fn on_open(&mut self, _frame: &epi::Frame, _store: &StorageContainer, _config: &mut Config) {
+	fn on_open(&mut self, _frame: &epi::Frame, store: &StorageContainer, config: &mut Config) {
 		self.database_size = Some(std::fs::metadata("userdata.db").map(|v| v.len()).map_err(|v| v.into()));
+		self.stored_size = Some(store.compute_total_size());
+		self.retention_report = None;
+		self.ignored_apps = config.apps.ignore.join("\n");
+		self.allowed_apps = config.apps.allow.join("\n");
 	}
 
-	fn update(&mut self, ctx: &egui::CtxRef, _frame: &epi::Frame, _store: &StorageContainer, config: &mut Config) {
+	fn update(&mut self, ctx: &egui::CtxRef, _frame: &epi::Frame, store: &StorageContainer, config: &mut Config) {
 		egui::CentralPanel::default()
 		.show(ctx, |ui| {
 			ui.heading("Settings");
@@ -49,11 +62,83 @@ impl Tab for SettingsTab {
 				ui.add(egui::Slider::new(&mut config.stores.image.max_size, 1..=10240).text("Max Size (MB)"));
 			});
 
-			// ui.label("Files");
-			// ui.indent(789, |ui| {
-			// 	ui.checkbox(&mut false, "Save files?");
-			// 	ui.add(egui::Slider::new(&mut 5120i64, 1..=10240).text("Max Size"));
-			// });
+			ui.label("Files");
+			ui.indent(789, |ui| {
+				ui.checkbox(&mut config.stores.file.enabled, "Save files?");
+				ui.checkbox(&mut config.stores.file.snapshot_contents, "Save file contents?");
+				ui.add(egui::Slider::new(&mut config.stores.file.max_size, 1..=10240).text("Max Size (MB)"));
+			});
+
+			// PRIMARY only exists on X11/Wayland.
+			#[cfg(not(windows))]
+			{
+				ui.add_space(20.0);
+				ui.heading("Selections");
+
+				ui.checkbox(&mut config.selections.record_primary, "Save the primary selection (middle-click paste)?");
+				ui.checkbox(&mut config.selections.sync_primary, "Sync the primary selection and clipboard?");
+			}
+
+			ui.add_space(20.0);
+			ui.heading("Privacy");
+
+			ui.checkbox(&mut config.privacy.respect_concealed_hints, "Skip passwords marked as concealed by password managers?");
+			ui.add(egui::Slider::new(&mut config.privacy.expire_minutes, 1..=1440).text("Expire After (Minutes)"));
+
+			ui.label("Sensitive Data Rules");
+			ui.indent(1011, |ui| {
+				for (index, rule) in config.privacy.rules.iter_mut().enumerate() {
+					ui.horizontal(|ui| {
+						ui.checkbox(&mut rule.enabled, rule.name.as_str());
+
+						egui::ComboBox::from_id_source(("privacy_rule", index))
+							.selected_text(format!("{:?}", rule.action))
+							.show_ui(ui, |ui| {
+								ui.selectable_value(&mut rule.action, SensitiveAction::Mask, "Mask");
+								ui.selectable_value(&mut rule.action, SensitiveAction::Expire, "Expire");
+								ui.selectable_value(&mut rule.action, SensitiveAction::Drop, "Drop");
+							});
+					});
+				}
+			});
+
+			ui.add_space(20.0);
+			ui.heading("Retention");
+
+			ui.label("Older copies are deleted past these limits. Starred and pinned copies are kept. 0 is no limit.");
+			ui.add(egui::Slider::new(&mut config.retention.max_items, 0..=10000).text("Max Copies"));
+			ui.add(egui::Slider::new(&mut config.retention.max_age_days, 0..=365).text("Max Age (Days)"));
+			ui.add(egui::Slider::new(&mut config.retention.max_total_size, 0..=10240).text("Max Total Size (MB)"));
+			ui.add(egui::Slider::new(&mut config.retention.interval_minutes, 1..=1440).text("Check Every (Minutes)"));
+
+			if ui.button("Clean Up Now").clicked() {
+				self.retention_report = Some(store.apply_retention(&config.retention));
+				self.stored_size = Some(store.compute_total_size());
+			}
+
+			match self.retention_report.as_ref() {
+				Some(Ok(report)) => { ui.label(report.to_string()); }
+				Some(Err(e)) => { ui.colored_label(egui::Color32::RED, e.to_string()); }
+				None => ()
+			}
+
+			ui.add_space(20.0);
+			ui.heading("Trash");
+
+			ui.add(egui::Slider::new(&mut config.trash.purge_after_days, 0..=365).text("Purge After (Days, 0 is never)"));
+
+			ui.add_space(20.0);
+			ui.heading("Applications");
+
+			ui.label("Ignore copies from (one per line)");
+			if ui.add(egui::TextEdit::multiline(&mut self.ignored_apps).desired_rows(3)).changed() {
+				config.apps.ignore = split_lines(&self.ignored_apps);
+			}
+
+			ui.label("Only save copies from (one per line, empty for all)");
+			if ui.add(egui::TextEdit::multiline(&mut self.allowed_apps).desired_rows(3)).changed() {
+				config.apps.allow = split_lines(&self.allowed_apps);
+			}
 
 			// Authentication (Button, popup)
 
@@ -73,6 +158,12 @@ impl Tab for SettingsTab {
 			} else {
 				ui.label("Unknown Database Size");
 			}
+
+			match self.stored_size.as_ref() {
+				Some(&Ok(v)) => { ui.label(format!("Stored Size {:?}", display_size(v as u64))); }
+				Some(Err(v)) => { ui.label(format!("Stored Size Error {}", v)); }
+				None => ()
+			}
 		});
 	}
 }
@@ -96,4 +187,13 @@ fn display_size(mut bytes: u64) -> String {
 	bytes /= 1000;
 
 	format!("{} Gigabytes", bytes)
-}
\ No newline at end of file
+}
+
+
+fn split_lines(value: &str) -> Vec<String> {
+	value.lines()
+		.map(|v| v.trim())
+		.filter(|v| !v.is_empty())
+		.map(|v| v.to_string())
+		.collect()
+}
@@ -3,15 +3,30 @@ use eframe::{egui, epi};
 use log::error;
 
 
-use crate::{Tab, ReturnedItem, StorageContainer, StorageQuery, Config};
+use crate::{Tab, ReturnedItem, StorageContainer, StorageQuery, Config, Tag};
+
+use super::RowAction;
+use super::editor::Editor;
 
 
 #[derive(Default)]
 pub struct StarredTab {
 	items: Vec<ReturnedItem>,
-	fetching_items: bool
+	tags: Vec<Tag>,
+	/// Showing the items with this tag instead of the starred items.
+	viewing_tag: Option<String>,
+	new_tag: String,
+	/// Why the tag couldn't be created.
+	tag_error: Option<String>,
+	editor: Editor,
+	/// Index of the item being dragged to a new position.
+	dragging: Option<usize>,
+	fetching_items: bool,
+	/// Moved to the trash since the App last asked.
+	deleted: Option<usize>
 }
 
+
 impl StarredTab {
 	pub fn fetch(&mut self, frame: &epi::Frame, store: &StorageContainer) {
 		if self.fetching_items {
@@ -19,16 +34,83 @@ impl StarredTab {
 		}
 
 		self.items.clear();
+		self.dragging = None;
 
 		self.fetching_items = true;
 
-		match store.query(StorageQuery::Favorites) {
+		match store.get_tags() {
+			Ok(tags) => self.tags = tags,
+			Err(e) => error!(target: "clipboard_gui", "{:?}", e),
+		}
+
+		// The tag may have been deleted.
+		if let Some(name) = self.viewing_tag.as_deref() {
+			if !self.tags.iter().any(|v| v.name == name) {
+				self.viewing_tag = None;
+			}
+		}
+
+		let query = match self.viewing_tag.clone() {
+			Some(name) => StorageQuery::Tag(name),
+			None => StorageQuery::Favorites
+		};
+
+		match store.query(query) {
 			Ok(new_items) => super::prepend_new_items_into_existing(&mut self.items, new_items, frame),
 			Err(e) => error!(target: "clipboard_gui", "{:?}", e),
 		}
 
 		self.fetching_items = false;
 	}
+
+	fn display_tags(&mut self, ui: &mut egui::Ui, frame: &epi::Frame, store: &StorageContainer) {
+		ui.heading("Tags");
+
+		let previous_tag = self.viewing_tag.clone();
+		let mut deleted_tag = None;
+
+		ui.selectable_value(&mut self.viewing_tag, None, "⭐ Starred");
+
+		for tag in &self.tags {
+			ui.horizontal(|ui| {
+				ui.selectable_value(&mut self.viewing_tag, Some(tag.name.clone()), format!("{} ({})", tag.name, tag.data_count));
+
+				if ui.small_button("❌").on_hover_text("Delete Tag").clicked() {
+					deleted_tag = Some(tag.name.clone());
+				}
+			});
+		}
+
+		if let Some(name) = deleted_tag {
+			if let Err(e) = store.delete_tag(&name) {
+				error!(target: "clipboard_gui", "{:?}", e);
+			}
+
+			self.fetch(frame, store);
+		} else if self.viewing_tag != previous_tag {
+			self.fetch(frame, store);
+		}
+
+		ui.separator();
+
+		let text_edit = ui.add(egui::TextEdit::singleline(&mut self.new_tag).hint_text("New Tag"));
+
+		if ui.button("Add Tag").clicked() || (text_edit.lost_focus() && ui.input().key_pressed(egui::Key::Enter)) {
+			match store.create_tag(&self.new_tag) {
+				Ok(_) => {
+					self.new_tag.clear();
+					self.tag_error = None;
+					self.fetch(frame, store);
+				}
+
+				Err(e) => self.tag_error = Some(e.to_string()),
+			}
+		}
+
+		if let Some(error) = self.tag_error.as_deref() {
+			ui.colored_label(egui::Color32::RED, error);
+		}
+	}
 }
 
 impl Tab for StarredTab {
@@ -36,7 +118,21 @@ impl Tab for StarredTab {
 		self.fetch(frame, store);
 	}
 
-	fn update(&mut self, ctx: &egui::CtxRef, _frame: &epi::Frame, store: &StorageContainer, config: &mut Config) {
+	fn take_deleted(&mut self) -> Option<usize> {
+		self.deleted.take()
+	}
+
+	fn update(&mut self, ctx: &egui::CtxRef, frame: &epi::Frame, store: &StorageContainer, config: &mut Config) {
+		egui::SidePanel::left("tags_panel")
+		.resizable(false)
+		.show(ctx, |ui| self.display_tags(ui, frame, store));
+
+		if self.editor.show(ctx, store, config) {
+			self.fetch(frame, store);
+		}
+
+		let mut edited_data_index: Option<usize> = None;
+
 		egui::CentralPanel::default()
 		.show(ctx, |ui| {
 			egui::ScrollArea::vertical()
@@ -47,21 +143,71 @@ impl Tab for StarredTab {
 
 				let now = Utc::now();
 
+				// Only starred items are kept in order.
+				let can_arrange = self.viewing_tag.is_none();
+
+				let pointer_pos = ui.input().pointer.hover_pos();
+				let released = ui.input().pointer.any_released();
+
+				let mut dropped_index: Option<usize> = None;
+
 				for index in viewing {
-					super::display_scroll_row(ui, desired_size, &mut self.items[index], config, store, &mut removed_data_index, now);
+					let row = ui.horizontal(|ui| {
+						if can_arrange {
+							let handle = ui.add(egui::Label::new("☰").sense(egui::Sense::drag()))
+								.on_hover_text("Drag to reorder")
+								.on_hover_cursor(egui::CursorIcon::Grab);
+
+							if handle.drag_started() {
+								self.dragging = Some(index);
+							}
+						}
+
+						let desired_size = egui::vec2(ui.available_width(), desired_size.y);
+
+						match super::display_scroll_row(ui, desired_size, &mut self.items[index], config, store, now) {
+							Some(RowAction::Delete) => removed_data_index = Some(self.items[index].data_id),
+							Some(RowAction::Edit) => edited_data_index = Some(self.items[index].data_id),
+							None => ()
+						}
+					}).response;
+
+					if let (Some(dragging), Some(pointer_pos)) = (self.dragging, pointer_pos) {
+						if dragging != index && row.rect.contains(pointer_pos) {
+							// Where it'll end up.
+							let y = if dragging < index { row.rect.bottom() } else { row.rect.top() };
+
+							ui.painter().line_segment([egui::pos2(row.rect.left(), y), egui::pos2(row.rect.right(), y)], ui.visuals().selection.stroke);
+
+							if released {
+								dropped_index = Some(index);
+							}
+						}
+					}
+
 					ui.separator();
 				}
 
+				if released {
+					if let (Some(dragging), Some(index)) = (self.dragging.take(), dropped_index) {
+						let item = self.items.remove(dragging);
+
+						if let Err(e) = store.move_favorite(item.data_id, index) {
+							error!(target: "clipboard_gui", "{:?}", e);
+						}
+
+						self.items.insert(index, item);
+					}
+				}
+
 				// If you clicked the Remove Button
 				if let Some(data_id) = removed_data_index {
 					// Remove all items in view list.
-					for index in self.items.len() - 1..=0 {
-						if self.items[index].data_id == data_id {
-							self.items.remove(index);
-						}
-					}
+					self.items.retain(|v| v.data_id != data_id);
 
 					store.delete(data_id).unwrap();
+
+					self.deleted = Some(data_id);
 				}
 
 				if self.fetching_items {
@@ -71,11 +217,17 @@ impl Tab for StarredTab {
 				}
 
 				if self.items.is_empty() {
+					let text = if self.viewing_tag.is_some() { "Right click something to tag it" } else { "⭐ something :)" };
+
 					ui.allocate_ui_with_layout(desired_size, egui::Layout::centered_and_justified(egui::Direction::LeftToRight), |ui| {
-						ui.label(egui::RichText::new("⭐ something :)").color(egui::Rgba::from_rgb(1.0, 1.0, 1.0)).strong().heading());
+						ui.label(egui::RichText::new(text).color(egui::Rgba::from_rgb(1.0, 1.0, 1.0)).strong().heading());
 					});
 				}
 			});
 		});
+
+		if let Some(data_id) = edited_data_index {
+			self.editor.open(data_id, store);
+		}
 	}
 }
\ No newline at end of file
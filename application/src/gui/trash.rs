@@ -0,0 +1,133 @@
+use chrono::Utc;
+use eframe::{egui::{self, TextureId}, epi};
+use log::error;
+
+
+use crate::{Tab, ReturnedItem, ReturnedItemType, StorageContainer, StorageQuery, Config};
+
+
+#[derive(Default)]
+pub struct TrashTab {
+	items: Vec<ReturnedItem>,
+	fetching_items: bool
+}
+
+impl TrashTab {
+	pub fn fetch(&mut self, frame: &epi::Frame, store: &StorageContainer) {
+		if self.fetching_items {
+			return;
+		}
+
+		self.items.clear();
+
+		self.fetching_items = true;
+
+		match store.query(StorageQuery::Trash) {
+			Ok(new_items) => super::prepend_new_items_into_existing(&mut self.items, new_items, frame),
+			Err(e) => error!(target: "clipboard_gui", "{:?}", e),
+		}
+
+		self.fetching_items = false;
+	}
+}
+
+impl Tab for TrashTab {
+	fn on_open(&mut self, frame: &epi::Frame, store: &StorageContainer, _config: &mut Config) {
+		self.fetch(frame, store);
+	}
+
+	fn update(&mut self, ctx: &egui::CtxRef, frame: &epi::Frame, store: &StorageContainer, config: &mut Config) {
+		egui::CentralPanel::default()
+		.show(ctx, |ui| {
+			ui.horizontal(|ui| {
+				if ui.button("Empty Trash").clicked() {
+					if let Err(e) = store.purge_trash(Utc::now().timestamp_millis() as usize) {
+						error!(target: "clipboard_gui", "{:?}", e);
+					}
+
+					self.fetch(frame, store);
+				}
+
+				if config.trash.purge_after_days == 0 {
+					ui.label("Deleted copies are kept until the trash is emptied.");
+				} else {
+					ui.label(format!("Deleted copies are purged after {} days.", config.trash.purge_after_days));
+				}
+			});
+
+			ui.separator();
+
+			egui::ScrollArea::vertical()
+			.show_rows(ui, 40.0, self.items.len(), |ui, viewing| {
+				// (data_id, restore)
+				let mut clicked: Option<(usize, bool)> = None;
+
+				let desired_size = egui::vec2(ui.available_width(), 40.0);
+
+				for item in &self.items[viewing] {
+					display_trash_row(ui, desired_size, item, &mut clicked);
+					ui.separator();
+				}
+
+				if let Some((data_id, restore)) = clicked {
+					self.items.retain(|v| v.data_id != data_id);
+
+					let result = if restore {
+						store.restore(data_id)
+					} else {
+						store.purge(data_id)
+					};
+
+					if let Err(e) = result {
+						error!(target: "clipboard_gui", "{:?}", e);
+					}
+				}
+
+				if self.items.is_empty() && !self.fetching_items {
+					ui.allocate_ui_with_layout(desired_size, egui::Layout::centered_and_justified(egui::Direction::LeftToRight), |ui| {
+						ui.label(egui::RichText::new("Trash is empty").color(egui::Rgba::from_rgb(1.0, 1.0, 1.0)).strong().heading());
+					});
+				}
+			});
+		});
+	}
+}
+
+
+fn display_trash_row(ui: &mut egui::Ui, desired_size: egui::Vec2, item: &ReturnedItem, clicked: &mut Option<(usize, bool)>) {
+	ui.allocate_ui_with_layout(desired_size, egui::Layout::right_to_left(), |ui| {
+		ui.set_height(desired_size.y);
+
+		if ui.button("🗑").on_hover_text("Delete Forever").clicked() {
+			*clicked = Some((item.data_id, false));
+		}
+
+		if ui.button("♻").on_hover_text("Restore").clicked() {
+			*clicked = Some((item.data_id, true));
+		}
+
+		ui.with_layout(egui::Layout::left_to_right(), |ui| {
+			ui.set_clip_rect(ui.available_rect_before_wrap());
+
+			match &item.value {
+				ReturnedItemType::Text(_) if item.is_masked => {
+					ui.label("********");
+				}
+
+				ReturnedItemType::Text(text_data) => {
+					ui.add(egui::Label::new(text_data.replace(['\n', '\t'], " ")).wrap(false));
+				}
+
+				ReturnedItemType::Files(paths) => {
+					ui.add(egui::Label::new(format!("📄 {}", paths.join(", "))).wrap(false));
+				}
+
+				&ReturnedItemType::ThumbTextureId(texture_id) => {
+					ui.image(TextureId::User(texture_id), [32.0, 32.0]);
+				}
+
+				ReturnedItemType::Thumb(_) => {} // Should never be ran.
+			}
+		});
+	});
+}
@@ -0,0 +1,58 @@
+use anyhow::Result;
+use serde::{Serialize, Deserialize};
+
+
+/// Format names used between backends and the capture pipeline.
+///
+/// Backends translate their native formats (Windows clipboard formats, X11 targets) into these MIME types
+/// and convert the data into the matching encoding (UTF-8 text, a HTML fragment, etc.).
+/// Formats which don't have a translation are passed through with their native name.
+pub mod formats {
+	pub const TEXT: &str = "text/plain;charset=utf-8";
+	pub const HTML: &str = "text/html";
+	pub const PNG: &str = "image/png";
+	pub const BMP: &str = "image/bmp";
+	pub const URI_LIST: &str = "text/uri-list";
+}
+
+
+/// X11 and Wayland have a second selection, PRIMARY, which holds the last selected text (middle-click paste).
+/// Windows only has the clipboard.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Selection {
+	Clipboard,
+	Primary,
+}
+
+impl Selection {
+	/// The selection which mirrors this one when syncing.
+	pub fn other(self) -> Self {
+		match self {
+			Self::Clipboard => Self::Primary,
+			Self::Primary => Self::Clipboard,
+		}
+	}
+}
+
+
+/// An OS clipboard.
+pub trait ClipboardBackend {
+	/// Formats which the changed selection can be read as.
+	fn formats(&mut self) -> Result<Vec<String>>;
+
+	/// Read the changed selection as `format`.
+	fn read(&mut self, format: &str) -> Result<Vec<u8>>;
+
+	/// Name of the application which owns the changed selection, if it can be found.
+	fn source_app(&mut self) -> Option<String> {
+		None
+	}
+
+	/// Replace the contents of `selection` with every (format, data) pair.
+	fn write(&mut self, selection: Selection, formats: &[(String, Vec<u8>)]) -> Result<()>;
+
+	/// Subscribe to clipboard changes. Blocks until a selection's contents change and returns which one.
+	///
+	/// `formats` and `read` then operate on that selection.
+	fn wait_for_change(&mut self) -> Result<Selection>;
+}
\ No newline at end of file
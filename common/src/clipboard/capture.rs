@@ -0,0 +1,289 @@
+use std::fs;
+use std::io::Cursor;
+use std::sync::{Arc, RwLock};
+
+use anyhow::Result;
+use log::error;
+
+use crate::config::Config;
+use crate::store::{CopiedFile, CopySource, StorageContainer};
+
+use super::{ClipboardBackend, Selection, formats, parse_uri_list};
+use super::privacy::is_concealed;
+
+
+/// Store each clipboard change until the backend stops.
+///
+/// The config is read again for each change, so whoever owns it can update it in between.
+pub fn listen(backend: &mut dyn ClipboardBackend, conn: StorageContainer, config: Arc<RwLock<Config>>) -> Result<()> {
+	// Last write into the other selection. Its next change is normally our own write.
+	let mut synced: Option<Synced> = None;
+
+	loop {
+		let selection = backend.wait_for_change()?;
+
+		let config = config.read().unwrap();
+
+		match synced.take() {
+			// The write may never have landed, in which case this is a real change.
+			Some(synced) if synced.selection == selection => {
+				if synced.is_current(backend) {
+					continue;
+				}
+			}
+
+			other => synced = other,
+		}
+
+		if config.selections.sync_primary {
+			match sync_selection(backend, selection, &config) {
+				Ok(Some(v)) => synced = Some(v),
+				Ok(None) => (),
+				Err(e) => {
+					synced = None;
+					error!(target: "clipboard_listener", "Selection Sync Error: {:?}", e);
+				}
+			}
+		}
+
+		if selection == Selection::Primary && !config.selections.record_primary {
+			continue;
+		}
+
+		if let Err(e) = capture(backend, selection, &conn, &config) {
+			error!(target: "clipboard_listener", "{:?}", e);
+		}
+	}
+}
+
+
+/// Contents `listen` wrote into a selection to keep it in sync.
+struct Synced {
+	selection: Selection,
+	/// First format written and its data.
+	format: String,
+	data: Vec<u8>,
+}
+
+impl Synced {
+	/// Whether the selection still holds what was written.
+	fn is_current(&self, backend: &mut dyn ClipboardBackend) -> bool {
+		backend.read(&self.format).map(|v| v == self.data).unwrap_or_default()
+	}
+}
+
+/// Copy the changed selection into the other one. Returns None if there wasn't anything to copy.
+fn sync_selection(backend: &mut dyn ClipboardBackend, selection: Selection, config: &Config) -> Result<Option<Synced>> {
+	let available = backend.formats()?;
+
+	// Don't spread passwords into the other selection.
+	if config.privacy.respect_concealed_hints && is_concealed(backend, &available) {
+		return Ok(None);
+	}
+
+	let mut contents = Vec::new();
+
+	for format in [formats::TEXT, formats::HTML, formats::PNG, formats::BMP, formats::URI_LIST] {
+		if available.iter().any(|v| v == format) {
+			contents.push((format.to_string(), backend.read(format)?));
+		}
+	}
+
+	if contents.is_empty() {
+		return Ok(None);
+	}
+
+	backend.write(selection.other(), &contents)?;
+
+	let (format, data) = contents.swap_remove(0);
+
+	Ok(Some(Synced {
+		selection: selection.other(),
+		format,
+		data
+	}))
+}
+
+
+/// Read the changed selection from the backend and store it.
+pub fn capture(backend: &mut dyn ClipboardBackend, selection: Selection, conn: &StorageContainer, config: &Config) -> Result<()> {
+	let available = backend.formats()?;
+	let has_format = |format: &str| available.iter().any(|v| v == format);
+
+	if config.privacy.respect_concealed_hints && is_concealed(backend, &available) {
+		log::info!(target: "clipboard_listener", "Skipping a copy marked as concealed");
+		return Ok(());
+	}
+
+	let source = CopySource {
+		selection,
+		app: backend.source_app()
+	};
+
+	if config.apps.is_ignored(source.app.as_deref()) {
+		log::info!(target: "clipboard_listener", "Skipping a copy from {:?}", source.app);
+		return Ok(());
+	}
+
+	// Data ids the copy was stored as.
+	let mut stored = Vec::new();
+	// Formats which were stored in the data columns.
+	let mut captured = Vec::new();
+
+	// File Clipboard
+	if config.stores.file.enabled && has_format(formats::URI_LIST) {
+		let paths = parse_uri_list(&String::from_utf8_lossy(&backend.read(formats::URI_LIST)?));
+
+		if !paths.is_empty() {
+			match conn.add_files(read_files(paths, config), &source, config) {
+				Ok(data_id) => stored.extend(data_id),
+				Err(e) => error!(target: "clipboard_listener", "[add_files] Clipboard Files Error: {:?}", e)
+			}
+
+			// Any text offered alongside is just the file paths.
+			captured.extend([formats::URI_LIST, formats::TEXT]);
+		}
+	}
+
+	// Text Clipboard
+	if config.stores.text.enabled && !captured.contains(&formats::TEXT) {
+		// File managers may only offer the uri list when copying files.
+		let text_format = [formats::TEXT, formats::URI_LIST].into_iter().find(|v| has_format(v));
+
+		if let Some(text_format) = text_format {
+			let text_data = String::from_utf8_lossy(&backend.read(text_format)?).into_owned();
+
+			if !text_data.is_empty() {
+				let html_data = if has_format(formats::HTML) {
+					match backend.read(formats::HTML) {
+						Ok(data) => Some(String::from_utf8_lossy(&data).into_owned()).filter(|v| !v.is_empty()),
+						Err(e) => {
+							error!(target: "clipboard_listener", "Clipboard Update Error {:?}", e);
+							None
+						}
+					}
+				} else {
+					None
+				};
+
+				if html_data.is_some() {
+					captured.push(formats::HTML);
+				}
+
+				match conn.add_text(text_data, html_data, &source, config) {
+					Ok(data_id) => stored.extend(data_id),
+					Err(e) => error!(target: "clipboard_listener", "[add_text] Clipboard Text Error: {:?}", e)
+				}
+
+				captured.push(formats::TEXT);
+			}
+		}
+	}
+
+	// Image Clipboard
+	if config.stores.image.enabled {
+		let image_format = [formats::PNG, formats::BMP].into_iter().find(|v| has_format(v));
+
+		if let Some(image_format) = image_format {
+			let image_data = backend.read(image_format)?;
+
+			match create_thumbnail(&image_data) {
+				Ok(image_thumb_data) => {
+					match conn.add_image(image_data, image_thumb_data, &source, config) {
+						Ok(data_id) => stored.extend(data_id),
+						Err(e) => error!(target: "clipboard_listener", "[add_img] Clipboard Image Error: {:?}", e)
+					}
+
+					captured.push(image_format);
+				}
+
+				Err(e) => error!(target: "clipboard_listener", "Image Load Error: {:?}", e)
+			}
+		}
+	}
+
+	// Other Formats
+	if config.stores.formats.enabled && !stored.is_empty() {
+		let other_formats = read_other_formats(backend, &available, &captured, config);
+
+		for data_id in stored {
+			if let Err(e) = conn.set_formats(data_id, &other_formats) {
+				error!(target: "clipboard_listener", "[set_formats] Clipboard Formats Error: {:?}", e);
+			}
+		}
+	}
+
+	Ok(())
+}
+
+
+/// Read every available format which wasn't already stored, up to the max size.
+fn read_other_formats(backend: &mut dyn ClipboardBackend, available: &[String], captured: &[&str], config: &Config) -> Vec<(String, Vec<u8>)> {
+	let mut remaining_size = config.stores.formats.max_size * 1000 * 1000; // B -> KB -> MB
+	let mut other_formats = Vec::new();
+
+	for format in available.iter().filter(|v| !captured.contains(&v.as_str())) {
+		match backend.read(format) {
+			Ok(data) if data.len() <= remaining_size => {
+				remaining_size -= data.len();
+				other_formats.push((format.clone(), data));
+			}
+
+			Ok(data) => log::info!(target: "clipboard_listener", "[read_other_formats]: Skipping {} ({}B), over the max size", format, data.len()),
+
+			Err(e) => error!(target: "clipboard_listener", "Clipboard Format {} Error: {:?}", format, e)
+		}
+	}
+
+	other_formats
+}
+
+
+fn read_files(paths: Vec<String>, config: &Config) -> Vec<CopiedFile> {
+	let mut remaining_size = config.stores.file.max_size * 1000 * 1000; // B -> KB -> MB
+
+	paths.into_iter()
+		.map(|path| {
+			let file_size = fs::metadata(&path).ok()
+				.filter(|v| v.is_file())
+				.map(|v| v.len() as usize);
+
+			let file_data = match file_size {
+				Some(size) if config.stores.file.snapshot_contents && size <= remaining_size => {
+					match fs::read(&path) {
+						Ok(data) => {
+							remaining_size = remaining_size.saturating_sub(data.len());
+							Some(data)
+						}
+
+						Err(e) => {
+							error!(target: "clipboard_listener", "File Read Error {:?}: {:?}", path, e);
+							None
+						}
+					}
+				}
+
+				_ => None
+			};
+
+			CopiedFile {
+				id: 0,
+				row_id: 0,
+				path,
+				file_size,
+				file_data
+			}
+		})
+		.collect()
+}
+
+
+fn create_thumbnail(image_data: &[u8]) -> Result<Option<Vec<u8>>> {
+	let img = image::load_from_memory(image_data)?;
+
+	let thumb = img.thumbnail(64, 64);
+	let mut buffer = Cursor::new(Vec::new());
+	let _ = thumb.write_to(&mut buffer, image::ImageFormat::Jpeg);
+
+	Ok(Some(buffer.into_inner()).filter(|v| !v.is_empty()))
+}
\ No newline at end of file
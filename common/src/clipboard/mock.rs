@@ -0,0 +1,84 @@
+use std::collections::VecDeque;
+
+use anyhow::{Result, anyhow};
+
+use super::{ClipboardBackend, Selection};
+
+
+/// (format, data) pairs
+type Contents = Vec<(String, Vec<u8>)>;
+
+
+/// In-memory clipboard for exercising the capture pipeline without an OS clipboard.
+///
+/// Queue changes with [`MockBackend::push_change`]. Each call to `wait_for_change` makes the next queued
+/// change the current contents and fails once the queue is empty, which ends `listen`.
+#[derive(Default)]
+pub struct MockBackend {
+	contents: Contents,
+	changes: VecDeque<(Selection, Contents)>,
+
+	/// Returned by `source_app`.
+	pub source_app: Option<String>,
+
+	/// Every successful `write` call, oldest first.
+	pub written: Vec<(Selection, Contents)>,
+
+	/// Makes `write` fail, like a selection which couldn't be taken.
+	pub fail_writes: bool,
+}
+
+impl MockBackend {
+	pub fn new() -> Self {
+		Self::default()
+	}
+
+	/// Replace the current contents without counting it as a change.
+	pub fn set_contents<F: Into<String>, D: Into<Vec<u8>>>(&mut self, contents: impl IntoIterator<Item = (F, D)>) {
+		self.contents = contents.into_iter().map(|(f, d)| (f.into(), d.into())).collect();
+	}
+
+	/// Queue contents of `selection` to be returned by the next `wait_for_change`.
+	pub fn push_change<F: Into<String>, D: Into<Vec<u8>>>(&mut self, selection: Selection, contents: impl IntoIterator<Item = (F, D)>) {
+		self.changes.push_back((selection, contents.into_iter().map(|(f, d)| (f.into(), d.into())).collect()));
+	}
+
+	pub fn contents(&self) -> &[(String, Vec<u8>)] {
+		&self.contents
+	}
+}
+
+impl ClipboardBackend for MockBackend {
+	fn formats(&mut self) -> Result<Vec<String>> {
+		Ok(self.contents.iter().map(|(format, _)| format.clone()).collect())
+	}
+
+	fn read(&mut self, format: &str) -> Result<Vec<u8>> {
+		self.contents.iter()
+			.find(|(v, _)| v == format)
+			.map(|(_, data)| data.clone())
+			.ok_or_else(|| anyhow!("Format {} isn't on the clipboard", format))
+	}
+
+	fn source_app(&mut self) -> Option<String> {
+		self.source_app.clone()
+	}
+
+	fn write(&mut self, selection: Selection, formats: &[(String, Vec<u8>)]) -> Result<()> {
+		if self.fail_writes {
+			return Err(anyhow!("Writing {:?} failed", selection));
+		}
+
+		self.written.push((selection, formats.to_vec()));
+
+		Ok(())
+	}
+
+	fn wait_for_change(&mut self) -> Result<Selection> {
+		let (selection, contents) = self.changes.pop_front().ok_or_else(|| anyhow!("No more clipboard changes"))?;
+
+		self.contents = contents;
+
+		Ok(selection)
+	}
+}
\ No newline at end of file
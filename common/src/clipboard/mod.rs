@@ -0,0 +1,93 @@
+use std::io::Cursor;
+use std::sync::{Arc, RwLock};
+
+
+use anyhow::Result;
+#[cfg(windows)]
+pub use windows::*;
+#[cfg(not(windows))]
+pub use nonwindows::*;
+
+pub use backend::{ClipboardBackend, Selection, formats};
+pub use capture::{capture, listen};
+pub use mock::MockBackend;
+pub use uri_list::{create_uri_list, parse_uri_list};
+
+use crate::{store::{StorageContainer, CopiedData}, config::Config};
+
+
+mod backend;
+mod capture;
+mod mock;
+mod privacy;
+mod uri_list;
+
+#[cfg(windows)]
+mod windows;
+#[cfg(not(windows))]
+mod nonwindows;
+
+
+pub trait Listener: Default {
+	fn run(&mut self, conn: StorageContainer, config: Arc<RwLock<Config>>) -> Result<()>;
+}
+
+
+/// Put every stored representation of a clip back onto the clipboard.
+///
+/// On X11 and Wayland the contents are served from a background thread so they're only pasteable while this process is alive.
+pub fn set_clipboard(data_id: usize, store: &StorageContainer) -> Result<()> {
+	restore(&mut *system_backend()?, data_id, store)
+}
+
+/// Put every stored representation of a clip back onto the backend's clipboard.
+pub fn restore(backend: &mut dyn ClipboardBackend, data_id: usize, store: &StorageContainer) -> Result<()> {
+	let data = store.get_data(data_id)?;
+
+	let mut formats = clipboard_formats(&data)?;
+
+	// Everything else the original copy offered.
+	if !data.formats.is_empty() {
+		for format in store.get_formats(data_id)? {
+			if !formats.iter().any(|(v, _)| *v == format.format) {
+				formats.push((format.format, format.format_data));
+			}
+		}
+	}
+
+	backend.write(Selection::Clipboard, &formats)
+}
+
+/// (format, data) pairs for each representation stored in the data columns.
+pub fn clipboard_formats(data: &CopiedData) -> Result<Vec<(String, Vec<u8>)>> {
+	let mut values = Vec::new();
+
+	if let Some(text_data) = data.text_data.as_ref() {
+		values.push((formats::TEXT.to_string(), text_data.clone().into_bytes()));
+
+		// Files
+		if data.type_of == 2 {
+			let paths = text_data.lines().map(|v| v.to_string()).collect::<Vec<_>>();
+
+			values.push((formats::URI_LIST.to_string(), create_uri_list(&paths).into_bytes()));
+		}
+	}
+
+	if let Some(html_data) = data.html_data.as_ref() {
+		values.push((formats::HTML.to_string(), html_data.clone().into_bytes()));
+	}
+
+	if let Some(image_data) = data.image_data.as_ref() {
+		// Windows stores bitmaps. Restore everything as PNG and let the backend convert it if needed.
+		if image::guess_format(image_data)? == image::ImageFormat::Png {
+			values.push((formats::PNG.to_string(), image_data.clone()));
+		} else {
+			let mut buffer = Cursor::new(Vec::new());
+			image::load_from_memory(image_data)?.write_to(&mut buffer, image::ImageFormat::Png)?;
+
+			values.push((formats::PNG.to_string(), buffer.into_inner()));
+		}
+	}
+
+	Ok(values)
+}
\ No newline at end of file
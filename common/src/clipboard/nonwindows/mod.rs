@@ -0,0 +1,36 @@
+use std::sync::{RwLock, Arc};
+
+use anyhow::Result;
+use log::info;
+
+use crate::{StorageContainer, Config, ClipboardBackend};
+
+mod wayland;
+mod x11;
+
+pub use wayland::WaylandBackend;
+pub use x11::X11Backend;
+
+
+/// Clipboard of the current session.
+pub fn system_backend() -> Result<Box<dyn ClipboardBackend>> {
+	// Wayland sessions usually also run XWayland, but it only mirrors the clipboard to X11 clients with focus. Prefer Wayland.
+	if std::env::var_os("WAYLAND_DISPLAY").is_some() {
+		match WaylandBackend::connect() {
+			Ok(backend) => return Ok(Box::new(backend)),
+			Err(e) => info!(target: "clipboard_listener", "Unable to use Wayland data control, falling back to X11: {:?}", e),
+		}
+	}
+
+	Ok(Box::new(X11Backend::connect()?))
+}
+
+
+#[derive(Default)]
+pub struct AppListener;
+
+impl super::Listener for AppListener {
+	fn run(&mut self, conn: StorageContainer, config: Arc<RwLock<Config>>) -> Result<()> {
+		super::listen(&mut *system_backend()?, conn, config)
+	}
+}
\ No newline at end of file
@@ -0,0 +1,615 @@
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{Read, Write};
+use std::os::fd::{AsFd, OwnedFd};
+use std::sync::Arc;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use anyhow::{Result, anyhow};
+use log::error;
+use rustix::event::{PollFd, PollFlags, Timespec, poll};
+use rustix::io::Errno;
+use wayland_client::backend::ObjectId;
+use wayland_client::protocol::{wl_registry, wl_seat::WlSeat};
+use wayland_client::{Connection, Dispatch, EventQueue, Proxy, QueueHandle, delegate_noop, event_created_child};
+use wayland_protocols::ext::data_control::v1::client::{
+	ext_data_control_device_v1::{self, ExtDataControlDeviceV1},
+	ext_data_control_manager_v1::ExtDataControlManagerV1,
+	ext_data_control_offer_v1::{self, ExtDataControlOfferV1},
+	ext_data_control_source_v1::{self, ExtDataControlSourceV1},
+};
+use wayland_protocols_wlr::data_control::v1::client::{
+	zwlr_data_control_device_v1::{self, ZwlrDataControlDeviceV1},
+	zwlr_data_control_manager_v1::ZwlrDataControlManagerV1,
+	zwlr_data_control_offer_v1::{self, ZwlrDataControlOfferV1},
+	zwlr_data_control_source_v1::{self, ZwlrDataControlSourceV1},
+};
+
+use crate::clipboard::{ClipboardBackend, Selection, formats};
+
+
+/// How long we'll wait on the source client to write an offer into our pipe.
+const RECEIVE_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// Mime types which hold text, most preferred first.
+static MIME_TEXT: [&str; 4] = [formats::TEXT, "UTF8_STRING", "text/plain", "STRING"];
+
+
+// Listening for Wayland Clipboard Changes
+
+// Regular clients only see the selection while one of their surfaces has keyboard focus.
+// The data-control protocols (wlr-data-control, and its standardized successor ext-data-control) give a
+//      privileged client a data device which isn't tied to focus, so a clipboard manager can watch the selection.
+
+// Each time the selection changes the compositor introduces a new offer, sends its mime types and then
+//      sends the selection event. Each mime type we store is received through a pipe.
+// PRIMARY changes are sent the same way with a primary_selection event (wlr-data-control version 2 and up).
+
+// Setting the selection works the other way around. We create a data source, offer the mime types we have
+//      and write into the pipe we're sent whenever another client wants to paste.
+
+pub struct WaylandBackend {
+	conn: Connection,
+	queue: EventQueue<State>,
+	state: State,
+
+	/// Selection we're reading, with its mime types.
+	current: Option<(Offer, Vec<String>)>,
+}
+
+impl WaylandBackend {
+	pub fn connect() -> Result<Self> {
+		let conn = Connection::connect_to_env()?;
+
+		let (mut queue, mut state) = State::connect(&conn)?;
+
+		// Receive the current selection.
+		queue.roundtrip(&mut state)?;
+
+		// Don't store whatever was copied before we started.
+		for (_, offer, _) in state.selections.drain(..) {
+			offer.destroy();
+		}
+
+		Ok(Self {
+			conn,
+			queue,
+			state,
+			current: None
+		})
+	}
+
+	/// Best mime type to read text as. Some clients (XWayland) only offer the X11 names.
+	fn text_mime_type(&self) -> Option<&str> {
+		let (_, mime_types) = self.current.as_ref()?;
+
+		MIME_TEXT.into_iter().find(|v| mime_types.iter().any(|m| m == v))
+	}
+}
+
+impl ClipboardBackend for WaylandBackend {
+	fn formats(&mut self) -> Result<Vec<String>> {
+		Ok(available_formats(self.current.iter().flat_map(|(_, v)| v)))
+	}
+
+	fn read(&mut self, format: &str) -> Result<Vec<u8>> {
+		let mime_type = if format == formats::TEXT {
+			self.text_mime_type().ok_or_else(|| anyhow!("Source client doesn't offer text"))?
+		} else {
+			format
+		};
+
+		let (offer, _) = self.current.as_ref().ok_or_else(|| anyhow!("Nothing on the clipboard"))?;
+
+		self.receive(offer, mime_type)
+	}
+
+	fn write(&mut self, selection: Selection, formats: &[(String, Vec<u8>)]) -> Result<()> {
+		let source_data = source_data(formats);
+
+		// The selection only exists for as long as someone is around to send it.
+		thread::spawn(move || {
+			if let Err(e) = serve_selection(selection, source_data) {
+				error!(target: "clipboard_listener", "Wayland Selection Error: {:?}", e);
+			}
+		});
+
+		Ok(())
+	}
+
+	fn wait_for_change(&mut self) -> Result<Selection> {
+		if let Some((offer, _)) = self.current.take() {
+			offer.destroy();
+		}
+
+		loop {
+			if !self.state.selections.is_empty() {
+				let (selection, offer, mime_types) = self.state.selections.remove(0);
+
+				self.current = Some((offer, mime_types));
+
+				return Ok(selection);
+			}
+
+			self.queue.blocking_dispatch(&mut self.state)?;
+
+			if self.state.finished {
+				return Err(anyhow!("Wayland data control device was destroyed"));
+			}
+		}
+	}
+}
+
+impl WaylandBackend {
+	/// Ask the source client to write the offer as `mime_type` into a pipe and read it out.
+	fn receive(&self, offer: &Offer, mime_type: &str) -> Result<Vec<u8>> {
+		let (reader, writer) = std::io::pipe()?;
+
+		offer.receive(mime_type, &writer);
+
+		// Our end of the writer has to be closed otherwise we'd never see EOF.
+		drop(writer);
+
+		self.conn.flush()?;
+
+		// A source client which never closes the pipe can't hang the listener.
+		read_with_timeout(reader, RECEIVE_TIMEOUT)
+			.map_err(|e| anyhow!("Receiving {} from the source client: {}", mime_type, e))
+	}
+}
+
+
+/// Formats the mime types are stored as. The text mime types are all stored as text.
+fn available_formats<'a>(mime_types: impl Iterator<Item = &'a String>) -> Vec<String> {
+	let mut available = Vec::new();
+
+	for mime_type in mime_types {
+		let name = if MIME_TEXT.contains(&mime_type.as_str()) {
+			formats::TEXT.to_string()
+		} else {
+			mime_type.clone()
+		};
+
+		if !available.contains(&name) {
+			available.push(name);
+		}
+	}
+
+	available
+}
+
+/// Mime type -> Data. Text is also offered under the names other clients may look for.
+fn source_data(formats: &[(String, Vec<u8>)]) -> HashMap<String, Arc<[u8]>> {
+	let mut source_data = HashMap::new();
+
+	for (format, data) in formats {
+		let data: Arc<[u8]> = data.as_slice().into();
+
+		if format == formats::TEXT {
+			for mime_type in MIME_TEXT {
+				source_data.insert(mime_type.to_string(), data.clone());
+			}
+		} else {
+			source_data.insert(format.clone(), data);
+		}
+	}
+
+	source_data
+}
+
+/// Read until the writer closes the pipe, giving up once `timeout` has passed.
+///
+/// The reader is dropped when this returns, closing our end. A source client still writing then gets an error
+///      instead of blocking forever, and nothing is left waiting on the pipe.
+fn read_with_timeout(mut reader: impl Read + AsFd, timeout: Duration) -> Result<Vec<u8>> {
+	let deadline = Instant::now() + timeout;
+
+	let mut data = Vec::new();
+	let mut buffer = [0; 64 * 1024];
+
+	loop {
+		let remaining = Timespec::try_from(deadline.saturating_duration_since(Instant::now()))?;
+
+		match poll(&mut [PollFd::new(&reader, PollFlags::IN)], Some(&remaining)) {
+			Ok(0) => return Err(anyhow!("Timed out after {:?}", timeout)),
+			Ok(_) | Err(Errno::INTR) => (),
+			Err(e) => return Err(e.into()),
+		}
+
+		match reader.read(&mut buffer) {
+			Ok(0) => return Ok(data),
+			Ok(read) => data.extend_from_slice(&buffer[..read]),
+			Err(e) if e.kind() == std::io::ErrorKind::Interrupted => (),
+			Err(e) => return Err(e.into()),
+		}
+	}
+}
+
+impl Drop for WaylandBackend {
+	fn drop(&mut self) {
+		if let Some((offer, _)) = self.current.take() {
+			offer.destroy();
+		}
+
+		if let Some(device) = self.state.device.take() {
+			device.destroy();
+		}
+
+		if let Some(manager) = self.state.manager.take() {
+			manager.destroy();
+		}
+
+		let _ = self.conn.flush();
+	}
+}
+
+
+
+enum Manager {
+	Ext(ExtDataControlManagerV1),
+	Wlr(ZwlrDataControlManagerV1),
+}
+
+impl Manager {
+	fn destroy(&self) {
+		match self {
+			Self::Ext(manager) => manager.destroy(),
+			Self::Wlr(manager) => manager.destroy(),
+		}
+	}
+
+	fn create_data_source(&self, qh: &QueueHandle<State>) -> Source {
+		match self {
+			Self::Ext(manager) => Source::Ext(manager.create_data_source(qh, ())),
+			Self::Wlr(manager) => Source::Wlr(manager.create_data_source(qh, ())),
+		}
+	}
+}
+
+enum Device {
+	Ext(ExtDataControlDeviceV1),
+	Wlr(ZwlrDataControlDeviceV1),
+}
+
+impl Device {
+	fn set_selection(&self, selection: Selection, source: &Source) -> Result<()> {
+		match (self, source, selection) {
+			(Self::Ext(device), Source::Ext(source), Selection::Clipboard) => device.set_selection(Some(source)),
+			(Self::Ext(device), Source::Ext(source), Selection::Primary) => device.set_primary_selection(Some(source)),
+			(Self::Wlr(device), Source::Wlr(source), Selection::Clipboard) => device.set_selection(Some(source)),
+			(Self::Wlr(device), Source::Wlr(source), Selection::Primary) => {
+				if device.version() < 2 {
+					return Err(anyhow!("Wayland compositor's wlr-data-control doesn't support the primary selection"));
+				}
+
+				device.set_primary_selection(Some(source));
+			}
+			_ => unreachable!("Data source and device come from the same manager"),
+		}
+
+		Ok(())
+	}
+
+	fn destroy(&self) {
+		match self {
+			Self::Ext(device) => device.destroy(),
+			Self::Wlr(device) => device.destroy(),
+		}
+	}
+}
+
+enum Offer {
+	Ext(ExtDataControlOfferV1),
+	Wlr(ZwlrDataControlOfferV1),
+}
+
+impl Offer {
+	fn id(&self) -> ObjectId {
+		match self {
+			Self::Ext(offer) => offer.id(),
+			Self::Wlr(offer) => offer.id(),
+		}
+	}
+
+	fn receive(&self, mime_type: &str, fd: &impl AsFd) {
+		match self {
+			Self::Ext(offer) => offer.receive(mime_type.to_string(), fd.as_fd()),
+			Self::Wlr(offer) => offer.receive(mime_type.to_string(), fd.as_fd()),
+		}
+	}
+
+	fn destroy(&self) {
+		match self {
+			Self::Ext(offer) => offer.destroy(),
+			Self::Wlr(offer) => offer.destroy(),
+		}
+	}
+}
+
+enum Source {
+	Ext(ExtDataControlSourceV1),
+	Wlr(ZwlrDataControlSourceV1),
+}
+
+impl Source {
+	fn offer(&self, mime_type: &str) {
+		match self {
+			Self::Ext(source) => source.offer(mime_type.to_string()),
+			Self::Wlr(source) => source.offer(mime_type.to_string()),
+		}
+	}
+
+	fn destroy(&self) {
+		match self {
+			Self::Ext(source) => source.destroy(),
+			Self::Wlr(source) => source.destroy(),
+		}
+	}
+}
+
+#[derive(Default)]
+struct State {
+	seat: Option<WlSeat>,
+	manager: Option<Manager>,
+	device: Option<Device>,
+
+	/// Mime types of offers which haven't been turned into a selection yet.
+	offers: HashMap<ObjectId, Vec<String>>,
+	/// Selections which haven't been stored yet. Only the newest per selection.
+	selections: Vec<(Selection, Offer, Vec<String>)>,
+	/// Compositor destroyed our data device.
+	finished: bool,
+
+	/// Data of the selection we're serving, by mime type.
+	source_data: HashMap<String, Arc<[u8]>>,
+	/// Another client replaced the selection we're serving.
+	cancelled: bool,
+}
+
+impl State {
+	/// Bind the globals we need and create a data device for the seat.
+	fn connect(conn: &Connection) -> Result<(EventQueue<Self>, Self)> {
+		let mut queue = conn.new_event_queue();
+		let qh = queue.handle();
+
+		conn.display().get_registry(&qh, ());
+
+		let mut state = State::default();
+
+		// Receive the globals.
+		queue.roundtrip(&mut state)?;
+
+		let seat = state.seat.as_ref().ok_or_else(|| anyhow!("Wayland compositor didn't advertise a seat"))?;
+
+		state.device = Some(match state.manager.as_ref() {
+			Some(Manager::Ext(manager)) => Device::Ext(manager.get_data_device(seat, &qh, ())),
+			Some(Manager::Wlr(manager)) => Device::Wlr(manager.get_data_device(seat, &qh, ())),
+			None => return Err(anyhow!("Wayland compositor doesn't support ext-data-control or wlr-data-control")),
+		});
+
+		Ok((queue, state))
+	}
+
+	fn send(&self, mime_type: String, fd: OwnedFd) {
+		if let Some(data) = self.source_data.get(&mime_type).cloned() {
+			// Write on another thread so a slow reader can't hold up the event queue.
+			thread::spawn(move || {
+				if let Err(e) = File::from(fd).write_all(&data) {
+					error!(target: "clipboard_listener", "Wayland Send Error: {:?}", e);
+				}
+			});
+		}
+	}
+
+	fn new_offer(&mut self, id: ObjectId) {
+		self.offers.insert(id, Vec::new());
+	}
+
+	fn offer_mime_type(&mut self, id: ObjectId, mime_type: String) {
+		if let Some(mime_types) = self.offers.get_mut(&id) {
+			mime_types.push(mime_type);
+		}
+	}
+
+	fn set_selection(&mut self, selection: Selection, offer: Option<Offer>) {
+		// None when the selection was cleared (or the source client exited).
+		if let Some(offer) = offer {
+			let mime_types = self.offers.remove(&offer.id()).unwrap_or_default();
+
+			// Only the newest selection is stored.
+			if let Some(index) = self.selections.iter().position(|(v, _, _)| *v == selection) {
+				let (_, previous, _) = self.selections.remove(index);
+				previous.destroy();
+			}
+
+			self.selections.push((selection, offer, mime_types));
+		}
+	}
+}
+
+
+impl Dispatch<wl_registry::WlRegistry, ()> for State {
+	fn event(state: &mut Self, registry: &wl_registry::WlRegistry, event: wl_registry::Event, _: &(), _: &Connection, qh: &QueueHandle<Self>) {
+		if let wl_registry::Event::Global { name, interface, version } = event {
+			match interface.as_str() {
+				"wl_seat" if state.seat.is_none() => {
+					state.seat = Some(registry.bind(name, 1, qh, ()));
+				}
+
+				// Prefer the standardized protocol over the wlroots one.
+				"ext_data_control_manager_v1" if !matches!(state.manager, Some(Manager::Ext(_))) => {
+					// The wlroots one may have been advertised first.
+					if let Some(manager) = state.manager.take() {
+						manager.destroy();
+					}
+
+					state.manager = Some(Manager::Ext(registry.bind(name, 1, qh, ())));
+				}
+
+				"zwlr_data_control_manager_v1" if state.manager.is_none() => {
+					state.manager = Some(Manager::Wlr(registry.bind(name, version.min(2), qh, ())));
+				}
+
+				_ => {}
+			}
+		}
+	}
+}
+
+delegate_noop!(State: ignore WlSeat);
+delegate_noop!(State: ExtDataControlManagerV1);
+delegate_noop!(State: ZwlrDataControlManagerV1);
+
+
+macro_rules! impl_data_control_dispatch {
+	($variant:ident, $device_mod:ident::$device:ident, $offer_mod:ident::$offer:ident, $source_mod:ident::$source:ident) => {
+		impl Dispatch<$device, ()> for State {
+			fn event(state: &mut Self, _: &$device, event: $device_mod::Event, _: &(), _: &Connection, _: &QueueHandle<Self>) {
+				match event {
+					$device_mod::Event::DataOffer { id } => state.new_offer(id.id()),
+					$device_mod::Event::Selection { id } => state.set_selection(Selection::Clipboard, id.map(Offer::$variant)),
+					$device_mod::Event::PrimarySelection { id } => state.set_selection(Selection::Primary, id.map(Offer::$variant)),
+					$device_mod::Event::Finished => state.finished = true,
+					_ => {}
+				}
+			}
+
+			event_created_child!(State, $device, [
+				$device_mod::EVT_DATA_OFFER_OPCODE => ($offer, ()),
+			]);
+		}
+
+		impl Dispatch<$offer, ()> for State {
+			fn event(state: &mut Self, offer: &$offer, event: $offer_mod::Event, _: &(), _: &Connection, _: &QueueHandle<Self>) {
+				if let $offer_mod::Event::Offer { mime_type } = event {
+					state.offer_mime_type(offer.id(), mime_type);
+				}
+			}
+		}
+
+		impl Dispatch<$source, ()> for State {
+			fn event(state: &mut Self, _: &$source, event: $source_mod::Event, _: &(), _: &Connection, _: &QueueHandle<Self>) {
+				match event {
+					$source_mod::Event::Send { mime_type, fd } => state.send(mime_type, fd),
+					$source_mod::Event::Cancelled => state.cancelled = true,
+					_ => {}
+				}
+			}
+		}
+	};
+}
+
+impl_data_control_dispatch!(
+	Ext,
+	ext_data_control_device_v1::ExtDataControlDeviceV1,
+	ext_data_control_offer_v1::ExtDataControlOfferV1,
+	ext_data_control_source_v1::ExtDataControlSourceV1
+);
+
+impl_data_control_dispatch!(
+	Wlr,
+	zwlr_data_control_device_v1::ZwlrDataControlDeviceV1,
+	zwlr_data_control_offer_v1::ZwlrDataControlOfferV1,
+	zwlr_data_control_source_v1::ZwlrDataControlSourceV1
+);
+
+
+/// Set the selection to `source_data` and send it to anyone who asks until another client replaces it.
+fn serve_selection(selection: Selection, source_data: HashMap<String, Arc<[u8]>>) -> Result<()> {
+	let conn = Connection::connect_to_env()?;
+
+	let (mut queue, mut state) = State::connect(&conn)?;
+
+	let source = state.manager.as_ref().unwrap().create_data_source(&queue.handle());
+
+	for mime_type in source_data.keys() {
+		source.offer(mime_type);
+	}
+
+	state.source_data = source_data;
+
+	let result = match state.device.as_ref() {
+		Some(device) => device.set_selection(selection, &source),
+		None => Ok(()),
+	};
+
+	while result.is_ok() && !state.cancelled && !state.finished {
+		queue.blocking_dispatch(&mut state)?;
+	}
+
+	source.destroy();
+
+	if let Some(device) = state.device.take() {
+		device.destroy();
+	}
+
+	if let Some(manager) = state.manager.take() {
+		manager.destroy();
+	}
+
+	conn.flush()?;
+
+	result
+}
+
+#[cfg(test)]
+mod tests {
+	use std::io::ErrorKind;
+
+	use super::*;
+
+	fn strings(values: &[&str]) -> Vec<String> {
+		values.iter().map(|v| v.to_string()).collect()
+	}
+
+
+	#[test]
+	fn text_mime_types_are_one_format() {
+		let mime_types = strings(&["UTF8_STRING", "text/html", formats::TEXT, "STRING", "text/html"]);
+
+		assert_eq!(available_formats(mime_types.iter()), strings(&[formats::TEXT, "text/html"]));
+	}
+
+	#[test]
+	fn text_is_offered_under_every_text_mime_type() {
+		let source_data = source_data(&[
+			(formats::TEXT.to_string(), b"text".to_vec()),
+			(String::from("text/html"), b"<b>text</b>".to_vec()),
+		]);
+
+		assert_eq!(source_data.len(), MIME_TEXT.len() + 1);
+
+		for mime_type in MIME_TEXT {
+			assert_eq!(&*source_data[mime_type], b"text");
+		}
+
+		assert_eq!(&*source_data["text/html"], b"<b>text</b>");
+	}
+
+	#[test]
+	fn reads_until_the_writer_closes() {
+		let (reader, mut writer) = std::io::pipe().unwrap();
+
+		// More than fits in the pipe at once.
+		let written = (0..200_000).map(|v| v as u8).collect::<Vec<_>>();
+		let sent = written.clone();
+
+		let writing = thread::spawn(move || writer.write_all(&sent));
+
+		assert_eq!(read_with_timeout(reader, Duration::from_secs(5)).unwrap(), written);
+		writing.join().unwrap().unwrap();
+	}
+
+	#[test]
+	fn timeout_closes_the_pipe() {
+		let (reader, mut writer) = std::io::pipe().unwrap();
+
+		writer.write_all(b"never finished").unwrap();
+
+		assert!(read_with_timeout(reader, Duration::from_millis(50)).is_err());
+
+		// The writer isn't left blocked on a pipe nobody reads.
+		assert_eq!(writer.write_all(b"more").unwrap_err().kind(), ErrorKind::BrokenPipe);
+	}
+}
@@ -0,0 +1,496 @@
+use std::cell::RefCell;
+use std::fs;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use anyhow::{Result, anyhow};
+use log::error;
+use x11rb::connection::{Connection, RequestConnection};
+use x11rb::protocol::Event;
+use x11rb::protocol::xfixes::{ConnectionExt as _, SelectionEventMask};
+use x11rb::protocol::xproto::{self, Atom, AtomEnum, ChangeWindowAttributesAux, ConnectionExt as _, CreateWindowAux, EventMask, PropMode, Property, SelectionNotifyEvent, Timestamp, Window, WindowClass};
+use x11rb::rust_connection::RustConnection;
+use x11rb::wrapper::ConnectionExt as _;
+
+use crate::clipboard::{ClipboardBackend, Selection, formats};
+
+
+/// How long we'll wait on the selection owner before giving up on a conversion.
+const CONVERT_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// Data we serve which is larger than this is sent with INCR, in chunks of this size.
+const INCR_CHUNK_SIZE: usize = 256 * 1024;
+
+/// Targets which describe the selection instead of holding its contents.
+static META_TARGETS: [&str; 6] = ["TARGETS", "TIMESTAMP", "MULTIPLE", "SAVE_TARGETS", "DELETE", "INCR"];
+
+x11rb::atom_manager! {
+	pub Atoms: AtomsCookie {
+		CLIPBOARD,
+		TARGETS,
+		INCR,
+		UTF8_STRING,
+		TEXT_PLAIN_UTF8: b"text/plain;charset=utf-8",
+		TEXT_HTML: b"text/html",
+		_NET_WM_PID,
+		// Property on our window which the selection owner writes converted data into.
+		CLIPBOARD_LISTENER: b"CLIPBOARD_LISTENER",
+	}
+}
+
+
+// Listening for X11 Clipboard Changes
+
+// Unlike Windows there's no clipboard "update" message. The CLIPBOARD selection is owned by a client and
+//      the contents only exist inside of that client until someone asks for them.
+
+// The XFixes extension lets us register with [SelectSelectionInput] to receive a [XFixesSelectionNotify]
+//      event whenever the owner of the selection changes. We then ask the new owner which TARGETS it supports
+//      and convert the ones we store into a property on our own (hidden) window.
+
+// PRIMARY works the same way. Its owner is whichever client last had text selected.
+
+// Data too large for a single request is sent with INCR. The owner sets the property to the INCR type and then
+//      writes each chunk after the requestor deletes the previous one. A zero length chunk ends the transfer.
+
+pub struct X11Backend {
+	conn: RustConnection,
+	window: Window,
+	atoms: Atoms,
+
+	/// Selection whose owner changed.
+	selection: Atom,
+	/// Window of the new selection owner.
+	owner: Window,
+	/// Time of the selection owner change we're reading.
+	time: Timestamp,
+	/// Targets offered by the current selection owner.
+	targets: Vec<Atom>,
+	/// Owner changes (selection, owner, time) which happened while we were reading the previous one. Only the newest per selection.
+	missed_updates: RefCell<Vec<(Atom, Window, Timestamp)>>,
+}
+
+impl X11Backend {
+	pub fn connect() -> Result<Self> {
+		let (conn, screen_num) = x11rb::connect(None)?;
+
+		conn.xfixes_query_version(5, 0)?.reply()?;
+
+		let atoms = Atoms::new(&conn)?.reply()?;
+
+		let window = conn.generate_id()?;
+		let root = conn.setup().roots[screen_num].root;
+
+		conn.create_window(
+			x11rb::COPY_DEPTH_FROM_PARENT,
+			window,
+			root,
+			0, 0, 1, 1, 0,
+			WindowClass::INPUT_ONLY,
+			x11rb::COPY_FROM_PARENT,
+			&CreateWindowAux::new().event_mask(EventMask::PROPERTY_CHANGE)
+		)?;
+
+		conn.xfixes_select_selection_input(window, atoms.CLIPBOARD, SelectionEventMask::SET_SELECTION_OWNER)?;
+		conn.xfixes_select_selection_input(window, AtomEnum::PRIMARY.into(), SelectionEventMask::SET_SELECTION_OWNER)?;
+		conn.flush()?;
+
+		Ok(Self {
+			conn,
+			window,
+			selection: atoms.CLIPBOARD,
+			owner: x11rb::NONE,
+			atoms,
+			time: x11rb::CURRENT_TIME,
+			targets: Vec::new(),
+			missed_updates: RefCell::new(Vec::new())
+		})
+	}
+
+	/// Best target to read text as, and whether it's Latin-1 (STRING) instead of UTF-8.
+	fn text_target(&self) -> Option<(Atom, bool)> {
+		[self.atoms.UTF8_STRING, self.atoms.TEXT_PLAIN_UTF8]
+			.into_iter()
+			.find(|v| self.targets.contains(v))
+			.map(|v| (v, false))
+			.or_else(|| Some((AtomEnum::STRING.into(), true)).filter(|(v, _)| self.targets.contains(v)))
+	}
+}
+
+impl ClipboardBackend for X11Backend {
+	fn formats(&mut self) -> Result<Vec<String>> {
+		self.targets = self.read_targets()?;
+
+		let cookies = self.targets.iter()
+			.map(|v| self.conn.get_atom_name(*v))
+			.collect::<std::result::Result<Vec<_>, _>>()?;
+
+		let mut available = Vec::new();
+
+		for cookie in cookies {
+			let name = String::from_utf8_lossy(&cookie.reply()?.name).into_owned();
+
+			let name = match name.as_str() {
+				v if META_TARGETS.contains(&v) => continue,
+				"UTF8_STRING" | "STRING" | formats::TEXT => formats::TEXT.to_string(),
+				_ => name
+			};
+
+			if !available.contains(&name) {
+				available.push(name);
+			}
+		}
+
+		Ok(available)
+	}
+
+	fn read(&mut self, format: &str) -> Result<Vec<u8>> {
+		match format {
+			formats::TEXT => match self.text_target() {
+				// STRING is Latin-1
+				Some((target, true)) => Ok(self.read_target(target)?.into_iter().map(char::from).collect::<String>().into_bytes()),
+				Some((target, false)) => Ok(self.read_target(target)?),
+				None => Err(anyhow!("Selection owner doesn't offer text")),
+			},
+
+			formats::HTML => Ok(decode_html_target(self.read_target(self.atoms.TEXT_HTML)?).into_bytes()),
+
+			_ => {
+				let target = self.conn.intern_atom(false, format.as_bytes())?.reply()?.atom;
+
+				self.read_target(target)
+			}
+		}
+	}
+
+	fn source_app(&mut self) -> Option<String> {
+		// Toolkits own the selection with a hidden window. Most of them still set the pid on it.
+		let pid = self.conn.get_property(false, self.owner, self.atoms._NET_WM_PID, AtomEnum::CARDINAL, 0, 1).ok()?
+			.reply().ok()
+			.and_then(|v| v.value32()?.next());
+
+		if let Some(name) = pid.and_then(|pid| fs::read_to_string(format!("/proc/{}/comm", pid)).ok()) {
+			return Some(name.trim().to_string());
+		}
+
+		// WM_CLASS is "instance\0class\0". The instance is usually the executable name.
+		let wm_class = self.conn.get_property(false, self.owner, AtomEnum::WM_CLASS, AtomEnum::STRING, 0, 256).ok()?.reply().ok()?;
+
+		wm_class.value.split(|v| *v == 0)
+			.next()
+			.map(|v| String::from_utf8_lossy(v).into_owned())
+			.filter(|v| !v.is_empty())
+	}
+
+	fn write(&mut self, selection: Selection, formats: &[(String, Vec<u8>)]) -> Result<()> {
+		let formats = formats.to_vec();
+
+		// The selection only exists for as long as someone is around to convert it.
+		thread::spawn(move || {
+			if let Err(e) = serve_selection(selection, formats) {
+				error!(target: "clipboard_listener", "X11 Selection Error: {:?}", e);
+			}
+		});
+
+		Ok(())
+	}
+
+	fn wait_for_change(&mut self) -> Result<Selection> {
+		let missed_update = {
+			let mut missed_updates = self.missed_updates.borrow_mut();
+			if missed_updates.is_empty() { None } else { Some(missed_updates.remove(0)) }
+		};
+
+		let (selection, owner, time) = match missed_update {
+			Some(update) => update,
+
+			None => loop {
+				match self.conn.wait_for_event()? {
+					// Owner is NONE when the selection was cleared.
+					Event::XfixesSelectionNotify(event) if event.owner != x11rb::NONE => break (event.selection, event.owner, event.timestamp),
+					_ => continue
+				}
+			}
+		};
+
+		self.selection = selection;
+		self.owner = owner;
+		self.time = time;
+
+		if self.selection == self.atoms.CLIPBOARD {
+			Ok(Selection::Clipboard)
+		} else {
+			Ok(Selection::Primary)
+		}
+	}
+}
+
+impl X11Backend {
+	/// Ask the selection owner which targets (formats) it can convert the clipboard into.
+	fn read_targets(&self) -> Result<Vec<Atom>> {
+		let data = self.read_target(self.atoms.TARGETS)?;
+
+		Ok(
+			data.chunks_exact(4)
+				.map(|v| Atom::from_ne_bytes([v[0], v[1], v[2], v[3]]))
+				.collect()
+		)
+	}
+
+	fn read_target(&self, target: Atom) -> Result<Vec<u8>> {
+		let property = self.atoms.CLIPBOARD_LISTENER;
+
+		self.conn.convert_selection(self.window, self.selection, target, property, self.time)?;
+		self.conn.flush()?;
+
+		let started = Instant::now();
+
+		loop {
+			if let Event::SelectionNotify(event) = self.next_event(started)? {
+				if event.requestor != self.window {
+					continue;
+				}
+
+				if event.property == x11rb::NONE {
+					return Err(anyhow!("Selection owner refused to convert target {}", target));
+				}
+
+				break;
+			}
+		}
+
+		let reply = self.conn.get_property(true, self.window, property, AtomEnum::ANY, 0, u32::MAX)?.reply()?;
+
+		if reply.type_ == self.atoms.INCR {
+			self.read_incremental(property)
+		} else {
+			Ok(reply.value)
+		}
+	}
+
+	/// Large transfers are sent in chunks. Each chunk is written into the property after we delete the previous one.
+	/// A zero length chunk marks the end of the transfer.
+	fn read_incremental(&self, property: Atom) -> Result<Vec<u8>> {
+		let mut data = Vec::new();
+		let mut started = Instant::now();
+
+		loop {
+			if let Event::PropertyNotify(event) = self.next_event(started)? {
+				if event.window != self.window || event.atom != property || event.state != Property::NEW_VALUE {
+					continue;
+				}
+
+				let reply = self.conn.get_property(true, self.window, property, AtomEnum::ANY, 0, u32::MAX)?.reply()?;
+
+				if reply.value.is_empty() {
+					return Ok(data);
+				}
+
+				data.extend_from_slice(&reply.value);
+				started = Instant::now();
+			}
+		}
+	}
+
+	/// Polls for the next event, giving up if the selection owner doesn't respond in time.
+	fn next_event(&self, started: Instant) -> Result<Event> {
+		loop {
+			match self.conn.poll_for_event()? {
+				Some(Event::XfixesSelectionNotify(event)) => {
+					if event.owner != x11rb::NONE {
+						let mut missed_updates = self.missed_updates.borrow_mut();
+
+						missed_updates.retain(|(selection, _, _)| *selection != event.selection);
+						missed_updates.push((event.selection, event.owner, event.timestamp));
+					}
+				}
+
+				Some(event) => return Ok(event),
+
+				None => {
+					if started.elapsed() > CONVERT_TIMEOUT {
+						return Err(anyhow!("Timed out waiting on the selection owner"));
+					}
+
+					thread::sleep(Duration::from_millis(5));
+				}
+			}
+		}
+	}
+}
+
+impl Drop for X11Backend {
+	fn drop(&mut self) {
+		let _ = self.conn.destroy_window(self.window);
+		let _ = self.conn.flush();
+	}
+}
+
+
+/// Some applications (Firefox) offer text/html as UTF-16 with a BOM.
+fn decode_html_target(data: Vec<u8>) -> String {
+	if let Some(data) = data.strip_prefix(&[0xFF, 0xFE]) {
+		let data = data.chunks_exact(2)
+			.map(|v| u16::from_le_bytes([v[0], v[1]]))
+			.collect::<Vec<_>>();
+
+		String::from_utf16_lossy(&data)
+	} else {
+		String::from_utf8_lossy(&data).into_owned()
+	}
+}
+
+
+/// An INCR transfer to a requestor which is still waiting on the rest of the data.
+struct IncrTransfer {
+	requestor: Window,
+	property: Atom,
+	target: Atom,
+	/// Index into formats
+	index: usize,
+	/// Bytes already written into the property.
+	sent: usize,
+}
+
+
+/// Own the selection and answer conversion requests with `formats` until another client takes it.
+fn serve_selection(selection: Selection, formats: Vec<(String, Vec<u8>)>) -> Result<()> {
+	let (conn, screen_num) = x11rb::connect(None)?;
+
+	let atoms = Atoms::new(&conn)?.reply()?;
+
+	let window = conn.generate_id()?;
+	let root = conn.setup().roots[screen_num].root;
+
+	conn.create_window(
+		x11rb::COPY_DEPTH_FROM_PARENT,
+		window,
+		root,
+		0, 0, 1, 1, 0,
+		WindowClass::INPUT_ONLY,
+		x11rb::COPY_FROM_PARENT,
+		&CreateWindowAux::new()
+	)?;
+
+	// Target -> Index into formats
+	let mut targets = Vec::new();
+
+	for (index, (format, _)) in formats.iter().enumerate() {
+		if format == formats::TEXT {
+			targets.push((atoms.UTF8_STRING, index));
+			targets.push((atoms.TEXT_PLAIN_UTF8, index));
+		} else {
+			targets.push((conn.intern_atom(false, format.as_bytes())?.reply()?.atom, index));
+		}
+	}
+
+	let selection_atom = match selection {
+		Selection::Clipboard => atoms.CLIPBOARD,
+		Selection::Primary => AtomEnum::PRIMARY.into(),
+	};
+
+	conn.set_selection_owner(window, selection_atom, x11rb::CURRENT_TIME)?;
+
+	if conn.get_selection_owner(selection_atom)?.reply()?.owner != window {
+		return Err(anyhow!("Unable to take ownership of the X11 {:?} selection", selection));
+	}
+
+	// Leaves room for the rest of the request.
+	let chunk_size = INCR_CHUNK_SIZE.min(conn.maximum_request_bytes() / 2);
+
+	let mut transfers: Vec<IncrTransfer> = Vec::new();
+
+	loop {
+		match conn.wait_for_event()? {
+			Event::SelectionRequest(event) => {
+				// Obsolete clients don't specify a property.
+				let property = if event.property == x11rb::NONE { event.target } else { event.property };
+
+				let property = if event.target == atoms.TARGETS {
+					let mut list = targets.iter().map(|(target, _)| *target).collect::<Vec<_>>();
+					list.push(atoms.TARGETS);
+
+					conn.change_property32(PropMode::REPLACE, event.requestor, property, AtomEnum::ATOM, &list)?;
+
+					property
+				} else if let Some(&(_, index)) = targets.iter().find(|(target, _)| *target == event.target) {
+					let data = &formats[index].1;
+
+					if data.len() > chunk_size {
+						// Deleting the property asks for the next chunk. Destroying the window abandons the transfer.
+						conn.change_window_attributes(event.requestor, &ChangeWindowAttributesAux::new().event_mask(EventMask::PROPERTY_CHANGE | EventMask::STRUCTURE_NOTIFY))?;
+
+						// The size is only a lower bound, so anything past u32 still works.
+						conn.change_property32(PropMode::REPLACE, event.requestor, property, atoms.INCR, &[u32::try_from(data.len()).unwrap_or(u32::MAX)])?;
+
+						transfers.retain(|v| v.requestor != event.requestor || v.property != property);
+						transfers.push(IncrTransfer {
+							requestor: event.requestor,
+							property,
+							target: event.target,
+							index,
+							sent: 0
+						});
+
+						property
+					} else {
+						match conn.change_property8(PropMode::REPLACE, event.requestor, property, event.target, data) {
+							Ok(_) => property,
+							Err(e) => {
+								error!(target: "clipboard_listener", "X11 Selection Error: {:?}", e);
+								x11rb::NONE
+							}
+						}
+					}
+				} else {
+					x11rb::NONE
+				};
+
+				conn.send_event(false, event.requestor, EventMask::NO_EVENT, SelectionNotifyEvent {
+					response_type: xproto::SELECTION_NOTIFY_EVENT,
+					sequence: 0,
+					time: event.time,
+					requestor: event.requestor,
+					selection: event.selection,
+					target: event.target,
+					property,
+				})?;
+
+				conn.flush()?;
+			}
+
+			Event::PropertyNotify(event) if event.state == Property::DELETE => {
+				let position = match transfers.iter().position(|v| v.requestor == event.window && v.property == event.atom) {
+					Some(v) => v,
+					None => continue
+				};
+
+				let transfer = &mut transfers[position];
+				let data = &formats[transfer.index].1;
+
+				let chunk = &data[transfer.sent..data.len().min(transfer.sent + chunk_size)];
+
+				conn.change_property8(PropMode::REPLACE, transfer.requestor, transfer.property, transfer.target, chunk)?;
+
+				transfer.sent += chunk.len();
+
+				// That was the zero length chunk which ends it.
+				if chunk.is_empty() {
+					transfers.remove(position);
+
+					if !transfers.iter().any(|v| v.requestor == event.window) {
+						conn.change_window_attributes(event.window, &ChangeWindowAttributesAux::new().event_mask(EventMask::NO_EVENT))?;
+					}
+				}
+
+				conn.flush()?;
+			}
+
+			Event::DestroyNotify(event) => transfers.retain(|v| v.requestor != event.window),
+
+			Event::SelectionClear(_) => return Ok(()),
+
+			_ => {}
+		}
+	}
+}
\ No newline at end of file
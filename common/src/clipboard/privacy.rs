@@ -0,0 +1,54 @@
+use super::ClipboardBackend;
+
+
+// Concealed Hints
+
+// Password managers (KeePassXC, 1Password, etc.) add a format to copied passwords asking clipboard managers not to keep them.
+
+// Windows: https://docs.microsoft.com/en-us/windows/win32/dataxchg/clipboard-formats#cloud-clipboard-and-clipboard-history-formats
+//      ExcludeClipboardContentFromMonitorProcessing - Any data. Don't store it at all.
+//      CanIncludeInClipboardHistory - DWORD. 0 means it shouldn't be kept in a clipboard history.
+// KDE (and the Linux password managers following it):
+//      x-kde-passwordManagerHint - "secret"
+
+static EXCLUDE_FROM_MONITOR: &str = "ExcludeClipboardContentFromMonitorProcessing";
+static CAN_INCLUDE_IN_HISTORY: &str = "CanIncludeInClipboardHistory";
+static KDE_PASSWORD_MANAGER_HINT: &str = "x-kde-passwordManagerHint";
+
+
+/// Whether the changed selection is marked as something which shouldn't be stored.
+///
+/// A hint we can't read is treated as concealed.
+pub fn is_concealed(backend: &mut dyn ClipboardBackend, available: &[String]) -> bool {
+	let has_format = |format: &str| available.iter().any(|v| v == format);
+
+	if has_format(EXCLUDE_FROM_MONITOR) {
+		return true;
+	}
+
+	if has_format(CAN_INCLUDE_IN_HISTORY) {
+		match backend.read(CAN_INCLUDE_IN_HISTORY) {
+			Ok(data) if data.len() >= 4 => {
+				if u32::from_le_bytes([data[0], data[1], data[2], data[3]]) == 0 {
+					return true;
+				}
+			}
+
+			_ => return true
+		}
+	}
+
+	if has_format(KDE_PASSWORD_MANAGER_HINT) {
+		match backend.read(KDE_PASSWORD_MANAGER_HINT) {
+			Ok(data) => {
+				if String::from_utf8_lossy(&data).trim() == "secret" {
+					return true;
+				}
+			}
+
+			Err(_) => return true
+		}
+	}
+
+	false
+}
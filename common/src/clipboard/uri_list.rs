@@ -0,0 +1,73 @@
+// text/uri-list
+// https://www.rfc-editor.org/rfc/rfc2483#section-5
+
+// One URI per line (separated by CRLF). Lines starting with # are comments.
+// Copied files are file:// URIs with their paths percent-encoded.
+
+
+/// File paths from a text/uri-list. URIs which aren't local files are skipped.
+pub fn parse_uri_list(value: &str) -> Vec<String> {
+	value.lines()
+		.map(|v| v.trim())
+		.filter(|v| !v.is_empty() && !v.starts_with('#'))
+		.filter_map(|v| v.strip_prefix("file://"))
+		.filter_map(|v| {
+			// Skip the host. Usually empty or localhost.
+			let path = &v[v.find('/')?..];
+			let path = percent_decode(path);
+
+			// Windows: /C:/Users -> C:/Users
+			if path.as_bytes().get(2) == Some(&b':') {
+				Some(path[1..].to_string())
+			} else {
+				Some(path)
+			}
+		})
+		.collect()
+}
+
+pub fn create_uri_list(paths: &[String]) -> String {
+	paths.iter()
+		.map(|path| {
+			let path = path.replace('\\', "/");
+
+			if path.starts_with('/') {
+				format!("file://{}\r\n", percent_encode(&path))
+			} else {
+				format!("file:///{}\r\n", percent_encode(&path))
+			}
+		})
+		.collect()
+}
+
+
+fn percent_decode(value: &str) -> String {
+	let bytes = value.as_bytes();
+	let mut decoded = Vec::with_capacity(bytes.len());
+
+	let mut index = 0;
+
+	while index < bytes.len() {
+		if bytes[index] == b'%' {
+			if let Some(byte) = value.get(index + 1..index + 3).and_then(|v| u8::from_str_radix(v, 16).ok()) {
+				decoded.push(byte);
+				index += 3;
+				continue;
+			}
+		}
+
+		decoded.push(bytes[index]);
+		index += 1;
+	}
+
+	String::from_utf8_lossy(&decoded).into_owned()
+}
+
+fn percent_encode(value: &str) -> String {
+	value.bytes()
+		.map(|v| match v {
+			b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'_' | b'.' | b'~' | b'/' | b':' => (v as char).to_string(),
+			_ => format!("%{:02X}", v)
+		})
+		.collect()
+}
\ No newline at end of file
@@ -0,0 +1,389 @@
+use std::io::{self, Cursor};
+use std::path::Path;
+use std::process;
+use std::sync::{Arc, RwLock};
+
+use anyhow::{Result, anyhow};
+use clipboard_win::SystemError;
+use image::{DynamicImage, ImageFormat};
+use log::error;
+use windows_win::{Messages, Window, raw};
+use windows_win::winapi;
+use windows_win::winapi::um::handleapi::CloseHandle;
+use windows_win::winapi::um::processthreadsapi::OpenProcess;
+use windows_win::winapi::um::winbase::QueryFullProcessImageNameW;
+use windows_win::winapi::um::winnt::PROCESS_QUERY_LIMITED_INFORMATION;
+use windows_win::winapi::um::winuser::{AddClipboardFormatListener, GetClipboardOwner, GetWindowThreadProcessId, RemoveClipboardFormatListener};
+
+use crate::config::Config;
+use crate::store::StorageContainer;
+
+use super::{ClipboardBackend, Selection, formats, create_uri_list, parse_uri_list};
+
+
+/// Size of the BITMAPFILEHEADER which prefixes a bitmap file.
+const BITMAP_FILE_HEADER_LEN: usize = 14;
+/// Size of the DROPFILES struct which prefixes a CF_HDROP file list.
+const DROPFILES_LEN: u32 = 20;
+/// Formats registered with RegisterClipboardFormat are in 0xC000 through 0xFFFF.
+const REGISTERED_FORMATS: std::ops::RangeInclusive<u32> = 0xC000..=0xFFFF;
+
+// Creating a Clipboard Format Listener
+
+// A clipboard format listener is a window which has registered to be notified when the contents of the clipboard has changed.
+// This method is recommended over creating a clipboard viewer window because it is simpler to implement and
+//      avoids problems if programs fail to maintain the clipboard viewer chain properly or if a window in
+//      the clipboard viewer chain stops responding to messages.
+
+// A window registers as a clipboard format listener by calling the [AddClipboardFormatListener] function.
+// When the contents of the clipboard change, the window is posted a [WM_CLIPBOARDUPDATE] message.
+// The registration remains valid until the window unregister itself by calling the [RemoveClipboardFormatListener] function.
+
+fn attempt_to_register_format() -> u32 {
+	for _ in 0..10 {
+		let format = clipboard_win::register_format("HTML Format");
+
+		if let Some(format) = format {
+			// TODO: Doesn't always return true for some reason.
+			// if clipboard_win::raw::is_format_avail(format.get()) {
+			return format.get();
+		} else {
+			error!(target: "clipboard_listener", "HTML Format Creation Error: {}", SystemError::last());
+		}
+	}
+
+	error!(target: "clipboard_listener", "Unable to create HTML Format for Clipboard");
+
+	process::exit(1);
+}
+
+/// Clipboard of the current session.
+pub fn system_backend() -> Result<Box<dyn ClipboardBackend>> {
+	Ok(Box::new(WindowsBackend::new()))
+}
+
+
+#[derive(Default)]
+pub struct AppListener;
+
+impl super::Listener for AppListener {
+	fn run(&mut self, conn: StorageContainer, config: Arc<RwLock<Config>>) -> Result<()> {
+		super::listen(&mut WindowsBackend::new(), conn, config)
+	}
+}
+
+
+pub struct WindowsBackend {
+	html_format: u32,
+	/// "PNG" registered format used by browsers and office applications.
+	png_format: Option<u32>,
+	/// Created on the first [`ClipboardBackend::wait_for_change`] call.
+	listener: Option<(ListenerGuard, Window)>,
+}
+
+impl WindowsBackend {
+	pub fn new() -> Self {
+		Self {
+			html_format: attempt_to_register_format(),
+			png_format: clipboard_win::register_format("PNG").map(|v| v.get()),
+			listener: None
+		}
+	}
+}
+
+impl Default for WindowsBackend {
+	fn default() -> Self {
+		Self::new()
+	}
+}
+
+impl ClipboardBackend for WindowsBackend {
+	fn formats(&mut self) -> Result<Vec<String>> {
+		let _clippy = clipboard_win::Clipboard::new_attempts(10).map_err(|v| anyhow!(v))?;
+
+		let mut available = Vec::new();
+
+		if clipboard_win::is_format_avail(clipboard_win::formats::CF_UNICODETEXT) {
+			available.push(formats::TEXT.to_string());
+		}
+
+		if clipboard_win::is_format_avail(self.html_format) {
+			available.push(formats::HTML.to_string());
+		}
+
+		if self.png_format.map(clipboard_win::is_format_avail).unwrap_or_default() {
+			available.push(formats::PNG.to_string());
+		}
+
+		if clipboard_win::is_format_avail(clipboard_win::formats::CF_BITMAP) {
+			available.push(formats::BMP.to_string());
+		}
+
+		if clipboard_win::is_format_avail(clipboard_win::formats::CF_HDROP) {
+			available.push(formats::URI_LIST.to_string());
+		}
+
+		// Application formats (Rich Text Format, etc.) by their registered name.
+		// The other standard formats are synthesized by Windows from the ones above.
+		for format in clipboard_win::raw::EnumFormats::new() {
+			if !REGISTERED_FORMATS.contains(&format) || format == self.html_format || Some(format) == self.png_format {
+				continue;
+			}
+
+			if let Some(name) = clipboard_win::raw::format_name_big(format) {
+				available.push(name);
+			}
+		}
+
+		Ok(available)
+	}
+
+	fn read(&mut self, format: &str) -> Result<Vec<u8>> {
+		let _clippy = clipboard_win::Clipboard::new_attempts(10).map_err(|v| anyhow!(v))?;
+
+		match format {
+			formats::TEXT => Ok(clipboard_win::get::<String, _>(clipboard_win::Unicode).map_err(|v| anyhow!(v))?.into_bytes()),
+
+			formats::HTML => {
+				let mut data = Vec::new();
+
+				// Error Codes: https://docs.microsoft.com/en-us/windows/win32/debug/system-error-codes--1000-1299-
+				clipboard_win::raw::get_vec(self.html_format, &mut data).map_err(|v| anyhow!(v))?;
+
+				Ok(parse_html_clipboard(String::from_utf8(data)?).into_bytes())
+			}
+
+			formats::PNG => {
+				let png_format = self.png_format.ok_or_else(|| anyhow!("PNG Format isn't registered"))?;
+				let mut data = Vec::new();
+
+				clipboard_win::raw::get_vec(png_format, &mut data).map_err(|v| anyhow!(v))?;
+
+				Ok(data)
+			}
+
+			formats::BMP => Ok(clipboard_win::get::<Vec<u8>, _>(clipboard_win::formats::Bitmap).map_err(|v| anyhow!(v))?),
+
+			formats::URI_LIST => {
+				let paths = clipboard_win::get::<Vec<String>, _>(clipboard_win::formats::FileList).map_err(|v| anyhow!(v))?;
+
+				Ok(create_uri_list(&paths).into_bytes())
+			}
+
+			_ => {
+				let registered = clipboard_win::register_format(format).ok_or_else(|| anyhow!("Unsupported Clipboard Format {}", format))?;
+				let mut data = Vec::new();
+
+				clipboard_win::raw::get_vec(registered.get(), &mut data).map_err(|v| anyhow!(v))?;
+
+				Ok(data)
+			}
+		}
+	}
+
+	fn source_app(&mut self) -> Option<String> {
+		unsafe {
+			let owner = GetClipboardOwner();
+
+			if owner.is_null() {
+				return None;
+			}
+
+			let mut process_id = 0;
+			GetWindowThreadProcessId(owner, &mut process_id);
+
+			if process_id == 0 {
+				return None;
+			}
+
+			let process = OpenProcess(PROCESS_QUERY_LIMITED_INFORMATION, 0, process_id);
+
+			if process.is_null() {
+				return None;
+			}
+
+			let mut buffer = [0u16; 1024];
+			let mut size = buffer.len() as u32;
+
+			let found = QueryFullProcessImageNameW(process, 0, buffer.as_mut_ptr(), &mut size);
+
+			CloseHandle(process);
+
+			if found == 0 {
+				return None;
+			}
+
+			let path = String::from_utf16_lossy(&buffer[..size as usize]);
+
+			Path::new(&path).file_name().map(|v| v.to_string_lossy().into_owned())
+		}
+	}
+
+	fn write(&mut self, selection: Selection, formats: &[(String, Vec<u8>)]) -> Result<()> {
+		if selection != Selection::Clipboard {
+			return Err(anyhow!("Windows doesn't have a {:?} selection", selection));
+		}
+
+		let _clippy = clipboard_win::Clipboard::new_attempts(10).map_err(|v| anyhow!(v))?;
+
+		clipboard_win::empty().map_err(|v| anyhow!(v))?;
+
+		// Use the raw setters since the typed ones empty the clipboard before setting.
+		for (format, data) in formats {
+			match format.as_str() {
+				formats::TEXT => {
+					let data = std::str::from_utf8(data)?
+						.encode_utf16()
+						.chain(std::iter::once(0))
+						.flat_map(|v| v.to_le_bytes())
+						.collect::<Vec<_>>();
+
+					clipboard_win::raw::set_without_clear(clipboard_win::formats::CF_UNICODETEXT, &data).map_err(|v| anyhow!(v))?;
+				}
+
+				formats::HTML => {
+					let data = create_html_clipboard(std::str::from_utf8(data)?);
+
+					clipboard_win::raw::set_without_clear(self.html_format, data.as_bytes()).map_err(|v| anyhow!(v))?;
+				}
+
+				formats::PNG => {
+					if let Some(png_format) = self.png_format {
+						clipboard_win::raw::set_without_clear(png_format, data).map_err(|v| anyhow!(v))?;
+					}
+
+					// Most applications only understand bitmaps.
+					let mut bitmap = Cursor::new(Vec::new());
+					DynamicImage::ImageRgb8(image::load_from_memory(data)?.to_rgb8()).write_to(&mut bitmap, ImageFormat::Bmp)?;
+
+					clipboard_win::raw::set_without_clear(clipboard_win::formats::CF_DIB, &bitmap.into_inner()[BITMAP_FILE_HEADER_LEN..]).map_err(|v| anyhow!(v))?;
+				}
+
+				formats::BMP => {
+					// CF_DIB is a bitmap file without the file header.
+					let data = data.get(BITMAP_FILE_HEADER_LEN..).ok_or_else(|| anyhow!("Invalid Bitmap"))?;
+
+					clipboard_win::raw::set_without_clear(clipboard_win::formats::CF_DIB, data).map_err(|v| anyhow!(v))?;
+				}
+
+				formats::URI_LIST => {
+					let paths = parse_uri_list(std::str::from_utf8(data)?);
+
+					clipboard_win::raw::set_without_clear(clipboard_win::formats::CF_HDROP, &create_drop_files(&paths)).map_err(|v| anyhow!(v))?;
+				}
+
+				_ => match clipboard_win::register_format(format) {
+					Some(registered) => clipboard_win::raw::set_without_clear(registered.get(), data).map_err(|v| anyhow!(v))?,
+					None => error!(target: "clipboard_listener", "Unable to write Clipboard Format {}", format)
+				}
+			}
+		}
+
+		Ok(())
+	}
+
+	fn wait_for_change(&mut self) -> Result<Selection> {
+		if self.listener.is_none() {
+			let window = Window::from_builder(raw::window::Builder::new().class_name("STATIC").parent_message())?;
+			let guard = ListenerGuard::new(&window)?;
+
+			self.listener = Some((guard, window));
+		}
+
+		let window = &self.listener.as_ref().unwrap().1;
+
+		// 0x031D (797) is Clipboard update.
+		for msg in Messages::new().window(Some(window.inner())).low(Some(0x031D)).high(Some(0x031D)) {
+			match msg {
+				Ok(_) => return Ok(Selection::Clipboard),
+
+				Err(error) => {
+					error!(target: "clipboard_listener", "{:?}", error);
+				}
+			}
+		}
+
+		Err(anyhow!("Clipboard Listener Message Loop Ended"))
+	}
+}
+
+
+
+
+pub struct ListenerGuard(winapi::shared::windef::HWND);
+
+impl ListenerGuard {
+	#[inline]
+	pub fn new(window: &Window) -> io::Result<Self> {
+		let window = window.inner();
+
+		unsafe {
+			if AddClipboardFormatListener(window) != 1 {
+				Err(io::Error::last_os_error())
+			} else {
+				Ok(ListenerGuard(window))
+			}
+		}
+	}
+}
+
+impl Drop for ListenerGuard {
+	fn drop(&mut self) {
+		unsafe {
+			RemoveClipboardFormatListener(self.0);
+		}
+	}
+}
+
+
+
+
+static FRAG_START: &str = "<!--StartFragment-->";
+static FRAG_END: &str = "<!--EndFragment-->";
+
+fn parse_html_clipboard(value: String) -> String {
+	if let (Some(start), Some(end)) = (value.find(FRAG_START), value.find(FRAG_END)) {
+		value[start + FRAG_START.len()..end].to_string()
+	} else {
+		value
+	}
+}
+
+// https://docs.microsoft.com/en-us/windows/win32/dataxchg/html-clipboard-format
+fn create_html_clipboard(fragment: &str) -> String {
+	let header = |start_html: usize, end_html: usize, start_fragment: usize, end_fragment: usize| format!(
+		"Version:0.9\r\nStartHTML:{:010}\r\nEndHTML:{:010}\r\nStartFragment:{:010}\r\nEndFragment:{:010}\r\n",
+		start_html, end_html, start_fragment, end_fragment
+	);
+
+	// Offsets are zero padded so the header length doesn't depend on them.
+	let start_html = header(0, 0, 0, 0).len();
+	let body = format!("<html><body>{}{}{}</body></html>", FRAG_START, fragment, FRAG_END);
+
+	let start_fragment = start_html + "<html><body>".len() + FRAG_START.len();
+	let end_fragment = start_fragment + fragment.len();
+	let end_html = start_html + body.len();
+
+	header(start_html, end_html, start_fragment, end_fragment) + &body
+}
+
+// https://docs.microsoft.com/en-us/windows/win32/shell/clipboard#cf_hdrop
+fn create_drop_files(paths: &[String]) -> Vec<u8> {
+	let mut data = Vec::new();
+
+	// DROPFILES { pFiles, pt.x, pt.y, fNC, fWide }
+	data.extend(DROPFILES_LEN.to_le_bytes());
+	data.extend(0i32.to_le_bytes());
+	data.extend(0i32.to_le_bytes());
+	data.extend(0u32.to_le_bytes());
+	data.extend(1u32.to_le_bytes());
+
+	// Each path is NUL terminated with an extra NUL ending the list.
+	for path in paths {
+		data.extend(path.replace('/', "\\").encode_utf16().chain(std::iter::once(0)).flat_map(|v| v.to_le_bytes()));
+	}
+
+	data.extend(0u16.to_le_bytes());
+
+	data
+}
@@ -1,4 +1,7 @@
+use std::time::SystemTime;
+
 use anyhow::Result;
+use chrono::Utc;
 use serde::{Serialize, Deserialize};
 
 
@@ -9,16 +12,28 @@ static CONFIG_PATH: &str = "config.toml";
 pub struct Config {
 	pub app: ConfigApp,
 	pub stores: Stores,
+	pub selections: ConfigSelections,
+	pub privacy: ConfigPrivacy,
+	pub apps: ConfigApps,
+	pub retention: ConfigRetention,
+	pub trash: ConfigTrash,
 	// pub auth
 }
 
 impl Config {
 	pub fn load() -> Result<Self> {
-		if let Ok(value) = std::fs::read(CONFIG_PATH) {
-			Ok(toml::from_slice(&value)?)
+		let config: Self = if let Ok(value) = std::fs::read(CONFIG_PATH) {
+			toml::from_slice(&value)?
 		} else {
-			Ok(Self::default())
+			Self::default()
+		};
+
+		// Reported here once instead of on every copy.
+		for problem in crate::store::check_rules(&config.privacy.rules) {
+			log::error!(target: "clipboard_listener", "{}", problem);
 		}
+
+		Ok(config)
 	}
 
 	pub fn save(&self) -> Result<()> {
@@ -33,6 +48,11 @@ impl Config {
 		*self = Self::load()?;
 		Ok(())
 	}
+
+	/// When the config file was last changed. None if there isn't one.
+	pub fn modified() -> Option<SystemTime> {
+		std::fs::metadata(CONFIG_PATH).and_then(|v| v.modified()).ok()
+	}
 }
 
 
@@ -59,12 +79,198 @@ impl Default for ConfigApp {
 }
 
 
+/// X11/Wayland PRIMARY selection (middle-click paste). Ignored on Windows.
+#[derive(Serialize, Deserialize, Default)]
+#[serde(default)]
+pub struct ConfigSelections {
+	/// Store PRIMARY changes as well as CLIPBOARD changes.
+	pub record_primary: bool,
+	/// Copy PRIMARY into CLIPBOARD and CLIPBOARD into PRIMARY when either changes.
+	pub sync_primary: bool
+}
+
+
+/// Applications are matched by their process name (case-insensitive, `.exe` is optional).
+#[derive(Serialize, Deserialize, Default)]
+#[serde(default)]
+pub struct ConfigApps {
+	/// Copies from these applications aren't stored.
+	pub ignore: Vec<String>,
+	/// If not empty only copies from these applications are stored.
+	pub allow: Vec<String>
+}
+
+impl ConfigApps {
+	/// Whether copies from `app` shouldn't be stored. Unknown applications are only ignored when there's an allow list.
+	pub fn is_ignored(&self, app: Option<&str>) -> bool {
+		let matches = |list: &[String], app: &str| {
+			let app = app.to_lowercase();
+			let app = app.strip_suffix(".exe").unwrap_or(&app);
+
+			list.iter().any(|v| {
+				let v = v.to_lowercase();
+				v.strip_suffix(".exe").unwrap_or(&v) == app
+			})
+		};
+
+		match app {
+			Some(app) => matches(&self.ignore, app) || (!self.allow.is_empty() && !matches(&self.allow, app)),
+			None => !self.allow.is_empty()
+		}
+	}
+}
+
+
+/// Older copies are deleted once any limit is reached. Starred and pinned copies are never deleted. 0 is no limit.
+#[derive(Serialize, Deserialize, Clone)]
+#[serde(default)]
+pub struct ConfigRetention {
+	/// Copies to keep which aren't starred or pinned.
+	pub max_items: usize,
+	/// Days since it was last copied.
+	pub max_age_days: usize,
+	/// Size of everything stored, starred and pinned included (MB).
+	pub max_total_size: usize,
+	/// How often the tray applies the limits.
+	pub interval_minutes: usize
+}
+
+impl Default for ConfigRetention {
+	fn default() -> Self {
+		Self {
+			max_items: 0,
+			max_age_days: 0,
+			max_total_size: 0,
+			interval_minutes: 30
+		}
+	}
+}
+
+impl ConfigRetention {
+	pub fn is_enabled(&self) -> bool {
+		self.max_items != 0 || self.max_age_days != 0 || self.max_total_size != 0
+	}
+}
+
+
+/// Deleted copies are kept in the trash until they're purged.
+#[derive(Serialize, Deserialize, Clone)]
+#[serde(default)]
+pub struct ConfigTrash {
+	/// Days after deleting that copies are purged. 0 keeps them until the trash is emptied.
+	pub purge_after_days: usize
+}
+
+impl Default for ConfigTrash {
+	fn default() -> Self {
+		Self {
+			purge_after_days: 30
+		}
+	}
+}
+
+impl ConfigTrash {
+	/// Unix timestamp (ms). Anything deleted before it should be purged.
+	pub fn purge_before(&self) -> Option<usize> {
+		if self.purge_after_days == 0 {
+			None
+		} else {
+			Some((Utc::now().timestamp_millis() as usize).saturating_sub(self.purge_after_days * 24 * 60 * 60 * 1000))
+		}
+	}
+}
+
+
+#[derive(Serialize, Deserialize)]
+#[serde(default)]
+pub struct ConfigPrivacy {
+	/// Skip copies which password managers mark as concealed.
+	pub respect_concealed_hints: bool,
+	/// How long text matching an `expire` rule is kept for.
+	pub expire_minutes: usize,
+	/// Checked against copied text before it's stored.
+	pub rules: Vec<SensitiveRule>
+}
+
+impl Default for ConfigPrivacy {
+	fn default() -> Self {
+		Self {
+			respect_concealed_hints: true,
+			expire_minutes: 10,
+			rules: vec![
+				SensitiveRule::new("Credit Card Number", SensitiveDetector::CreditCard, SensitiveAction::Mask),
+				SensitiveRule::new("AWS Access Key", SensitiveDetector::AwsKey, SensitiveAction::Drop),
+				SensitiveRule::new("JSON Web Token", SensitiveDetector::Jwt, SensitiveAction::Expire),
+				SensitiveRule::new("Private Key", SensitiveDetector::PrivateKey, SensitiveAction::Drop),
+			]
+		}
+	}
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+#[serde(default)]
+pub struct SensitiveRule {
+	pub name: String,
+	pub enabled: bool,
+	pub detector: SensitiveDetector,
+	/// Used by the `regex` detector.
+	#[serde(skip_serializing_if = "Option::is_none")]
+	pub pattern: Option<String>,
+	pub action: SensitiveAction
+}
+
+impl SensitiveRule {
+	pub fn new(name: &str, detector: SensitiveDetector, action: SensitiveAction) -> Self {
+		Self {
+			name: name.to_string(),
+			enabled: true,
+			detector,
+			pattern: None,
+			action
+		}
+	}
+}
+
+impl Default for SensitiveRule {
+	fn default() -> Self {
+		Self::new("Custom", SensitiveDetector::Regex, SensitiveAction::Drop)
+	}
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Debug)]
+#[serde(rename_all = "snake_case")]
+pub enum SensitiveDetector {
+	/// 13-19 digits which pass the Luhn check.
+	CreditCard,
+	/// AWS access key ids (AKIA..., ASIA...)
+	AwsKey,
+	Jwt,
+	/// PEM private key blocks.
+	PrivateKey,
+	Regex,
+}
+
+/// Ordered from least to most strict. The strictest matching rule wins.
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Debug)]
+#[serde(rename_all = "snake_case")]
+pub enum SensitiveAction {
+	/// Store it but hide the text in the list.
+	Mask,
+	/// Store it and delete it after `expire_minutes`.
+	Expire,
+	/// Don't store it.
+	Drop,
+}
+
+
 #[derive(Serialize, Deserialize, Default)]
 #[serde(default)]
 pub struct Stores {
 	pub text: StoreTypeText,
 	pub image: StoreTypeImage,
 	pub file: StoreTypeFile,
+	pub formats: StoreTypeFormats,
+	pub blobs: StoreBlobs,
 }
 
 #[derive(Serialize, Deserialize)]
@@ -103,6 +309,8 @@ impl Default for StoreTypeImage {
 #[serde(default)]
 pub struct StoreTypeFile {
 	pub enabled: bool,
+	/// Also store the contents of copied files.
+	pub snapshot_contents: bool,
 	pub max_size: usize
 }
 
@@ -110,7 +318,50 @@ impl Default for StoreTypeFile {
 	fn default() -> Self {
 		Self {
 			enabled: false,
+			snapshot_contents: false,
 			max_size: 5120
 		}
 	}
-}
\ No newline at end of file
+}
+
+/// Every other format offered alongside the text, image or files (RTF, application specific formats, etc.)
+#[derive(Serialize, Deserialize)]
+#[serde(default)]
+pub struct StoreTypeFormats {
+	pub enabled: bool,
+	/// Total size of the other formats for a single copy.
+	pub max_size: usize
+}
+
+impl Default for StoreTypeFormats {
+	fn default() -> Self {
+		Self {
+			enabled: true,
+			max_size: 100
+		}
+	}
+}
+
+/// Images and file snapshots over the threshold are written to the `blobs` directory next to the database instead of into it.
+#[derive(Serialize, Deserialize)]
+#[serde(default)]
+pub struct StoreBlobs {
+	pub enabled: bool,
+	pub threshold: usize
+}
+
+impl Default for StoreBlobs {
+	fn default() -> Self {
+		Self {
+			enabled: true,
+			threshold: 1
+		}
+	}
+}
+
+impl StoreBlobs {
+	/// Whether a payload of `size` bytes should be stored on disk.
+	pub fn is_over_threshold(&self, size: usize) -> bool {
+		self.enabled && size > self.threshold * 1000 * 1000 // B -> KB -> MB
+	}
+}
@@ -0,0 +1,105 @@
+use std::collections::HashSet;
+use std::fs;
+use std::io::ErrorKind;
+use std::path::PathBuf;
+
+use anyhow::{Result, bail};
+
+
+// Blob Store
+
+// Large payloads are written to `blobs/ab/cdef…` next to the database instead of into it. Files are named by
+//      the SHA-256 hash of their contents so identical payloads share a single file.
+// The `blobs` table counts how many rows reference each file. A file is removed once nothing references it.
+
+
+#[derive(Clone)]
+pub struct BlobStore {
+	root: PathBuf
+}
+
+impl BlobStore {
+	pub fn new(root: impl Into<PathBuf>) -> Self {
+		Self {
+			root: root.into()
+		}
+	}
+
+	pub fn path(&self, hash: &str) -> Result<PathBuf> {
+		if hash.len() < 3 || !hash.bytes().all(|v| v.is_ascii_hexdigit()) {
+			bail!("Invalid Blob Hash {:?}", hash);
+		}
+
+		Ok(self.root.join(&hash[..2]).join(&hash[2..]))
+	}
+
+	pub fn write(&self, hash: &str, data: &[u8]) -> Result<()> {
+		let path = self.path(hash)?;
+
+		if let Some(parent) = path.parent() {
+			fs::create_dir_all(parent)?;
+		}
+
+		// Write then rename so a crash never leaves a partial blob under its final name.
+		let temp_path = path.with_extension("tmp");
+
+		fs::write(&temp_path, data)?;
+		fs::rename(&temp_path, &path)?;
+
+		Ok(())
+	}
+
+	pub fn read(&self, hash: &str) -> Result<Vec<u8>> {
+		Ok(fs::read(self.path(hash)?)?)
+	}
+
+	pub fn remove(&self, hash: &str) -> Result<()> {
+		match fs::remove_file(self.path(hash)?) {
+			Err(e) if e.kind() != ErrorKind::NotFound => Err(e.into()),
+			_ => Ok(())
+		}
+	}
+
+	/// Delete the directory and everything in it.
+	pub fn remove_all(&self) -> Result<()> {
+		match fs::remove_dir_all(&self.root) {
+			Err(e) if e.kind() != ErrorKind::NotFound => Err(e.into()),
+			_ => Ok(())
+		}
+	}
+
+	/// Delete every file whose hash isn't in `referenced`. Returns how many were deleted.
+	pub fn remove_unreferenced(&self, referenced: &HashSet<String>) -> Result<usize> {
+		let dirs = match fs::read_dir(&self.root) {
+			Ok(v) => v,
+			Err(e) if e.kind() == ErrorKind::NotFound => return Ok(0),
+			Err(e) => return Err(e.into())
+		};
+
+		let mut deleted = 0;
+
+		for dir in dirs {
+			let dir = dir?;
+
+			if !dir.file_type()?.is_dir() {
+				continue;
+			}
+
+			let prefix = dir.file_name().to_string_lossy().into_owned();
+
+			for file in fs::read_dir(dir.path())? {
+				let file = file?;
+
+				// Also catches .tmp files left behind by an interrupted write.
+				let hash = format!("{}{}", prefix, file.file_name().to_string_lossy());
+
+				if !referenced.contains(&hash) {
+					fs::remove_file(file.path())?;
+					deleted += 1;
+				}
+			}
+		}
+
+		Ok(deleted)
+	}
+}
@@ -0,0 +1,445 @@
+use super::{marked_snippet, ReturnedItem, ReturnedItemType};
+
+
+// Fuzzy Matching
+
+// Scores text the way fzf and skim do. The pattern's characters have to appear in order (ignoring case)
+//      and matches are scored higher when they're consecutive or start a word.
+// To tolerate typos a few pattern characters can be missing from the text, each at a large penalty.
+
+const SCORE_MATCH: i32 = 16;
+const SCORE_GAP: i32 = 1;
+const SCORE_TYPO: i32 = 24;
+
+const BONUS_CONSECUTIVE: i32 = 8;
+const BONUS_BOUNDARY: i32 = 8;
+const BONUS_CAMEL_CASE: i32 = 6;
+
+/// Results returned by `rank`.
+const RESULT_LIMIT: usize = 100;
+/// Only the newest candidates are scored.
+pub const CANDIDATE_LIMIT: usize = 1000;
+
+/// Only the start of long text is matched against.
+const MAX_TEXT_CHARS: usize = 2000;
+/// Longer patterns are cut short.
+const MAX_PATTERN_CHARS: usize = 64;
+
+
+/// Items which fuzzily match the pattern, best first, with the matched characters marked in their snippet.
+pub fn rank(items: Vec<ReturnedItem>, pattern: &str) -> Vec<ReturnedItem> {
+	let mut scored = items.into_iter()
+		.filter_map(|mut item| {
+			let text = match &item.value {
+				ReturnedItemType::Text(text) => text.clone(),
+				ReturnedItemType::Files(paths) => paths.join("\n"),
+				_ => return None
+			};
+
+			let found = fuzzy_match(pattern, &text)?;
+
+			if !item.is_masked {
+				let ranges = found.positions.iter().map(|&v| v..v + text[v..].chars().next().map(|v| v.len_utf8()).unwrap_or(1)).collect::<Vec<_>>();
+				item.snippet = Some(marked_snippet(&text, &ranges));
+			}
+
+			Some((found.score, item))
+		})
+		.collect::<Vec<_>>();
+
+	// Stable so equal scores stay newest first.
+	scored.sort_by_key(|(score, _)| std::cmp::Reverse(*score));
+
+	scored.into_iter().take(RESULT_LIMIT).map(|(_, item)| item).collect()
+}
+
+
+/// What text needs to contain to possibly match a pattern. Checked before scoring so the stores can skip
+///      most of what they have, SqliteStore with LIKE.
+pub struct Candidates {
+	/// The pattern's ASCII characters, lowercase and without duplicates. Other characters are left out as
+	///      SQLite only ignores the case of ASCII.
+	pub chars: Vec<char>,
+	/// How many of them have to be in the text. Each missing one is at least one typo.
+	pub required: usize,
+}
+
+impl Candidates {
+	pub fn new(pattern: &str) -> Self {
+		let pattern = pattern_chars(pattern);
+
+		let mut chars = pattern.iter().copied().filter(char::is_ascii).collect::<Vec<_>>();
+		chars.sort_unstable();
+		chars.dedup();
+
+		let required = chars.len().saturating_sub(max_typos(pattern.len()));
+
+		Self { chars, required }
+	}
+
+	pub fn could_match(&self, text: &str) -> bool {
+		let found = self.chars.iter()
+			.filter(|&&c| text.bytes().any(|v| v.to_ascii_lowercase() == c as u8))
+			.count();
+
+		found >= self.required
+	}
+}
+
+
+pub struct FuzzyMatch {
+	pub score: i32,
+	/// Byte offset of each matched character in the text.
+	pub positions: Vec<usize>,
+}
+
+/// None if more pattern characters are missing than the typos allowed for its length.
+pub fn fuzzy_match(pattern: &str, text: &str) -> Option<FuzzyMatch> {
+	let pattern = pattern_chars(pattern);
+
+	if pattern.is_empty() {
+		return None;
+	}
+
+	let text = text.char_indices().take(MAX_TEXT_CHARS).collect::<Vec<_>>();
+	let lower = text.iter().map(|&(_, v)| v.to_lowercase().next().unwrap_or(v)).collect::<Vec<_>>();
+
+	let max_typos = max_typos(pattern.len());
+
+	// Cheap rejection before scoring. Greedily count the pattern characters found in order.
+	let mut found = 0;
+	let mut chars = lower.iter();
+
+	for c in &pattern {
+		// A missing character is skipped as a typo without using up the rest of the text.
+		let mut rest = chars.clone();
+
+		if rest.any(|v| v == c) {
+			found += 1;
+			chars = rest;
+		}
+	}
+
+	if found + max_typos < pattern.len() {
+		return None;
+	}
+
+	let bonuses = (0..text.len()).map(|index| bonus(&text, index)).collect::<Vec<_>>();
+
+	let (score, positions) = best_alignment(&pattern, &lower, &bonuses, max_typos)?;
+
+	Some(FuzzyMatch {
+		score,
+		positions: positions.into_iter().map(|v| text[v].0).collect(),
+	})
+}
+
+/// Lowercase without whitespace.
+fn pattern_chars(pattern: &str) -> Vec<char> {
+	pattern.chars()
+		.filter(|v| !v.is_whitespace())
+		.flat_map(char::to_lowercase)
+		.take(MAX_PATTERN_CHARS)
+		.collect()
+}
+
+/// One typo for every 4 characters, up to 2.
+fn max_typos(pattern_len: usize) -> usize {
+	(pattern_len / 4).min(2)
+}
+
+fn bonus(text: &[(usize, char)], index: usize) -> i32 {
+	let current = text[index].1;
+
+	let previous = match index.checked_sub(1) {
+		Some(v) => text[v].1,
+		None => return BONUS_BOUNDARY
+	};
+
+	if !previous.is_alphanumeric() && current.is_alphanumeric() {
+		BONUS_BOUNDARY
+	} else if previous.is_lowercase() && current.is_uppercase() {
+		BONUS_CAMEL_CASE
+	} else {
+		0
+	}
+}
+
+/// Highest scoring way to match the pattern. Returns the score and the index of each matched text character.
+///
+/// A row holds the best scores after some of the pattern characters, for each number of typos. Cell `j` of a
+///      row is the last match at text index `j - 1`, and `j == 0` means nothing has been matched yet. Only the
+///      current and next rows are kept, along with where each reached cell came from for walking back.
+fn best_alignment(pattern: &[char], text: &[char], bonuses: &[i32], max_typos: usize) -> Option<(i32, Vec<usize>)> {
+	let columns = text.len() + 1;
+	let levels = max_typos + 1;
+
+	let cell = |typos: usize, j: usize| typos * columns + j;
+
+	let mut current = vec![None::<i32>; levels * columns];
+	let mut next = current.clone();
+
+	// Previous cell of each cell in `next`.
+	let mut next_parents = vec![0usize; levels * columns];
+	// For each pattern character, the reached cells of the row after it and their previous cell. Sorted by cell.
+	let mut parents: Vec<Vec<(usize, usize)>> = Vec::with_capacity(pattern.len());
+
+	current[cell(0, 0)] = Some(0);
+
+	for &c in pattern {
+		next.fill(None);
+
+		for typos in 0..levels {
+			// Skip the pattern character as a typo.
+			if typos < max_typos {
+				for j in 0..columns {
+					if let Some(score) = current[cell(typos, j)] {
+						let target = cell(typos + 1, j);
+
+						if next[target].map(|v| score - SCORE_TYPO > v).unwrap_or(true) {
+							next[target] = Some(score - SCORE_TYPO);
+							next_parents[target] = cell(typos, j);
+						}
+					}
+				}
+			}
+
+			// Nothing matched yet (or only typos). The leading gap isn't penalised.
+			let leading = current[cell(typos, 0)];
+			// Best match at least two characters back, including the gap penalty.
+			let mut running: Option<(i32, usize)> = None;
+
+			for q in 1..columns {
+				if text[q - 1] == c {
+					let mut best = running;
+
+					let mut consider = |score: i32, parent: usize| {
+						if best.map(|(v, _)| score > v).unwrap_or(true) {
+							best = Some((score, parent));
+						}
+					};
+
+					if let Some(score) = leading {
+						consider(score, 0);
+					}
+
+					// Right after the last match.
+					if q > 1 {
+						if let Some(score) = current[cell(typos, q - 1)] {
+							consider(score + BONUS_CONSECUTIVE, q - 1);
+						}
+					}
+
+					if let Some((score, parent)) = best {
+						let score = score + SCORE_MATCH + bonuses[q - 1];
+						let target = cell(typos, q);
+
+						if next[target].map(|v| score > v).unwrap_or(true) {
+							next[target] = Some(score);
+							next_parents[target] = cell(typos, parent);
+						}
+					}
+				}
+
+				// Ready for q + 1, where a match at q - 1 is one character away.
+				running = running.map(|(v, p)| (v - SCORE_GAP, p));
+
+				if q > 1 {
+					if let Some(score) = current[cell(typos, q - 1)] {
+						if running.map(|(v, _)| score - SCORE_GAP > v).unwrap_or(true) {
+							running = Some((score - SCORE_GAP, q - 1));
+						}
+					}
+				}
+			}
+		}
+
+		parents.push(next.iter().enumerate().filter(|(_, v)| v.is_some()).map(|(k, _)| (k, next_parents[k])).collect());
+
+		std::mem::swap(&mut current, &mut next);
+	}
+
+	// Best end state.
+	let (mut at, score) = current.iter().enumerate()
+		.filter_map(|(k, v)| Some((k, (*v)?)))
+		.fold(None, |best: Option<(usize, i32)>, (k, score)| match best {
+			Some((_, v)) if v >= score => best,
+			_ => Some((k, score))
+		})?;
+
+	// Walk back through the parents for the matched positions.
+	let mut positions = Vec::new();
+
+	for row in parents.iter().rev() {
+		let parent = row[row.binary_search_by_key(&at, |v| v.0).ok()?].1;
+
+		// Matched rather than skipped.
+		if parent / columns == at / columns {
+			positions.push(at % columns - 1);
+		}
+
+		at = parent;
+	}
+
+	// Everything was a typo.
+	if positions.is_empty() {
+		return None;
+	}
+
+	positions.reverse();
+
+	Some((score, positions))
+}
+
+
+#[cfg(test)]
+mod tests {
+	use chrono::Utc;
+
+	use super::*;
+	use super::super::{SNIPPET_MATCH_END, SNIPPET_MATCH_START};
+
+	fn item(data_id: usize, text: &str) -> ReturnedItem {
+		ReturnedItem {
+			data_id,
+			value: ReturnedItemType::Text(text.to_string()),
+			is_favorite: false,
+			is_masked: false,
+			source_app: None,
+			snippet: None,
+			tags: Vec::new(),
+			title: None,
+			note: None,
+			is_pinned: false,
+			recent_id: data_id,
+			timestamp: Utc::now(),
+		}
+	}
+
+	/// Text of each ranked item.
+	fn ranked(texts: &[&str], pattern: &str) -> Vec<String> {
+		let items = texts.iter().enumerate().map(|(index, text)| item(index, text)).collect();
+
+		rank(items, pattern).into_iter()
+			.map(|v| match v.value {
+				ReturnedItemType::Text(text) => text,
+				_ => unreachable!()
+			})
+			.collect()
+	}
+
+	fn score(pattern: &str, text: &str) -> i32 {
+		fuzzy_match(pattern, text).unwrap().score
+	}
+
+
+	#[test]
+	fn ranking() {
+		let texts = ["u_s_e_r", "uxsxexr", "causer", "username", "user"];
+
+		// Exact and prefix matches score the same, so they stay in their original order. Like fzf, starting words
+		//      counts for more than being consecutive.
+		assert_eq!(ranked(&texts, "user"), vec!["username", "user", "u_s_e_r", "causer", "uxsxexr"]);
+
+		assert_eq!(score("user", "user"), score("user", "username"));
+		assert!(score("user", "username") > score("user", "u_s_e_r"));
+		assert!(score("user", "u_s_e_r") > score("user", "causer"));
+		assert!(score("user", "causer") > score("user", "uxsxexr"));
+	}
+
+	#[test]
+	fn ignores_case_and_whitespace() {
+		assert_eq!(score("User Name", "username"), score("username", "username"));
+		assert!(fuzzy_match("UN", "getUserName").is_some());
+	}
+
+	#[test]
+	fn camel_case_and_word_starts() {
+		assert!(score("un", "userName") > score("un", "username"));
+		assert!(score("sn", "set_name") > score("sn", "seen"));
+	}
+
+	#[test]
+	fn one_typo() {
+		// x isn't in the text.
+		let found = fuzzy_match("clipbxoard", "clipboard").unwrap();
+
+		assert!(found.score < score("clipboard", "clipboard"));
+		assert_eq!(found.positions, (0..9).collect::<Vec<_>>());
+
+		// Transposed.
+		assert!(fuzzy_match("clipbaord", "clipboard").is_some());
+	}
+
+	#[test]
+	fn too_many_typos() {
+		// Under 4 characters allows none.
+		assert!(fuzzy_match("abd", "abc").is_none());
+		assert!(fuzzy_match("abcd", "abc").is_some());
+
+		// Never more than 2.
+		assert!(fuzzy_match("clipbxxoard", "clipboard").is_some());
+		assert!(fuzzy_match("clipbxxxoard", "clipboard").is_none());
+
+		assert!(fuzzy_match("zzzz", "clipboard").is_none());
+		assert!(fuzzy_match("", "clipboard").is_none());
+		assert!(fuzzy_match("   ", "clipboard").is_none());
+	}
+
+	#[test]
+	fn multibyte_positions() {
+		let text = "héllo wörld";
+
+		let found = fuzzy_match("hwö", text).unwrap();
+		assert_eq!(found.positions, vec![0, 7, 8]);
+
+		for position in found.positions {
+			assert!(text.is_char_boundary(position));
+		}
+
+		let snippet = rank(vec![item(1, text)], "wö").remove(0).snippet;
+		assert_eq!(snippet, Some(format!("héllo {}wö{}rld", SNIPPET_MATCH_START, SNIPPET_MATCH_END)));
+	}
+
+	#[test]
+	fn masked_text_has_no_snippet() {
+		let mut masked = item(1, "password");
+		masked.is_masked = true;
+
+		let ranked = rank(vec![masked], "pass");
+
+		assert_eq!(ranked.len(), 1);
+		assert_eq!(ranked[0].snippet, None);
+	}
+
+	#[test]
+	fn candidates() {
+		// 9 characters allow 2 typos.
+		let candidates = Candidates::new("Clip Board");
+
+		assert_eq!(candidates.chars, vec!['a', 'b', 'c', 'd', 'i', 'l', 'o', 'p', 'r']);
+		assert_eq!(candidates.required, 7);
+
+		assert!(candidates.could_match("CLIPBOARD"));
+		assert!(candidates.could_match("clipboa"));
+		assert!(!candidates.could_match("clipbo"));
+
+		// Only ASCII is checked.
+		let candidates = Candidates::new("wö");
+
+		assert_eq!(candidates.chars, vec!['w']);
+		assert!(candidates.could_match("WORLD"));
+	}
+
+	#[test]
+	fn end_of_long_text() {
+		let text = format!("{}clipboard", "-".repeat(MAX_TEXT_CHARS - 9));
+
+		let found = fuzzy_match("clipbxoard", &text).unwrap();
+
+		assert_eq!(found.positions, (MAX_TEXT_CHARS - 9..MAX_TEXT_CHARS).collect::<Vec<_>>());
+
+		// Past the part which is matched against.
+		assert!(fuzzy_match("clipboard", &format!("{}clipboard", "-".repeat(MAX_TEXT_CHARS))).is_none());
+	}
+}
@@ -0,0 +1,984 @@
+use std::cmp::Reverse;
+use std::collections::HashMap;
+use std::sync::{Mutex, MutexGuard};
+
+use anyhow::{Result, anyhow, bail};
+use chrono::{Utc, TimeZone};
+use regex::Regex;
+
+use crate::config::{Config, SensitiveAction};
+
+use super::{CopiedData, CopiedFile, CopiedFormat, CopySource, DataRevision, DataUsage, LastCopied, ReturnedItem, ReturnedItemType, SearchTerm, StorageQuery, Store, Tag};
+use super::{check_edited_text, expires_at, fuzzy, hash_data, hash_files, marked_snippet, optional_text, privacy_action, regex_snippet, should_record_recopy};
+
+
+/// Store which is never written to disk. Behaves like [`super::SqliteStore`] for testing against.
+#[derive(Default)]
+pub struct MemoryStore(Mutex<Tables>);
+
+#[derive(Default)]
+struct Tables {
+	data: Vec<CopiedData>,
+	recent: Vec<LastCopied>,
+	files: Vec<CopiedFile>,
+	formats: Vec<CopiedFormat>,
+	images: HashMap<usize, Vec<u8>>,
+	/// (id, name)
+	tags: Vec<(usize, String)>,
+	/// (data_id, tag_id)
+	data_tags: Vec<(usize, usize)>,
+	revisions: Vec<DataRevision>,
+
+	/// Last id used in any table. Like SQLite ids are never reused.
+	last_id: usize,
+}
+
+impl Tables {
+	fn next_id(&mut self) -> usize {
+		self.last_id += 1;
+		self.last_id
+	}
+
+	fn get_data_from_hash(&self, hash: &str) -> Option<&CopiedData> {
+		self.data.iter().find(|v| v.hash == hash)
+	}
+
+	fn insert_data(&mut self, mut data: CopiedData) -> usize {
+		data.id = self.next_id();
+
+		let id = data.id;
+		self.data.push(data);
+
+		id
+	}
+
+	fn insert_recent(&mut self, data_id: usize, date: usize, source: &CopySource) {
+		let id = self.next_id();
+
+		self.recent.push(LastCopied {
+			id,
+			row_id: data_id,
+			date,
+			selection: source.selection,
+			source_app: source.app.clone()
+		});
+	}
+
+	/// Called when something we've already stored is copied again.
+	fn recopied(&mut self, data_id: usize, source: &CopySource) {
+		let current_date = Utc::now().timestamp_millis() as usize;
+
+		// Copying something in the trash takes it back out.
+		if let Some(data) = self.data.iter_mut().find(|v| v.id == data_id && v.deleted_at.is_some()) {
+			data.deleted_at = None;
+			self.insert_recent(data_id, current_date, source);
+			return;
+		}
+
+		let previous_date = match self.recent.iter().rev().find(|v| v.row_id == data_id) {
+			Some(v) => v.date,
+			None => return
+		};
+
+		let recent_items_after_previous = self.recent.iter().filter(|v| v.date > previous_date).count();
+
+		if should_record_recopy(previous_date, current_date, recent_items_after_previous) {
+			self.insert_recent(data_id, current_date, source);
+		}
+	}
+
+	fn get_tag_id(&self, name: &str) -> Option<usize> {
+		let name = name.trim();
+
+		self.tags.iter()
+			.find(|(_, v)| v.eq_ignore_ascii_case(name))
+			.map(|&(id, _)| id)
+	}
+
+	/// Sorted ignoring case like SQLite's NOCASE.
+	fn data_tag_names(&self, data_id: usize) -> Vec<String> {
+		let mut names = self.data_tags.iter()
+			.filter(|&&(v, _)| v == data_id)
+			.filter_map(|&(_, tag_id)| self.tags.iter().find(|&&(id, _)| id == tag_id))
+			.map(|(_, name)| name.clone())
+			.collect::<Vec<_>>();
+
+		names.sort_by_key(|v| v.to_ascii_lowercase());
+
+		names
+	}
+
+	/// Starred data in the order it was arranged. Data which was never arranged goes last like NULLs in SQLite.
+	fn favorites(&self) -> Vec<ReturnedItem> {
+		let mut items = self.distinct_items(|_, data| data.is_starred);
+
+		items.sort_by_key(|item| {
+			let position = self.data.iter().find(|v| v.id == item.data_id).and_then(|v| v.star_position);
+			(position.is_none(), position)
+		});
+
+		items
+	}
+
+	/// Newest recent of each data which matches.
+	fn distinct_items(&self, filter: impl Fn(&LastCopied, &CopiedData) -> bool) -> Vec<ReturnedItem> {
+		distinct(self.returned_items(filter))
+	}
+
+	/// Recents joined with their data, newest first. Skips the trash.
+	fn returned_items<'a>(&'a self, filter: impl Fn(&LastCopied, &CopiedData) -> bool + 'a) -> impl Iterator<Item = ReturnedItem> + 'a {
+		self.all_items(move |recent, data| data.deleted_at.is_none() && filter(recent, data))
+	}
+
+	/// Recents joined with their data, newest first. Including the trash.
+	fn all_items<'a>(&'a self, filter: impl Fn(&LastCopied, &CopiedData) -> bool + 'a) -> impl Iterator<Item = ReturnedItem> + 'a {
+		self.recent.iter()
+			.rev()
+			.filter_map(move |recent| {
+				let data = self.data.iter().find(|v| v.id == recent.row_id)?;
+
+				if !filter(recent, data) {
+					return None;
+				}
+
+				Some(ReturnedItem {
+					data_id: data.id,
+					value: ReturnedItemType::from_sql(data.type_of as usize, data.text_data.clone(), data.image_thumb_data.clone()),
+					is_favorite: data.is_starred,
+					is_masked: data.is_masked,
+					source_app: recent.source_app.clone(),
+					snippet: None,
+					tags: self.data_tag_names(data.id),
+					title: data.title.clone(),
+					note: data.note.clone(),
+					is_pinned: data.pinned_at.is_some(),
+
+					recent_id: recent.id,
+					timestamp: Utc.timestamp_millis_opt(recent.date as i64).single()?,
+				})
+			})
+	}
+}
+
+
+impl MemoryStore {
+	fn tables(&self) -> MutexGuard<'_, Tables> {
+		// A panic while holding the lock can't leave the tables half updated in a way that matters for testing.
+		self.0.lock().unwrap_or_else(|e| e.into_inner())
+	}
+
+	/// Move every recent of the data back to `date`.
+	#[cfg(test)]
+	pub(super) fn set_copied_at(&self, data_id: usize, date: usize) {
+		for recent in self.tables().recent.iter_mut().filter(|v| v.row_id == data_id) {
+			recent.date = date;
+		}
+	}
+}
+
+impl Store for MemoryStore {
+	fn query(&self, value: StorageQuery) -> Result<Vec<ReturnedItem>> {
+		self.delete_expired()?;
+
+		let tables = self.tables();
+
+		match value {
+			StorageQuery::Favorites => Ok(tables.favorites()),
+
+			StorageQuery::Pinned => {
+				let mut items = tables.distinct_items(|_, data| data.pinned_at.is_some());
+
+				items.sort_by_key(|item| Reverse(tables.data.iter().find(|v| v.id == item.data_id).and_then(|v| v.pinned_at)));
+
+				Ok(items)
+			}
+
+			StorageQuery::Tag(name) => {
+				let tag_id = tables.get_tag_id(&name);
+
+				Ok(tables.distinct_items(|_, data| tag_id.map(|tag_id| tables.data_tags.contains(&(data.id, tag_id))).unwrap_or_default()))
+			}
+
+			StorageQuery::Trash => Ok(distinct(tables.all_items(|_, data| data.deleted_at.is_some()))),
+
+			StorageQuery::Recent { limit, skip } => Ok(tables.returned_items(|_, _| true).skip(skip).take(limit).collect()),
+
+			StorageQuery::Search { value, source_app } => {
+				// Like the full-text index every word has to be found, ignoring ASCII case.
+				let value = value.to_ascii_lowercase();
+				let words = value.split_whitespace().collect::<Vec<_>>();
+
+				let mut items = tables.distinct_items(|recent, data| {
+					let text = searched_text(data);
+
+					words.iter().all(|word| text.contains(word))
+						&& (source_app.is_none() || recent.source_app == source_app)
+				});
+
+				if let Some(word) = words.first() {
+					highlight_items(&mut items, word);
+				}
+
+				Ok(items)
+			}
+
+			StorageQuery::Advanced(query) => {
+				let mut items = tables.distinct_items(|recent, data| query.terms.iter().all(|term| term_matches(term, recent, data)));
+
+				let first_word = query.terms.iter().find_map(|term| match term {
+					SearchTerm::Word(v) | SearchTerm::Phrase(v) => Some(v.to_ascii_lowercase()),
+					_ => None
+				});
+
+				if let Some(word) = first_word {
+					highlight_items(&mut items, &word);
+				}
+
+				Ok(items)
+			}
+
+			StorageQuery::Fuzzy { pattern, source_app } => {
+				let candidates = fuzzy::Candidates::new(&pattern);
+
+				let mut items = tables.distinct_items(|recent, data| {
+					!data.is_masked
+						&& data.text_data.as_ref().map(|v| candidates.could_match(v)).unwrap_or_default()
+						&& (source_app.is_none() || recent.source_app == source_app)
+				});
+
+				items.truncate(fuzzy::CANDIDATE_LIMIT);
+
+				Ok(fuzzy::rank(items, &pattern))
+			}
+
+			StorageQuery::Regex { pattern, source_app } => {
+				let regex = Regex::new(&pattern)?;
+
+				let mut items = tables.distinct_items(|recent, data| {
+					!data.is_masked
+						&& data.text_data.as_ref().map(|v| regex.is_match(v)).unwrap_or_default()
+						&& (source_app.is_none() || recent.source_app == source_app)
+				});
+
+				for item in &mut items {
+					if let (ReturnedItemType::Text(text), false) = (&item.value, item.is_masked) {
+						item.snippet = regex_snippet(&regex, text);
+					}
+				}
+
+				Ok(items)
+			}
+		}
+	}
+
+	fn add_text(&self, text_data: String, html_data: Option<String>, source: &CopySource, config: &Config) -> Result<Option<usize>> {
+		if text_data.len() > config.stores.text.max_size * 1000 * 1000 { // B -> KB -> MB
+			return Ok(None);
+		}
+
+		let action = privacy_action(&text_data, config);
+
+		if action == Some(SensitiveAction::Drop) {
+			return Ok(None);
+		}
+
+		let is_masked = action == Some(SensitiveAction::Mask);
+		let expires_at = expires_at(action, config);
+
+		// Masked text is only kept so it can be pasted. Its HTML would show it.
+		let html_data = html_data.filter(|_| !is_masked);
+
+		let hash = hash_data(text_data.as_bytes());
+
+		let mut tables = self.tables();
+
+		if let Some(data_id) = tables.get_data_from_hash(&hash).map(|v| v.id) {
+			tables.recopied(data_id, source);
+
+			// Rules may have changed since it was stored.
+			if action.is_some() {
+				if let Some(data) = tables.data.iter_mut().find(|v| v.id == data_id) {
+					data.is_masked = is_masked;
+					data.expires_at = expires_at;
+
+					if is_masked {
+						data.html_size = None;
+						data.html_data = None;
+					}
+				}
+			}
+
+			if is_masked {
+				tables.formats.retain(|v| v.row_id != data_id);
+			}
+
+			Ok(Some(data_id))
+		} else {
+			let data_id = tables.insert_data(CopiedData {
+				id: 0,
+				hash,
+				is_starred: false,
+				type_of: 0,
+				text_size: Some(text_data.len()),
+				text_data: Some(text_data),
+				html_size: html_data.as_ref().map(|v| v.len()),
+				html_data,
+				image_size: None,
+				image_data: None,
+				image_thumb_size: None,
+				image_thumb_data: None,
+				is_masked,
+				expires_at,
+				deleted_at: None,
+				title: None,
+				note: None,
+				star_position: None,
+				pinned_at: None,
+				formats: Vec::new(),
+			});
+
+			tables.insert_recent(data_id, Utc::now().timestamp_millis() as usize, source);
+
+			Ok(Some(data_id))
+		}
+	}
+
+	fn add_image(&self, image_data: Vec<u8>, image_thumb_data: Option<Vec<u8>>, source: &CopySource, config: &Config) -> Result<Option<usize>> {
+		if image_data.len() > config.stores.image.max_size * 1000 * 1000 { // B -> KB -> MB
+			return Ok(None);
+		}
+
+		let hash = hash_data(&image_data);
+
+		let mut tables = self.tables();
+
+		if let Some(data_id) = tables.get_data_from_hash(&hash).map(|v| v.id) {
+			tables.recopied(data_id, source);
+
+			Ok(Some(data_id))
+		} else {
+			let data_id = tables.insert_data(CopiedData {
+				id: 0,
+				hash,
+				is_starred: false,
+				type_of: 1,
+				text_size: None,
+				text_data: None,
+				html_size: None,
+				html_data: None,
+				image_size: Some(image_data.len()),
+				image_data: None,
+				image_thumb_size: image_thumb_data.as_ref().map(|v| v.len()),
+				image_thumb_data,
+				is_masked: false,
+				expires_at: None,
+				deleted_at: None,
+				title: None,
+				note: None,
+				star_position: None,
+				pinned_at: None,
+				formats: Vec::new(),
+			});
+
+			tables.images.insert(data_id, image_data);
+			tables.insert_recent(data_id, Utc::now().timestamp_millis() as usize, source);
+
+			Ok(Some(data_id))
+		}
+	}
+
+	fn add_files(&self, files: Vec<CopiedFile>, source: &CopySource, config: &Config) -> Result<Option<usize>> {
+		// Paths are stored as the text so they show up in the list and in search.
+		let text_data = files.iter().map(|v| v.path.as_str()).collect::<Vec<_>>().join("\n");
+
+		if text_data.len() > config.stores.text.max_size * 1000 * 1000 { // B -> KB -> MB
+			return Ok(None);
+		}
+
+		let hash = hash_files(&text_data);
+
+		let mut tables = self.tables();
+
+		if let Some(data_id) = tables.get_data_from_hash(&hash).map(|v| v.id) {
+			tables.recopied(data_id, source);
+
+			Ok(Some(data_id))
+		} else {
+			let data_id = tables.insert_data(CopiedData {
+				id: 0,
+				hash,
+				is_starred: false,
+				type_of: 2,
+				text_size: Some(text_data.len()),
+				text_data: Some(text_data),
+				html_size: None,
+				html_data: None,
+				image_size: None,
+				image_data: None,
+				image_thumb_size: None,
+				image_thumb_data: None,
+				is_masked: false,
+				expires_at: None,
+				deleted_at: None,
+				title: None,
+				note: None,
+				star_position: None,
+				pinned_at: None,
+				formats: Vec::new(),
+			});
+
+			for mut file in files {
+				file.id = tables.next_id();
+				file.row_id = data_id;
+
+				tables.files.push(file);
+			}
+
+			tables.insert_recent(data_id, Utc::now().timestamp_millis() as usize, source);
+
+			Ok(Some(data_id))
+		}
+	}
+
+	fn set_formats(&self, data_id: usize, formats: &[(String, Vec<u8>)]) -> Result<()> {
+		let mut tables = self.tables();
+
+		tables.formats.retain(|v| v.row_id != data_id);
+
+		// Not kept for masked text, like its HTML.
+		if tables.data.iter().any(|v| v.id == data_id && v.is_masked) {
+			return Ok(());
+		}
+
+		for (format, format_data) in formats {
+			let id = tables.next_id();
+
+			tables.formats.push(CopiedFormat {
+				id,
+				row_id: data_id,
+				format: format.clone(),
+				format_size: format_data.len(),
+				format_data: format_data.clone()
+			});
+		}
+
+		Ok(())
+	}
+
+	fn set_favorite(&self, index: usize, value: bool) -> Result<usize> {
+		let mut tables = self.tables();
+
+		let first_position = tables.data.iter()
+			.filter(|v| v.is_starred)
+			.filter_map(|v| v.star_position)
+			.min()
+			.unwrap_or_default() - 1;
+
+		match tables.data.iter_mut().find(|v| v.id == index) {
+			Some(data) => {
+				if !value {
+					data.star_position = None;
+				} else if !data.is_starred {
+					data.star_position = Some(first_position);
+				}
+
+				data.is_starred = value;
+				Ok(1)
+			}
+
+			None => Ok(0)
+		}
+	}
+
+	fn move_favorite(&self, data_id: usize, index: usize) -> Result<usize> {
+		let mut tables = self.tables();
+
+		let mut ids = tables.favorites().into_iter().map(|v| v.data_id).collect::<Vec<_>>();
+
+		let from = match ids.iter().position(|&v| v == data_id) {
+			Some(v) => v,
+			None => return Ok(0)
+		};
+
+		ids.remove(from);
+		ids.insert(index.min(ids.len()), data_id);
+
+		for (position, id) in ids.into_iter().enumerate() {
+			if let Some(data) = tables.data.iter_mut().find(|v| v.id == id) {
+				data.star_position = Some(position as i64);
+			}
+		}
+
+		Ok(1)
+	}
+
+	fn set_pinned(&self, data_id: usize, value: bool) -> Result<usize> {
+		let mut tables = self.tables();
+
+		match tables.data.iter_mut().find(|v| v.id == data_id) {
+			Some(data) => {
+				data.pinned_at = if value { Some(Utc::now().timestamp_millis() as usize) } else { None };
+				Ok(1)
+			}
+
+			None => Ok(0)
+		}
+	}
+
+	fn set_title(&self, data_id: usize, title: &str) -> Result<usize> {
+		let mut tables = self.tables();
+
+		match tables.data.iter_mut().find(|v| v.id == data_id) {
+			Some(data) => {
+				data.title = optional_text(title).map(String::from);
+				Ok(1)
+			}
+
+			None => Ok(0)
+		}
+	}
+
+	fn set_note(&self, data_id: usize, note: &str) -> Result<usize> {
+		let mut tables = self.tables();
+
+		match tables.data.iter_mut().find(|v| v.id == data_id) {
+			Some(data) => {
+				data.note = optional_text(note).map(String::from);
+				Ok(1)
+			}
+
+			None => Ok(0)
+		}
+	}
+
+	fn edit_text(&self, data_id: usize, text_data: String, html_data: Option<String>, config: &Config) -> Result<usize> {
+		let data = self.get_data(data_id)?;
+
+		if data.type_of != 0 {
+			bail!("Only text can be edited");
+		}
+
+		let action = check_edited_text(&text_data, config)?;
+
+		let html_data = html_data.filter(|_| action != Some(SensitiveAction::Mask));
+
+		let hash = hash_data(text_data.as_bytes());
+
+		if hash == data.hash && html_data == data.html_data {
+			return Ok(data_id);
+		}
+
+		let existing_id = {
+			let mut tables = self.tables();
+
+			let id = tables.next_id();
+
+			tables.revisions.push(DataRevision {
+				id,
+				data_id,
+				date: Utc::now().timestamp_millis() as usize,
+				text_data: data.text_data.clone(),
+				html_data: data.html_data.clone()
+			});
+
+			let existing_id = tables.get_data_from_hash(&hash).map(|v| v.id).filter(|&v| v != data_id);
+
+			match existing_id {
+				// Merge into the data which already has the text.
+				Some(existing_id) => {
+					for recent in tables.recent.iter_mut().filter(|v| v.row_id == data_id) {
+						recent.row_id = existing_id;
+					}
+
+					let tag_ids = tables.data_tags.iter()
+						.filter(|&&(v, _)| v == data_id)
+						.map(|&(_, tag_id)| tag_id)
+						.collect::<Vec<_>>();
+
+					for tag_id in tag_ids {
+						if !tables.data_tags.contains(&(existing_id, tag_id)) {
+							tables.data_tags.push((existing_id, tag_id));
+						}
+					}
+
+					for revision in tables.revisions.iter_mut().filter(|v| v.data_id == data_id) {
+						revision.data_id = existing_id;
+					}
+
+					let existing = tables.data.iter_mut().find(|v| v.id == existing_id).unwrap();
+
+					if !existing.is_starred {
+						existing.star_position = data.star_position;
+					}
+
+					existing.is_starred |= data.is_starred;
+					existing.pinned_at = existing.pinned_at.or(data.pinned_at);
+					existing.title = existing.title.take().or(data.title);
+					existing.note = existing.note.take().or(data.note);
+					existing.deleted_at = None;
+				}
+
+				None => {
+					let stored = tables.data.iter_mut().find(|v| v.id == data_id).unwrap();
+
+					stored.hash = hash;
+					stored.text_size = Some(text_data.len());
+					stored.text_data = Some(text_data);
+					stored.html_size = html_data.as_deref().map(|v| v.len());
+					stored.html_data = html_data;
+					stored.is_masked = action == Some(SensitiveAction::Mask);
+					stored.expires_at = expires_at(action, config);
+
+					// They were copied alongside the old text.
+					tables.formats.retain(|v| v.row_id != data_id);
+				}
+			}
+
+			existing_id
+		};
+
+		match existing_id {
+			Some(existing_id) => {
+				self.purge(data_id)?;
+				Ok(existing_id)
+			}
+
+			None => Ok(data_id)
+		}
+	}
+
+	fn get_revisions(&self, data_id: usize) -> Result<Vec<DataRevision>> {
+		let mut revisions = self.tables().revisions.iter()
+			.filter(|v| v.data_id == data_id)
+			.cloned()
+			.collect::<Vec<_>>();
+
+		revisions.sort_by_key(|v| Reverse((v.date, v.id)));
+
+		Ok(revisions)
+	}
+
+	fn create_tag(&self, name: &str) -> Result<usize> {
+		let name = Tag::parse_name(name)?;
+
+		let mut tables = self.tables();
+
+		if let Some(id) = tables.get_tag_id(name) {
+			return Ok(id);
+		}
+
+		let id = tables.next_id();
+		tables.tags.push((id, name.to_string()));
+
+		Ok(id)
+	}
+
+	fn add_tag(&self, data_id: usize, name: &str) -> Result<usize> {
+		let tag_id = self.create_tag(name)?;
+
+		let mut tables = self.tables();
+
+		if tables.data_tags.contains(&(data_id, tag_id)) {
+			Ok(0)
+		} else {
+			tables.data_tags.push((data_id, tag_id));
+			Ok(1)
+		}
+	}
+
+	fn remove_tag(&self, data_id: usize, name: &str) -> Result<usize> {
+		let mut tables = self.tables();
+
+		let tag_id = match tables.get_tag_id(name) {
+			Some(v) => v,
+			None => return Ok(0)
+		};
+
+		let count = tables.data_tags.len();
+		tables.data_tags.retain(|&v| v != (data_id, tag_id));
+
+		Ok(count - tables.data_tags.len())
+	}
+
+	fn delete_tag(&self, name: &str) -> Result<usize> {
+		let mut tables = self.tables();
+
+		let tag_id = match tables.get_tag_id(name) {
+			Some(v) => v,
+			None => return Ok(0)
+		};
+
+		tables.data_tags.retain(|&(_, v)| v != tag_id);
+		tables.tags.retain(|&(id, _)| id != tag_id);
+
+		Ok(1)
+	}
+
+	fn get_tags(&self) -> Result<Vec<Tag>> {
+		let tables = self.tables();
+
+		let mut tags = tables.tags.iter()
+			.map(|(id, name)| Tag {
+				id: *id,
+				name: name.clone(),
+				data_count: tables.data_tags.iter()
+					.filter(|&&(data_id, tag_id)| tag_id == *id && tables.data.iter().any(|v| v.id == data_id && v.deleted_at.is_none()))
+					.count(),
+			})
+			.collect::<Vec<_>>();
+
+		tags.sort_by_key(|v| v.name.to_ascii_lowercase());
+
+		Ok(tags)
+	}
+
+	fn get_data_tags(&self, data_id: usize) -> Result<Vec<String>> {
+		Ok(self.tables().data_tag_names(data_id))
+	}
+
+	fn delete(&self, index: usize) -> Result<usize> {
+		let mut tables = self.tables();
+
+		match tables.data.iter_mut().find(|v| v.id == index && v.deleted_at.is_none()) {
+			Some(data) => {
+				data.deleted_at = Some(Utc::now().timestamp_millis() as usize);
+				Ok(1)
+			}
+
+			None => Ok(0)
+		}
+	}
+
+	fn restore(&self, index: usize) -> Result<usize> {
+		let mut tables = self.tables();
+
+		match tables.data.iter_mut().find(|v| v.id == index && v.deleted_at.is_some()) {
+			Some(data) => {
+				data.deleted_at = None;
+				Ok(1)
+			}
+
+			None => Ok(0)
+		}
+	}
+
+	fn purge(&self, index: usize) -> Result<usize> {
+		let mut tables = self.tables();
+
+		let count = tables.data.len();
+		tables.data.retain(|v| v.id != index);
+
+		if tables.data.len() != count {
+			tables.files.retain(|v| v.row_id != index);
+			tables.formats.retain(|v| v.row_id != index);
+			tables.images.remove(&index);
+			tables.data_tags.retain(|&(data_id, _)| data_id != index);
+			tables.revisions.retain(|v| v.data_id != index);
+
+			let count = tables.recent.len();
+			tables.recent.retain(|v| v.row_id != index);
+
+			Ok(count - tables.recent.len())
+		} else {
+			Ok(0)
+		}
+	}
+
+	fn delete_expired(&self) -> Result<usize> {
+		let now = Utc::now().timestamp_millis() as usize;
+
+		let ids = self.tables().data.iter()
+			.filter(|v| v.expires_at.map(|v| v <= now).unwrap_or_default())
+			.map(|v| v.id)
+			.collect::<Vec<_>>();
+
+		let mut deleted = 0;
+
+		for id in ids {
+			deleted += self.purge(id)?;
+		}
+
+		Ok(deleted)
+	}
+
+	fn purge_trash(&self, deleted_before: usize) -> Result<usize> {
+		let ids = self.tables().data.iter()
+			.filter(|v| v.deleted_at.map(|v| v <= deleted_before).unwrap_or_default())
+			.map(|v| v.id)
+			.collect::<Vec<_>>();
+
+		for &id in &ids {
+			self.purge(id)?;
+		}
+
+		Ok(ids.len())
+	}
+
+	fn clear_database(&self, force: bool) -> Result<usize> {
+		let mut tables = self.tables();
+
+		if !force {
+			let now = Utc::now().timestamp_millis() as usize;
+
+			let mut trashed = 0;
+
+			for data in tables.data.iter_mut().filter(|v| v.deleted_at.is_none()) {
+				data.deleted_at = Some(now);
+				trashed += 1;
+			}
+
+			return Ok(trashed);
+		}
+
+		let deleted = tables.data.len() + tables.recent.len();
+
+		tables.data.clear();
+		tables.recent.clear();
+		tables.files.clear();
+		tables.formats.clear();
+		tables.images.clear();
+		tables.tags.clear();
+		tables.data_tags.clear();
+		tables.revisions.clear();
+
+		Ok(deleted)
+	}
+
+	fn get_data_usage(&self) -> Result<Vec<DataUsage>> {
+		let tables = self.tables();
+
+		let mut usage = tables.data.iter()
+			.map(|data| {
+				let formats_size = tables.formats.iter()
+					.filter(|v| v.row_id == data.id)
+					.map(|v| v.format_size)
+					.sum::<usize>();
+
+				let files_size = tables.files.iter()
+					.filter(|v| v.row_id == data.id)
+					.filter_map(|v| v.file_data.as_ref().map(|v| v.len()))
+					.sum::<usize>();
+
+				let size = [data.text_size, data.html_size, data.image_size, data.image_thumb_size]
+					.into_iter()
+					.flatten()
+					.sum::<usize>();
+
+				DataUsage {
+					data_id: data.id,
+					is_starred: data.is_starred,
+					is_pinned: data.pinned_at.is_some(),
+					is_deleted: data.deleted_at.is_some(),
+					last_copied: tables.recent.iter().filter(|v| v.row_id == data.id).map(|v| v.date).max().unwrap_or_default(),
+					size: size + formats_size + files_size,
+				}
+			})
+			.collect::<Vec<_>>();
+
+		// Copied in the same millisecond goes by which was stored last.
+		usage.sort_by_key(|v| std::cmp::Reverse((v.last_copied, v.data_id)));
+
+		Ok(usage)
+	}
+
+	fn get_data(&self, data_id: usize) -> Result<CopiedData> {
+		let tables = self.tables();
+
+		let mut data = tables.data.iter()
+			.find(|v| v.id == data_id)
+			.cloned()
+			.ok_or_else(|| anyhow!("No data with id {}", data_id))?;
+
+		data.formats = tables.formats.iter()
+			.filter(|v| v.row_id == data_id)
+			.map(|v| v.format.clone())
+			.collect();
+
+		data.image_data = tables.images.get(&data_id).cloned();
+
+		Ok(data)
+	}
+
+	fn get_formats(&self, data_id: usize) -> Result<Vec<CopiedFormat>> {
+		Ok(self.tables().formats.iter().filter(|v| v.row_id == data_id).cloned().collect())
+	}
+
+	fn get_files(&self, data_id: usize) -> Result<Vec<CopiedFile>> {
+		Ok(self.tables().files.iter().filter(|v| v.row_id == data_id).cloned().collect())
+	}
+
+	fn get_image(&self, data_id: usize) -> Result<Vec<u8>> {
+		self.tables().images.get(&data_id)
+			.cloned()
+			.ok_or_else(|| anyhow!("No image with id {}", data_id))
+	}
+
+	fn get_source_apps(&self) -> Result<Vec<String>> {
+		let mut apps = self.tables().recent.iter()
+			.filter_map(|v| v.source_app.clone())
+			.collect::<Vec<_>>();
+
+		apps.sort();
+		apps.dedup();
+
+		Ok(apps)
+	}
+
+	fn count_the_recents_newer_than(&self, time: usize) -> Result<usize> {
+		Ok(self.tables().recent.iter().filter(|v| v.date > time).count())
+	}
+}
+
+
+/// Same conditions as `ItemQuery::search` except words match anywhere instead of as a prefix.
+/// Lowercase text, title and note like the full-text index.
+/// Masked text is left out so searching can't be used to find out what it hides.
+fn searched_text(data: &CopiedData) -> String {
+	[if data.is_masked { &None } else { &data.text_data }, &data.title, &data.note]
+		.into_iter()
+		.flatten()
+		.map(|v| v.to_ascii_lowercase())
+		.collect::<Vec<_>>()
+		.join("\n")
+}
+
+fn term_matches(term: &SearchTerm, recent: &LastCopied, data: &CopiedData) -> bool {
+	match term {
+		SearchTerm::Word(text) | SearchTerm::Phrase(text) => searched_text(data).contains(&text.to_ascii_lowercase()),
+
+		SearchTerm::Type(clip_type) => data.type_of == clip_type.type_of(),
+		SearchTerm::Starred(starred) => data.is_starred == *starred,
+		SearchTerm::App(app) => recent.source_app.as_deref().unwrap_or_default().eq_ignore_ascii_case(app),
+		SearchTerm::After(date) => recent.date >= *date,
+		SearchTerm::Before(date) => recent.date < *date,
+		SearchTerm::Size(comparison, size) => comparison.compare(data.image_size.or(data.text_size).unwrap_or_default(), *size),
+
+		SearchTerm::Not(term) => !term_matches(term, recent, data),
+	}
+}
+
+/// Newest of each data.
+fn distinct(items: impl Iterator<Item = ReturnedItem>) -> Vec<ReturnedItem> {
+	let mut found: Vec<ReturnedItem> = Vec::new();
+
+	for item in items {
+		if !found.iter().any(|v| v.data_id == item.data_id) {
+			found.push(item);
+		}
+	}
+
+	found
+}
+
+/// Mark the first match of the lowercase `value` like an FTS5 snippet.
+fn highlight_items(items: &mut [ReturnedItem], value: &str) {
+	for item in items {
+		if let (ReturnedItemType::Text(text), false) = (&item.value, item.is_masked) {
+			// ASCII lowercasing doesn't change byte offsets.
+			if let Some(start) = text.to_ascii_lowercase().find(value).filter(|_| !value.is_empty()) {
+				let range = start..start + value.len();
+				item.snippet = Some(marked_snippet(text, std::slice::from_ref(&range)));
+			}
+		}
+	}
+}
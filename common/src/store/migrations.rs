@@ -0,0 +1,568 @@
+use anyhow::{Result, bail};
+use rusqlite::{Connection, params, Transaction, TransactionBehavior};
+
+use super::hash_files;
+
+
+// Schema Migrations
+
+// PRAGMA user_version holds how many of the migrations below have been applied to the database.
+// Each migration runs in its own transaction along with the user_version update so a failed
+//      migration leaves the database at the previous version. The transaction takes the write lock up front,
+//      so when two processes migrate at once the second one waits and then finds nothing left to do.
+
+// Never change a released migration. Add a new one to the end of the list.
+
+// Migrations 1-5 were applied unversioned before this existed. They have to cope with
+//      the changes already being there.
+
+type Migration = fn(&Connection) -> Result<()>;
+
+static MIGRATIONS: [Migration; 14] = [
+	add_files_table,
+	add_recent_selection,
+	add_data_formats_table,
+	add_data_privacy,
+	add_recent_source_app,
+	move_images_out_of_data,
+	add_blobs_table,
+	add_data_search_index,
+	add_data_deleted_at,
+	add_tags_tables,
+	add_data_title_and_note,
+	add_data_revisions_table,
+	add_data_star_position_and_pinned_at,
+	rehash_copied_files,
+];
+
+
+/// Bring the database up to the newest schema version.
+pub fn migrate(conn: &Connection) -> Result<()> {
+	if schema_version(conn)? == MIGRATIONS.len() {
+		return Ok(());
+	}
+
+	loop {
+		// The tray and the app can start at the same time, so the version is read again under the write lock.
+		let tx = Transaction::new_unchecked(conn, TransactionBehavior::Immediate)?;
+
+		let version = schema_version(&tx)?;
+
+		if version > MIGRATIONS.len() {
+			bail!("Database schema version {} is newer than this version supports ({})", version, MIGRATIONS.len());
+		}
+
+		if version == MIGRATIONS.len() {
+			return Ok(());
+		}
+
+		log::info!(target: "clipboard_listener", "Migrating database to schema version {}", version + 1);
+
+		MIGRATIONS[version](&tx)?;
+
+		tx.pragma_update(None, "user_version", version + 1)?;
+		tx.commit()?;
+	}
+}
+
+pub fn schema_version(conn: &Connection) -> Result<usize> {
+	Ok(conn.pragma_query_value(None, "user_version", |v| v.get(0))?)
+}
+
+
+// 1
+fn add_files_table(conn: &Connection) -> Result<()> {
+	conn.execute(r#"
+		CREATE TABLE IF NOT EXISTS files (
+			id			INTEGER NOT NULL,
+			row_id		INTEGER NOT NULL,
+			path		TEXT NOT NULL,
+			file_size	INTEGER,
+			file_data	BLOB,
+
+			PRIMARY KEY("id")
+		)
+		"#,
+		[]
+	)?;
+
+	Ok(())
+}
+
+// 2
+fn add_recent_selection(conn: &Connection) -> Result<()> {
+	add_column_if_missing(conn, "recent", "selection", "INTEGER NOT NULL DEFAULT 0")
+}
+
+// 3
+fn add_data_formats_table(conn: &Connection) -> Result<()> {
+	conn.execute(r#"
+		CREATE TABLE IF NOT EXISTS data_formats (
+			id			INTEGER NOT NULL,
+			row_id		INTEGER NOT NULL,
+			format		TEXT NOT NULL,
+			format_size	INTEGER NOT NULL,
+			format_data	BLOB NOT NULL,
+
+			PRIMARY KEY("id")
+		)
+		"#,
+		[]
+	)?;
+
+	Ok(())
+}
+
+// 4
+fn add_data_privacy(conn: &Connection) -> Result<()> {
+	add_column_if_missing(conn, "data", "is_masked", "BOOLEAN NOT NULL DEFAULT 0")?;
+	add_column_if_missing(conn, "data", "expires_at", "INTEGER")
+}
+
+// 5
+fn add_recent_source_app(conn: &Connection) -> Result<()> {
+	add_column_if_missing(conn, "recent", "source_app", "TEXT")
+}
+
+// 6
+// Full images move into data_images so selecting from data doesn't read them. The remaining image column
+//      becomes a BLOB. SQLite can't change a column type so data is rebuilt.
+fn move_images_out_of_data(conn: &Connection) -> Result<()> {
+	conn.execute_batch(r#"
+		CREATE TABLE data_images (
+			id			INTEGER NOT NULL,
+			image_data	BLOB NOT NULL,
+
+			PRIMARY KEY("id")
+		);
+
+		INSERT INTO data_images (id, image_data)
+			SELECT id, CAST(image_data AS BLOB) FROM data WHERE image_data IS NOT NULL;
+
+		CREATE TABLE data_new (
+			id					INTEGER NOT NULL,
+			hash				TEXT NOT NULL UNIQUE,
+			is_starred			BOOLEAN NOT NULL DEFAULT 0,
+
+			type_of				TINYINT NOT NULL,
+
+			text_size			INTEGER,
+			text_data			TEXT,
+
+			html_size			INTEGER,
+			html_data			TEXT,
+
+			image_size			INTEGER,
+			image_thumb_size	INTEGER,
+			image_thumb_data	BLOB,
+
+			is_masked			BOOLEAN NOT NULL DEFAULT 0,
+			expires_at			INTEGER,
+
+			PRIMARY KEY("id")
+		);
+
+		INSERT INTO data_new (id, hash, is_starred, type_of, text_size, text_data, html_size, html_data, image_size, image_thumb_size, image_thumb_data, is_masked, expires_at)
+			SELECT id, hash, is_starred, type_of, text_size, text_data, html_size, html_data, image_size, image_thumb_size, CAST(image_thumb_data AS BLOB), is_masked, expires_at FROM data;
+
+		DROP TABLE data;
+
+		ALTER TABLE data_new RENAME TO data;
+	"#)?;
+
+	Ok(())
+}
+
+// 7
+// Large images and file snapshots can be stored in the blob directory instead, referenced by blob_hash.
+//      image_data becomes nullable so data_images is rebuilt.
+fn add_blobs_table(conn: &Connection) -> Result<()> {
+	conn.execute_batch(r#"
+		CREATE TABLE blobs (
+			hash		TEXT NOT NULL,
+			size		INTEGER NOT NULL,
+			ref_count	INTEGER NOT NULL,
+
+			PRIMARY KEY("hash")
+		);
+
+		CREATE TABLE data_images_new (
+			id			INTEGER NOT NULL,
+			image_data	BLOB,
+			blob_hash	TEXT,
+
+			PRIMARY KEY("id")
+		);
+
+		INSERT INTO data_images_new (id, image_data)
+			SELECT id, image_data FROM data_images;
+
+		DROP TABLE data_images;
+
+		ALTER TABLE data_images_new RENAME TO data_images;
+
+		ALTER TABLE files ADD COLUMN blob_hash TEXT;
+	"#)?;
+
+	Ok(())
+}
+
+// 8
+// Full-text index over the text and HTML. It only references data (external content) so triggers keep it in sync.
+// If data is ever rebuilt again the triggers have to be recreated.
+fn add_data_search_index(conn: &Connection) -> Result<()> {
+	conn.execute_batch(r#"
+		CREATE VIRTUAL TABLE data_search USING fts5(
+			text_data,
+			html_data,
+
+			content = 'data',
+			content_rowid = 'id'
+		);
+
+		CREATE TRIGGER data_search_insert AFTER INSERT ON data BEGIN
+			INSERT INTO data_search (rowid, text_data, html_data) VALUES (new.id, new.text_data, new.html_data);
+		END;
+
+		CREATE TRIGGER data_search_delete AFTER DELETE ON data BEGIN
+			INSERT INTO data_search (data_search, rowid, text_data, html_data) VALUES ('delete', old.id, old.text_data, old.html_data);
+		END;
+
+		CREATE TRIGGER data_search_update AFTER UPDATE OF text_data, html_data ON data BEGIN
+			INSERT INTO data_search (data_search, rowid, text_data, html_data) VALUES ('delete', old.id, old.text_data, old.html_data);
+			INSERT INTO data_search (rowid, text_data, html_data) VALUES (new.id, new.text_data, new.html_data);
+		END;
+
+		-- Index everything already stored.
+		INSERT INTO data_search (data_search) VALUES ('rebuild');
+	"#)?;
+
+	Ok(())
+}
+
+
+// 9
+// Deleting moves data to the trash. It's only purged later.
+fn add_data_deleted_at(conn: &Connection) -> Result<()> {
+	conn.execute_batch(r#"
+		ALTER TABLE data ADD COLUMN deleted_at INTEGER;
+
+		CREATE INDEX data_deleted_at ON data (deleted_at);
+	"#)?;
+
+	Ok(())
+}
+
+
+// 10
+// Many-to-many tags on data.
+fn add_tags_tables(conn: &Connection) -> Result<()> {
+	conn.execute_batch(r#"
+		CREATE TABLE tags (
+			id		INTEGER NOT NULL,
+			name	TEXT NOT NULL UNIQUE COLLATE NOCASE,
+
+			PRIMARY KEY("id")
+		);
+
+		CREATE TABLE data_tags (
+			data_id	INTEGER NOT NULL,
+			tag_id	INTEGER NOT NULL,
+
+			PRIMARY KEY("data_id", "tag_id")
+		);
+
+		CREATE INDEX data_tags_tag_id ON data_tags (tag_id);
+	"#)?;
+
+	Ok(())
+}
+
+
+// 11
+// Titles and notes are searched too so the full-text index is recreated with them.
+fn add_data_title_and_note(conn: &Connection) -> Result<()> {
+	conn.execute_batch(r#"
+		ALTER TABLE data ADD COLUMN title TEXT;
+		ALTER TABLE data ADD COLUMN note TEXT;
+
+		DROP TRIGGER data_search_insert;
+		DROP TRIGGER data_search_delete;
+		DROP TRIGGER data_search_update;
+		DROP TABLE data_search;
+
+		CREATE VIRTUAL TABLE data_search USING fts5(
+			text_data,
+			html_data,
+			title,
+			note,
+
+			content = 'data',
+			content_rowid = 'id'
+		);
+
+		CREATE TRIGGER data_search_insert AFTER INSERT ON data BEGIN
+			INSERT INTO data_search (rowid, text_data, html_data, title, note) VALUES (new.id, new.text_data, new.html_data, new.title, new.note);
+		END;
+
+		CREATE TRIGGER data_search_delete AFTER DELETE ON data BEGIN
+			INSERT INTO data_search (data_search, rowid, text_data, html_data, title, note) VALUES ('delete', old.id, old.text_data, old.html_data, old.title, old.note);
+		END;
+
+		CREATE TRIGGER data_search_update AFTER UPDATE OF text_data, html_data, title, note ON data BEGIN
+			INSERT INTO data_search (data_search, rowid, text_data, html_data, title, note) VALUES ('delete', old.id, old.text_data, old.html_data, old.title, old.note);
+			INSERT INTO data_search (rowid, text_data, html_data, title, note) VALUES (new.id, new.text_data, new.html_data, new.title, new.note);
+		END;
+
+		INSERT INTO data_search (data_search) VALUES ('rebuild');
+	"#)?;
+
+	Ok(())
+}
+
+
+// 12
+// Previous contents of edited text.
+fn add_data_revisions_table(conn: &Connection) -> Result<()> {
+	conn.execute_batch(r#"
+		CREATE TABLE data_revisions (
+			id			INTEGER NOT NULL,
+			data_id		INTEGER NOT NULL,
+			date		INTEGER NOT NULL,
+			text_data	TEXT,
+			html_data	TEXT,
+
+			PRIMARY KEY("id")
+		);
+
+		CREATE INDEX data_revisions_data_id ON data_revisions (data_id);
+	"#)?;
+
+	Ok(())
+}
+
+
+// 13
+// Starred data is arranged by hand instead of by when it was last copied. Pinned data is kept above the recents.
+fn add_data_star_position_and_pinned_at(conn: &Connection) -> Result<()> {
+	conn.execute_batch(r#"
+		ALTER TABLE data ADD COLUMN star_position INTEGER;
+		ALTER TABLE data ADD COLUMN pinned_at INTEGER;
+
+		UPDATE data SET star_position = (
+			SELECT position FROM (
+				SELECT row_id, ROW_NUMBER() OVER (ORDER BY MAX(id) DESC) AS position FROM recent GROUP BY row_id
+			) WHERE row_id = data.id
+		) WHERE is_starred = 1;
+	"#)?;
+
+	Ok(())
+}
+
+
+// 14
+// Copied files were hashed like text, which made them and a text copy of their paths the same data.
+fn rehash_copied_files(conn: &Connection) -> Result<()> {
+	let files = {
+		let mut stmt = conn.prepare(r#"SELECT id, text_data FROM data WHERE type_of = 2"#)?;
+
+		let iter = stmt.query_map([], |v| Ok((v.get::<_, usize>(0)?, v.get::<_, String>(1)?)))?;
+
+		iter.collect::<std::result::Result<Vec<_>, _>>()?
+	};
+
+	for (id, text_data) in files {
+		conn.execute(
+			r#"UPDATE data SET hash = ?1 WHERE id = ?2"#,
+			params![ hash_files(&text_data), id ]
+		)?;
+	}
+
+	Ok(())
+}
+
+
+fn add_column_if_missing(conn: &Connection, table: &str, column: &str, definition: &str) -> Result<()> {
+	let exists = conn.prepare(&format!(r#"SELECT * FROM pragma_table_info('{}') WHERE name = ?1"#, table))?
+		.exists(params![column])?;
+
+	if !exists {
+		conn.execute(&format!(r#"ALTER TABLE {} ADD COLUMN {} {}"#, table, column, definition), [])?;
+	}
+
+	Ok(())
+}
+
+
+#[cfg(test)]
+mod tests {
+	use rusqlite::{Connection, params};
+
+	use super::*;
+
+	/// Schema from before there were any migrations.
+	fn baseline() -> Connection {
+		let conn = Connection::open_in_memory().unwrap();
+
+		conn.execute_batch(r#"
+			CREATE TABLE data (
+				id					INTEGER NOT NULL,
+				hash				TEXT NOT NULL UNIQUE,
+				is_starred			BOOLEAN NOT NULL DEFAULT 0,
+
+				type_of				TINYINT NOT NULL,
+
+				text_size			INTEGER,
+				text_data			TEXT,
+
+				html_size			INTEGER,
+				html_data			TEXT,
+
+				image_size			INTEGER,
+				image_data			TEXT,
+				image_thumb_size	INTEGER,
+				image_thumb_data	TEXT,
+
+				PRIMARY KEY("id")
+			);
+
+			CREATE TABLE recent (
+				id		INTEGER NOT NULL,
+				row_id	INTEGER NOT NULL,
+				date	INTEGER NOT NULL,
+
+				PRIMARY KEY("id")
+			);
+		"#).unwrap();
+
+		for (id, text, is_starred) in [(1, "first copy", true), (3, "third copy", true), (4, "fourth copy", false)] {
+			conn.execute(
+				r#"INSERT INTO data (id, hash, is_starred, type_of, text_size, text_data, html_size, html_data) VALUES (?1, ?2, ?3, 0, ?4, ?5, ?4, ?5)"#,
+				params![ id, format!("hash{}", id), is_starred, text.len(), text ]
+			).unwrap();
+		}
+
+		conn.execute(
+			r#"INSERT INTO data (id, hash, type_of, image_size, image_data, image_thumb_size, image_thumb_data) VALUES (2, 'hash2', 1, 4, ?1, 2, ?2)"#,
+			params![ vec![1u8, 2, 3, 4], vec![5u8, 6] ]
+		).unwrap();
+
+		for (id, row_id) in [(1, 1), (2, 2), (3, 3), (4, 4), (5, 1)] {
+			conn.execute(
+				r#"INSERT INTO recent (id, row_id, date) VALUES (?1, ?2, ?3)"#,
+				params![ id, row_id, id * 1000 ]
+			).unwrap();
+		}
+
+		conn
+	}
+
+	fn search(conn: &Connection, value: &str) -> Vec<usize> {
+		let mut stmt = conn.prepare(r#"SELECT rowid FROM data_search WHERE data_search MATCH ?1 ORDER BY rowid"#).unwrap();
+
+		stmt.query_map(params![value], |v| v.get(0)).unwrap()
+			.collect::<std::result::Result<Vec<_>, _>>().unwrap()
+	}
+
+	/// Everything which describes the schema.
+	fn schema(conn: &Connection) -> Vec<String> {
+		let mut stmt = conn.prepare(r#"SELECT sql FROM sqlite_master WHERE sql IS NOT NULL ORDER BY name"#).unwrap();
+
+		stmt.query_map([], |v| v.get(0)).unwrap()
+			.collect::<std::result::Result<Vec<_>, _>>().unwrap()
+	}
+
+
+	#[test]
+	fn migrates_the_baseline() {
+		let conn = baseline();
+
+		migrate(&conn).unwrap();
+
+		assert_eq!(schema_version(&conn).unwrap(), MIGRATIONS.len());
+
+		let text: String = conn.query_row(r#"SELECT text_data FROM data WHERE id = 3"#, [], |v| v.get(0)).unwrap();
+		assert_eq!(text, "third copy");
+
+		let recents: usize = conn.query_row(r#"SELECT COUNT(*) FROM recent WHERE selection = 0 AND source_app IS NULL"#, [], |v| v.get(0)).unwrap();
+		assert_eq!(recents, 5);
+
+		// Images moved out of data.
+		let image: Vec<u8> = conn.query_row(r#"SELECT image_data FROM data_images WHERE id = 2"#, [], |v| v.get(0)).unwrap();
+		assert_eq!(image, vec![1, 2, 3, 4]);
+
+		let thumb: Vec<u8> = conn.query_row(r#"SELECT image_thumb_data FROM data WHERE id = 2"#, [], |v| v.get(0)).unwrap();
+		assert_eq!(thumb, vec![5, 6]);
+
+		assert!(conn.prepare(r#"SELECT image_data FROM data"#).is_err());
+
+		// The full-text index covers what was already stored.
+		assert_eq!(search(&conn, "copy"), vec![1, 3, 4]);
+		assert_eq!(search(&conn, "third"), vec![3]);
+
+		let unpositioned: usize = conn.query_row(r#"SELECT COUNT(*) FROM data WHERE is_starred = 1 AND star_position IS NULL"#, [], |v| v.get(0)).unwrap();
+		assert_eq!(unpositioned, 0);
+	}
+
+	#[test]
+	fn star_positions_follow_the_last_copy() {
+		let conn = baseline();
+
+		migrate(&conn).unwrap();
+
+		let mut stmt = conn.prepare(r#"SELECT id, star_position FROM data ORDER BY star_position IS NULL, star_position, id"#).unwrap();
+
+		let positions = stmt.query_map([], |v| Ok((v.get::<_, usize>(0)?, v.get::<_, Option<usize>>(1)?))).unwrap()
+			.collect::<std::result::Result<Vec<_>, _>>().unwrap();
+
+		// 1 was copied again after 3. Only starred data has a position.
+		assert_eq!(positions.iter().map(|v| v.0).collect::<Vec<_>>(), vec![1, 3, 2, 4]);
+		assert!(positions[0].1 < positions[1].1);
+		assert_eq!(positions[2].1, None);
+		assert_eq!(positions[3].1, None);
+	}
+
+	#[test]
+	fn copied_files_are_rehashed() {
+		let conn = baseline();
+
+		// Stored before there was any schema version, hashed like text.
+		conn.execute(
+			r#"INSERT INTO data (id, hash, type_of, text_size, text_data) VALUES (5, ?1, 2, 4, '/a/b')"#,
+			params![super::super::hash_data(b"/a/b")]
+		).unwrap();
+
+		migrate(&conn).unwrap();
+
+		let hash: String = conn.query_row(r#"SELECT hash FROM data WHERE id = 5"#, [], |v| v.get(0)).unwrap();
+		assert_eq!(hash, hash_files("/a/b"));
+
+		let text: String = conn.query_row(r#"SELECT hash FROM data WHERE id = 1"#, [], |v| v.get(0)).unwrap();
+		assert_eq!(text, "hash1");
+	}
+
+	#[test]
+	fn migrating_again_does_nothing() {
+		let conn = baseline();
+
+		migrate(&conn).unwrap();
+
+		let schema_before = schema(&conn);
+
+		migrate(&conn).unwrap();
+
+		assert_eq!(schema(&conn), schema_before);
+		assert_eq!(schema_version(&conn).unwrap(), MIGRATIONS.len());
+		assert_eq!(search(&conn, "copy"), vec![1, 3, 4]);
+	}
+
+	#[test]
+	fn newer_schema_is_rejected() {
+		let conn = baseline();
+
+		conn.pragma_update(None, "user_version", MIGRATIONS.len() + 1).unwrap();
+
+		assert!(migrate(&conn).is_err());
+		assert_eq!(schema_version(&conn).unwrap(), MIGRATIONS.len() + 1);
+	}
+}
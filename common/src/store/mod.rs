@@ -1,396 +1,259 @@
+use std::ops::{Deref, Range};
 use std::sync::Arc;
 
-use anyhow::Result;
-use chrono::{Utc, TimeZone};
-use rusqlite::{Connection, params, Row, OptionalExtension};
+use anyhow::{Result, bail};
+use chrono::Utc;
+use regex::Regex;
+use rusqlite::Row;
+use rusqlite::types::{FromSql, FromSqlError, FromSqlResult, ToSql, ToSqlOutput, ValueRef};
 use serde::{Serialize, Deserialize};
 use sha2::{Sha256, Digest};
 
-use crate::config::Config;
+use crate::clipboard::Selection;
+use crate::config::{Config, ConfigRetention, SensitiveAction};
+
+pub use blobs::BlobStore;
+pub use memory::MemoryStore;
+pub use pool::MEMORY_PATH;
+pub use retention::{DataUsage, RetentionReport};
+pub use search::{ClipType, Comparison, SearchQuery, SearchTerm};
+pub use sensitive::{check_rules, find_sensitive};
+pub use sqlite::SqliteStore;
+
+mod blobs;
+mod fuzzy;
+mod memory;
+mod migrations;
+mod pool;
+mod query;
+mod retention;
+mod search;
+mod sensitive;
+mod sqlite;
+
+
+/// Storage for everything that's copied. Shared between the listener thread and the GUI.
+pub trait Store: Send + Sync {
+	fn query(&self, value: StorageQuery) -> Result<Vec<ReturnedItem>>;
+
+	/// Returns the id of the stored data. None if it was too large to store.
+	fn add_text(&self, text_data: String, html_data: Option<String>, source: &CopySource, config: &Config) -> Result<Option<usize>>;
+	fn add_image(&self, image_data: Vec<u8>, image_thumb_data: Option<Vec<u8>>, source: &CopySource, config: &Config) -> Result<Option<usize>>;
+	fn add_files(&self, files: Vec<CopiedFile>, source: &CopySource, config: &Config) -> Result<Option<usize>>;
+	/// Replace the other formats which were offered alongside the stored data.
+	fn set_formats(&self, data_id: usize, formats: &[(String, Vec<u8>)]) -> Result<()>;
+
+	/// Newly starred data is put first.
+	fn set_favorite(&self, index: usize, value: bool) -> Result<usize>;
+	/// Move starred data to `index` in the order of [`StorageQuery::Favorites`].
+	fn move_favorite(&self, data_id: usize, index: usize) -> Result<usize>;
+	fn set_pinned(&self, data_id: usize, value: bool) -> Result<usize>;
+	/// Shown instead of the copied text. Empty removes it.
+	fn set_title(&self, data_id: usize, title: &str) -> Result<usize>;
+	/// Empty removes it.
+	fn set_note(&self, data_id: usize, note: &str) -> Result<usize>;
+	/// Replace the text, keeping what it was as a revision. The other formats are removed since they'd no longer match.
+	///
+	/// If other data already has the new text they're merged into it. Returns the id of the data with the new text.
+	fn edit_text(&self, data_id: usize, text_data: String, html_data: Option<String>, config: &Config) -> Result<usize>;
+	/// Previous contents, newest first.
+	fn get_revisions(&self, data_id: usize) -> Result<Vec<DataRevision>>;
+
+	/// Returns the id of the tag. Names are matched ignoring case.
+	fn create_tag(&self, name: &str) -> Result<usize>;
+	/// Tag the data, creating the tag if it's new.
+	fn add_tag(&self, data_id: usize, name: &str) -> Result<usize>;
+	fn remove_tag(&self, data_id: usize, name: &str) -> Result<usize>;
+	/// Remove the tag from everything and delete it.
+	fn delete_tag(&self, name: &str) -> Result<usize>;
+	/// Every tag by name.
+	fn get_tags(&self) -> Result<Vec<Tag>>;
+	fn get_data_tags(&self, data_id: usize) -> Result<Vec<String>>;
+
+	/// Move the data to the trash. It's hidden from every query except [`StorageQuery::Trash`].
+	fn delete(&self, index: usize) -> Result<usize>;
+	/// Take the data back out of the trash.
+	fn restore(&self, index: usize) -> Result<usize>;
+	/// Delete the data and everything stored with it for good.
+	fn purge(&self, index: usize) -> Result<usize>;
+	/// Purge data moved to the trash at or before the Unix timestamp (ms). Returns how many were purged.
+	fn purge_trash(&self, deleted_before: usize) -> Result<usize>;
+	/// Purge data stored with an expiry which has passed.
+	fn delete_expired(&self) -> Result<usize>;
+	/// Move everything to the trash. Forced purges everything instead.
+	fn clear_database(&self, force: bool) -> Result<usize>;
+
+	/// Purge the oldest unstarred data past the limits of the policy.
+	fn apply_retention(&self, policy: &ConfigRetention) -> Result<RetentionReport> {
+		retention::apply_retention(self, policy)
+	}
 
+	/// Size of everything stored (bytes).
+	fn compute_total_size(&self) -> Result<usize> {
+		Ok(self.get_data_usage()?.iter().map(|v| v.size).sum())
+	}
 
-#[derive(Clone)]
-pub struct StorageContainer(Arc<Connection>);
+	/// Every data, most recently copied first.
+	fn get_data_usage(&self) -> Result<Vec<DataUsage>>;
 
-// TODO: I KNOW I KNOW
-#[allow(clippy::non_send_fields_in_send_ty)]
-unsafe impl Send for StorageContainer {}
+	fn get_data(&self, data_id: usize) -> Result<CopiedData>;
+	fn get_formats(&self, data_id: usize) -> Result<Vec<CopiedFormat>>;
+	fn get_files(&self, data_id: usize) -> Result<Vec<CopiedFile>>;
+	fn get_image(&self, data_id: usize) -> Result<Vec<u8>>;
+	/// Every application something was copied from.
+	fn get_source_apps(&self) -> Result<Vec<String>>;
 
+	fn count_the_recents_newer_than(&self, time: usize) -> Result<usize>;
+}
 
-impl StorageContainer {
-	pub fn open(path: &str) -> Result<Self> {
-		let conn = Connection::open(path)?;
 
-		init_database(&conn)?;
+/// Shared handle to the store.
+#[derive(Clone)]
+pub struct StorageContainer(Arc<dyn Store>);
 
-		Ok(Self(Arc::new(conn)))
-	}
 
+impl StorageContainer {
+	pub fn new<S: Store + 'static>(store: S) -> Self {
+		Self(Arc::new(store))
+	}
 
-	pub fn query(&self, value: StorageQuery) -> Result<Vec<ReturnedItem>> {
-		match value {
-			StorageQuery::Favorites => {
-				let sql = r#"
-					SELECT
-						recent.id,
-						recent.date,
-						data.is_starred,
-						data.type_of,
-						data.text_data,
-						data.image_thumb_data,
-						data.id
-					FROM recent
-					INNER JOIN data ON
-						data.id = recent.row_id
-					WHERE data.is_starred = 1
-					ORDER BY recent.id DESC
-				"#;
-
-				let mut stmt = self.0.prepare(sql)?;
-
-				let iter = stmt.query_map(
-					[],
-					|r| Ok(ReturnedItem {
-						recent_id: r.get(0)?,
-						timestamp: Utc.timestamp_millis(r.get(1)?),
-						is_favorite: r.get(2)?,
-						value: ReturnedItemType::from_sql(r.get(3)?, r.get(4)?, r.get(5)?),
-						data_id: r.get(6)?,
-				}))?;
-
-				Ok(iter.collect::<std::result::Result<Vec<_>, _>>()?)
-			}
-
-			StorageQuery::Recent { limit, skip } => {
-				let sql = format!(r#"
-					SELECT
-						recent.id,
-						recent.date,
-						data.is_starred,
-						data.type_of,
-						data.text_data,
-						data.image_thumb_data,
-						data.id
-					FROM recent
-					INNER JOIN data ON
-						data.id = recent.row_id
-					WHERE 1
-					ORDER BY recent.id DESC
-					LIMIT {}
-					OFFSET {}
-				"#, limit, skip);
-
-				let mut stmt = self.0.prepare(&sql)?;
-
-				let iter = stmt.query_map(
-					[],
-					|r| Ok(ReturnedItem {
-						recent_id: r.get(0)?,
-						timestamp: Utc.timestamp_millis(r.get(1)?),
-						is_favorite: r.get(2)?,
-						value: ReturnedItemType::from_sql(r.get(3)?, r.get(4)?, r.get(5)?),
-						data_id: r.get(6)?,
-				}))?;
-
-				Ok(iter.collect::<std::result::Result<Vec<_>, _>>()?)
-			}
-
-			StorageQuery::Search { value } => {
-				// TODO: Query works but I don't like it. Currently will remove newest instead of oldest duplicates from results.
-				let sql = if value.contains('%') || value.contains('_') {
-					let mut escape_char = '\\';
-
-					if value.contains(escape_char) {
-						for car in [ '!', '@', '#', '$', '^', '&', '*', '-', '=', '+', '|', '~', '`', '/', '?', '>', '<', ',' ] {
-							if !value.contains(car) {
-								escape_char = car;
-								break;
-							}
-						}
-					}
-
-					format!(
-						r#"
-							SELECT
-								recent.id,
-								recent.date,
-								data.is_starred,
-								data.type_of,
-								data.text_data,
-								data.image_thumb_data,
-								data.id
-							FROM data
-							INNER JOIN recent
-								ON recent.row_id = data.id
-							WHERE
-								text_data LIKE '%{}%' ESCAPE '{}'
-							GROUP BY recent.row_id
-							ORDER BY recent.id DESC
-						"#,
-						value.replace("%", &format!("{}%", escape_char)).replace("_", &format!("{}_", escape_char)),
-						escape_char
-					)
-				} else {
-					format!(r#"
-						SELECT
-							recent.id,
-							recent.date,
-							data.is_starred,
-							data.type_of,
-							data.text_data,
-							data.image_thumb_data,
-							data.id
-						FROM data
-						INNER JOIN recent
-							ON recent.row_id = data.id
-						WHERE
-							text_data LIKE '%{}%'
-						GROUP BY recent.row_id
-						ORDER BY recent.id DESC
-					"#, value)
-				};
-
-				let mut stmt = self.0.prepare(&sql)?;
-
-				let iter = stmt.query_map(
-					[],
-					|r| Ok(ReturnedItem {
-						recent_id: r.get(0)?,
-						timestamp: Utc.timestamp_millis(r.get(1)?),
-						is_favorite: r.get(2)?,
-						value: ReturnedItemType::from_sql(r.get(3)?, r.get(4)?, r.get(5)?),
-						data_id: r.get(6)?,
-				}))?;
-
-				Ok(iter.collect::<std::result::Result<Vec<_>, _>>()?)
-			}
-		}
+	/// SQLite database at `path`.
+	pub fn open(path: &str) -> Result<Self> {
+		Ok(Self::new(SqliteStore::open(path)?))
 	}
 
-	pub fn add_text(&self, text_data: String, html_data: Option<String>, config: &Config) -> Result<()> {
-		if text_data.len() > config.stores.text.max_size * 1000 * 1000 { // B -> KB -> MB
-			log::info!(target: "clipboard_listener", "[add_text]: Text Length {}MB > Max Length {}MB", text_data.len() / 1000 / 1000, config.stores.text.max_size);
-			return Ok(());
-		}
+	/// Empty store which only lives in memory.
+	pub fn memory() -> Self {
+		Self::new(MemoryStore::default())
+	}
+}
 
-		let hash = Sha256::digest(&text_data)
-			.iter()
-			.map(|v| format!("{:02x}", v))
-			.collect::<String>();
-
-		if let Some(v) = self.get_data_from_hash(&hash)? {
-			// Already exists?
-			let recent = self.get_most_recent_data(v.id)?;
-			let recent_items_after_previous = self.count_the_recents_newer_than(recent.date)?;
-
-			let current_date = Utc::now().timestamp_millis() as usize;
-			let minutes_ago = (current_date - recent.date) / 1000 / 60;
-
-			// If it's been 1 hour AND we've surpassed 30 new recents.
-			if minutes_ago > 60 && recent_items_after_previous > 30 {
-				self.insert_recent(&LastCopied {
-					id: 0,
-					row_id: v.id,
-					date: current_date
-				})?;
-			}
-		} else {
-			self.0.execute(
-				r#"INSERT INTO data (hash, type_of, text_size, text_data, html_size, html_data) VALUES (?1, ?2, ?3, ?4, ?5, ?6)"#,
-				params![ hash, 0, text_data.len(), text_data, html_data.as_deref().map(|v| v.len()), html_data ]
-			)?;
-
-			let data = self.get_data_from_hash(&hash)?.unwrap();
-
-			self.insert_recent(&LastCopied {
-				id: 0,
-				row_id: data.id,
-				date: Utc::now().timestamp_millis() as usize
-			})?;
-		}
+impl Deref for StorageContainer {
+	type Target = dyn Store;
 
-		Ok(())
+	fn deref(&self) -> &Self::Target {
+		&*self.0
 	}
+}
 
-	pub fn add_image(&self, image_data: Vec<u8>, image_thumb_data: Option<Vec<u8>>, config: &Config) -> Result<()> {
-		if image_data.len() > config.stores.image.max_size * 1000 * 1000 { // B -> KB -> MB
-			log::info!(target: "clipboard_listener", "[add_image]: Image Length {}MB > Max Length {}MB", image_data.len() / 1000 / 1000, config.stores.image.max_size);
-			return Ok(());
-		}
 
-		let hash = Sha256::digest(&image_data)
-			.iter()
-			.map(|v| format!("{:02x}", v))
-			.collect::<String>();
-
-		if let Some(stored_data) = self.get_data_from_hash(&hash)? {
-			// Already exists?
-			let recent = self.get_most_recent_data(stored_data.id)?;
-			let recent_items_after_previous = self.count_the_recents_newer_than(recent.date)?;
-
-			let current_date = Utc::now().timestamp_millis() as usize;
-			let minutes_ago = (current_date - recent.date) / 1000 / 60;
-
-			// If it's been 1 hour AND we've surpassed 30 new recents.
-			if minutes_ago > 60 && recent_items_after_previous > 30 {
-				self.insert_recent(&LastCopied {
-					id: 0,
-					row_id: stored_data.id,
-					date: current_date
-				})?;
-			}
-		} else {
-			self.0.execute(
-				r#"INSERT INTO data (hash, type_of, image_size, image_data, image_thumb_size, image_thumb_data) VALUES (?1, ?2, ?3, ?4, ?5, ?6)"#,
-				params![ hash, 1, image_data.len(), image_data, image_thumb_data.as_deref().map(|v| v.len()), image_thumb_data ]
-			)?;
-
-			let stored_data = self.get_data_from_hash(&hash)?.unwrap();
-
-			self.insert_recent(&LastCopied {
-				id: 0,
-				row_id: stored_data.id,
-				date: Utc::now().timestamp_millis() as usize
-			})?;
-		}
+// Shared between the stores.
 
-		Ok(())
-	}
+/// Lowercase hex SHA-256. Identical copies are found by it.
+fn hash_data(value: &[u8]) -> String {
+	Sha256::digest(value)
+		.iter()
+		.map(|v| format!("{:02x}", v))
+		.collect::<String>()
+}
 
-	pub fn set_favorite(&self, index: usize, value: bool) -> Result<usize> {
-		Ok(self.0.execute(
-			r#"UPDATE data SET is_starred = ?1 WHERE id = ?2"#,
-			params![value, index]
-		)?)
-	}
+/// Copied files are hashed apart from text, so copying a path as text doesn't find the copied file or the other way round.
+fn hash_files(text_data: &str) -> String {
+	hash_data(format!("files\0{}", text_data).as_bytes())
+}
 
-	pub fn delete(&self, index: usize) -> Result<usize> {
-		let deleted = self.0.execute(
-			r#"DELETE FROM data WHERE id = ?1"#,
-			params![index]
-		)?;
-
-		if deleted != 0 {
-			Ok(self.0.execute(
-				r#"DELETE FROM recent WHERE row_id = ?1"#,
-				params![index]
-			)?)
-		} else {
-			Ok(0)
-		}
+/// Action of the strictest privacy rule the text matches.
+fn privacy_action(text_data: &str, config: &Config) -> Option<SensitiveAction> {
+	find_sensitive(text_data, &config.privacy.rules).map(|rule| {
+		log::info!(target: "clipboard_listener", "[add_text]: Text matches privacy rule {:?} ({:?})", rule.name, rule.action);
+		rule.action
+	})
+}
+
+/// Unix timestamp (ms) text copied now should be deleted at.
+fn expires_at(action: Option<SensitiveAction>, config: &Config) -> Option<usize> {
+	if action == Some(SensitiveAction::Expire) {
+		Some(Utc::now().timestamp_millis() as usize + config.privacy.expire_minutes * 60 * 1000)
+	} else {
+		None
 	}
+}
 
-	pub fn clear_database(&self) -> Result<usize> {
-		let data_deleted = self.0.execute(
-			r#"DELETE FROM data WHERE 1"#,
-			[]
-		)?;
+/// Part of the text around the first range with each range between the snippet markers.
+///
+/// Ranges are byte offsets in order. Adjacent ranges are merged.
+fn marked_snippet(text: &str, ranges: &[Range<usize>]) -> String {
+	let first = ranges.first().map(|v| v.start).unwrap_or_default();
 
-		let recent_deleted = self.0.execute(
-			r#"DELETE FROM recent WHERE 1"#,
-			[]
-		)?;
+	let start = text[..first].char_indices().rev().nth(SNIPPET_CONTEXT_CHARS - 1).map(|(i, _)| i).unwrap_or_default();
+	let end = text[start..].char_indices().nth(SNIPPET_MAX_CHARS).map(|(i, _)| start + i).unwrap_or(text.len());
 
-		Ok(data_deleted + recent_deleted)
+	let mut snippet = String::new();
+
+	if start != 0 {
+		snippet.push('…');
 	}
 
-	pub fn compute_total_size(&self) -> Result<usize> {
-		let mut stmt = self.0.prepare("SELECT text_size FROM data WHERE 1")?;
+	let mut position = start;
 
-		let iter = stmt.query_map([], |v| v.get::<_, usize>(0))?;
+	for range in ranges {
+		// Overlapping or past the end of the snippet.
+		if range.start < position || range.end > end {
+			continue;
+		}
 
-		Ok(iter.sum::<rusqlite::Result<usize>>()?)
-	}
+		if range.start == position && snippet.ends_with(SNIPPET_MATCH_END) {
+			snippet.pop();
+		} else {
+			snippet += &text[position..range.start];
+			snippet.push(SNIPPET_MATCH_START);
+		}
+
+		snippet += &text[range.clone()];
+		snippet.push(SNIPPET_MATCH_END);
 
-	pub fn get_image(&self, data_id: usize) -> Result<Vec<u8>> {
-		Ok(self.0.query_row(
-			r#"SELECT image_data FROM data WHERE id = ?1 LIMIT 1"#,
-			params![data_id],
-			|v| v.get(0)
-		)?)
+		position = range.end;
 	}
 
+	snippet += &text[position..end];
 
-	fn insert_recent(&self, value: &LastCopied) -> Result<usize> {
-		Ok(self.0.execute(
-			r#"INSERT INTO recent (row_id, date) VALUES (?1, ?2)"#,
-			params![ value.row_id, value.date ]
-		)?)
+	if end != text.len() {
+		snippet.push('…');
 	}
 
+	snippet
+}
 
-	fn get_data_from_hash(&self, hash: &str) -> Result<Option<CopiedData>> {
-		Ok(self.0.query_row(
-			r#"SELECT * FROM data WHERE hash = ?1 LIMIT 1"#,
-			params![hash],
-			CopiedData::from_row
-		).optional()?)
+/// Snippet of every match of the regex. None if it doesn't match.
+fn regex_snippet(regex: &Regex, text: &str) -> Option<String> {
+	let ranges = regex.find_iter(text)
+		.map(|v| v.range())
+		.filter(|v| !v.is_empty())
+		.collect::<Vec<_>>();
+
+	if ranges.is_empty() {
+		None
+	} else {
+		Some(marked_snippet(text, &ranges))
 	}
+}
 
-	fn get_most_recent_data(&self, data_id: usize) -> Result<LastCopied> {
-		Ok(self.0.query_row(
-			r#"SELECT * FROM recent WHERE row_id = ?1 ORDER BY id DESC LIMIT 1"#,
-			params![data_id],
-			LastCopied::from_row
-		)?)
-	}
+/// Trimmed. None if it's empty.
+fn optional_text(value: &str) -> Option<&str> {
+	Some(value.trim()).filter(|v| !v.is_empty())
+}
 
-	fn get_most_recent_data_list(&self, data_id: usize) -> Result<Vec<LastCopied>> {
-		let mut stmt = self.0.prepare(&format!("SELECT * FROM recent WHERE row_id = '{}' ORDER BY id DESC LIMIT 1", data_id))?;
+/// Checks edited text the way `add_text` checks copied text.
+fn check_edited_text(text_data: &str, config: &Config) -> Result<Option<SensitiveAction>> {
+	if text_data.len() > config.stores.text.max_size * 1000 * 1000 { // B -> KB -> MB
+		bail!("The text is larger than the max size of {}MB", config.stores.text.max_size);
+	}
 
-		let iter = stmt.query_map([], LastCopied::from_row)?;
+	let action = privacy_action(text_data, config);
 
-		Ok(iter.collect::<std::result::Result<Vec<_>, _>>()?)
+	if action == Some(SensitiveAction::Drop) {
+		bail!("The text matches a privacy rule which doesn't allow storing it");
 	}
 
-	pub fn count_the_recents_newer_than(&self, time: usize) -> Result<usize> {
-		Ok(self.0.query_row(
-			r#"SELECT COUNT(*) FROM recent WHERE date > ?1"#,
-			params![time],
-			|v| v.get(0)
-		)?)
-	}
+	Ok(action)
 }
 
-fn init_database(conn: &Connection) -> Result<()> {
-	conn.execute(r#"
-		CREATE TABLE IF NOT EXISTS data (
-			id					INTEGER NOT NULL,
-			hash				TEXT NOT NULL UNIQUE,
-			is_starred			BOOLEAN NOT NULL DEFAULT 0,
-
-			type_of				TINYINT NOT NULL,
-
-			text_size			INTEGER,
-			text_data			TEXT,
-
-			html_size			INTEGER,
-			html_data			TEXT,
-
-			image_size			INTEGER,
-			image_data			TEXT,
-			image_thumb_size	INTEGER,
-			image_thumb_data	TEXT,
-
-			PRIMARY KEY("id")
-		)
-		"#,
-		[]
-	)?;
-
-	conn.execute(r#"
-		CREATE TABLE IF NOT EXISTS recent (
-			id		INTEGER NOT NULL,
-			row_id	INTEGER NOT NULL,
-			date	INTEGER NOT NULL,
-
-			PRIMARY KEY("id")
-		)
-		"#,
-		[]
-	)?;
-
-	Ok(())
+/// Whether copying stored data again should add it to the recents.
+fn should_record_recopy(previous_date: usize, current_date: usize, recent_items_after_previous: usize) -> bool {
+	let minutes_ago = (current_date - previous_date) / 1000 / 60;
+
+	// If it's been 1 hour AND we've surpassed 30 new recents.
+	minutes_ago > 60 && recent_items_after_previous > 30
 }
 
 
@@ -402,9 +265,36 @@ pub enum StorageQuery {
 
 	Search {
 		value: String,
+		/// Only copies from this application.
+		source_app: Option<String>,
 	},
 
-	Favorites
+	/// Parsed from the search query language.
+	Advanced(SearchQuery),
+
+	/// Text fuzzily matching the pattern, best match first.
+	Fuzzy {
+		pattern: String,
+		source_app: Option<String>,
+	},
+
+	/// Text matching the regex.
+	Regex {
+		pattern: String,
+		source_app: Option<String>,
+	},
+
+	/// Starred data in the order it was arranged.
+	Favorites,
+
+	/// Pinned data, last pinned first.
+	Pinned,
+
+	/// Data with the tag.
+	Tag(String),
+
+	/// Data in the trash.
+	Trash
 }
 
 
@@ -412,7 +302,7 @@ pub enum StorageQuery {
 
 // Storage Tables
 
-#[derive(Serialize, Deserialize)]
+#[derive(Clone, Serialize, Deserialize)]
 pub struct CopiedData {
 	pub id: usize,
 	pub hash: String,
@@ -430,9 +320,29 @@ pub struct CopiedData {
 
 	// Image
 	pub image_size: Option<usize>,
-	pub image_data: Option<Vec<u8>>, // TODO: Blobify
+	/// Stored in data_images. Only loaded by `get_data`.
+	pub image_data: Option<Vec<u8>>,
 	pub image_thumb_size: Option<usize>,
 	pub image_thumb_data: Option<Vec<u8>>,
+
+	// Privacy
+	pub is_masked: bool,
+	/// Unix timestamp (ms) it'll be deleted at.
+	pub expires_at: Option<usize>,
+
+	/// Unix timestamp (ms) it was moved to the trash.
+	pub deleted_at: Option<usize>,
+
+	pub title: Option<String>,
+	pub note: Option<String>,
+
+	/// Order in the starred list. Lowest first.
+	pub star_position: Option<i64>,
+	/// Unix timestamp (ms) it was pinned above the recents.
+	pub pinned_at: Option<usize>,
+
+	/// Names of the other formats stored in data_formats.
+	pub formats: Vec<String>,
 }
 
 impl CopiedData {
@@ -451,20 +361,46 @@ impl CopiedData {
 			html_data: row.get(7)?,
 
 			image_size: row.get(8)?,
-			image_data: row.get(9)?,
-			image_thumb_size: row.get(10)?,
-			image_thumb_data: row.get(11)?,
+			image_data: None,
+			image_thumb_size: row.get(9)?,
+			image_thumb_data: row.get(10)?,
+
+			is_masked: row.get(11)?,
+			expires_at: row.get(12)?,
+
+			deleted_at: row.get(13)?,
+
+			title: row.get(14)?,
+			note: row.get(15)?,
+
+			star_position: row.get(16)?,
+			pinned_at: row.get(17)?,
+
+			formats: Vec::new(),
 		})
 	}
 }
 
 
 
-#[derive(Serialize, Deserialize)]
+/// Where a copy came from.
+#[derive(Clone)]
+pub struct CopySource {
+	pub selection: Selection,
+	/// Name of the application which owned the selection, if it could be found.
+	pub app: Option<String>,
+}
+
+
+#[derive(Clone, Serialize, Deserialize)]
 pub struct LastCopied {
 	pub id: usize,
 	pub row_id: usize,
 	pub date: usize,
+	/// Selection it was copied into.
+	pub selection: Selection,
+	/// Application which owned the selection.
+	pub source_app: Option<String>,
 }
 
 impl LastCopied {
@@ -472,7 +408,139 @@ impl LastCopied {
 		Ok(Self {
 			id: row.get(0)?,
 			row_id: row.get(1)?,
-			date: row.get(2)?
+			date: row.get(2)?,
+			selection: row.get(3)?,
+			source_app: row.get(4)?
+		})
+	}
+}
+
+
+// selection
+//   0 - Clipboard
+//   1 - Primary
+
+impl ToSql for Selection {
+	fn to_sql(&self) -> rusqlite::Result<ToSqlOutput<'_>> {
+		Ok(ToSqlOutput::from(match self {
+			Selection::Clipboard => 0,
+			Selection::Primary => 1,
+		}))
+	}
+}
+
+impl FromSql for Selection {
+	fn column_result(value: ValueRef<'_>) -> FromSqlResult<Self> {
+		match value.as_i64()? {
+			0 => Ok(Selection::Clipboard),
+			1 => Ok(Selection::Primary),
+			v => Err(FromSqlError::OutOfRange(v)),
+		}
+	}
+}
+
+
+/// What text data was before it was edited.
+#[derive(Clone, Serialize, Deserialize)]
+pub struct DataRevision {
+	pub id: usize,
+	pub data_id: usize,
+	/// Unix timestamp (ms) it was replaced.
+	pub date: usize,
+	pub text_data: Option<String>,
+	pub html_data: Option<String>,
+}
+
+impl DataRevision {
+	pub fn from_row(row: &Row) -> rusqlite::Result<Self> {
+		Ok(Self {
+			id: row.get(0)?,
+			data_id: row.get(1)?,
+			date: row.get(2)?,
+			text_data: row.get(3)?,
+			html_data: row.get(4)?
+		})
+	}
+}
+
+
+/// Groups data. Data can have any number of tags.
+#[derive(Clone, Serialize, Deserialize)]
+pub struct Tag {
+	pub id: usize,
+	pub name: String,
+	/// Tagged data, not counting the trash.
+	pub data_count: usize,
+}
+
+impl Tag {
+	/// Longest a tag name can be (characters).
+	pub const MAX_NAME_CHARS: usize = 64;
+
+	/// Trimmed name. Errors if it's empty, too long or has control characters.
+	pub fn parse_name(name: &str) -> Result<&str> {
+		let name = name.trim();
+
+		if name.is_empty() {
+			bail!("Tag names can't be empty");
+		}
+
+		if name.chars().count() > Self::MAX_NAME_CHARS {
+			bail!("Tag names can't be longer than {} characters", Self::MAX_NAME_CHARS);
+		}
+
+		if name.chars().any(char::is_control) {
+			bail!("Tag names can't have control characters");
+		}
+
+		Ok(name)
+	}
+}
+
+
+/// Format offered alongside the copied data. Restored with it so the copy is reproduced exactly.
+#[derive(Clone, Serialize, Deserialize)]
+pub struct CopiedFormat {
+	pub id: usize,
+	pub row_id: usize,
+	/// MIME type, or the native format name if there isn't one.
+	pub format: String,
+	pub format_size: usize,
+	pub format_data: Vec<u8>,
+}
+
+impl CopiedFormat {
+	pub fn from_row(row: &Row) -> rusqlite::Result<Self> {
+		Ok(Self {
+			id: row.get(0)?,
+			row_id: row.get(1)?,
+			format: row.get(2)?,
+			format_size: row.get(3)?,
+			format_data: row.get(4)?
+		})
+	}
+}
+
+
+#[derive(Clone, Serialize, Deserialize)]
+pub struct CopiedFile {
+	pub id: usize,
+	pub row_id: usize,
+	pub path: String,
+	/// None if it isn't a regular file (directory, removed, etc.)
+	pub file_size: Option<usize>,
+	/// Snapshot of the file contents.
+	pub file_data: Option<Vec<u8>>,
+}
+
+impl CopiedFile {
+	pub fn from_row(row: &Row) -> rusqlite::Result<Self> {
+		Ok(Self {
+			id: row.get(0)?,
+			row_id: row.get(1)?,
+			path: row.get(2)?,
+			file_size: row.get(3)?,
+			file_data: row.get(4)?
 		})
 	}
 }
@@ -483,12 +551,36 @@ impl LastCopied {
 // type_of
 //   0 - Text
 //   1 - Image
+//   2 - Files
+
+
+/// Characters kept before the first match in a snippet.
+const SNIPPET_CONTEXT_CHARS: usize = 24;
+/// Longest a snippet can be before it's cut short.
+const SNIPPET_MAX_CHARS: usize = 200;
+
+/// Marks the start of a match in [`ReturnedItem::snippet`].
+pub const SNIPPET_MATCH_START: char = '\u{2}';
+/// Marks the end of a match in [`ReturnedItem::snippet`].
+pub const SNIPPET_MATCH_END: char = '\u{3}';
 
 
 pub struct ReturnedItem {
 	pub data_id: usize,
 	pub value: ReturnedItemType,
 	pub is_favorite: bool,
+	/// Matched a privacy rule which hides the text.
+	pub is_masked: bool,
+	/// Application it was copied from.
+	pub source_app: Option<String>,
+	/// Search results only. Part of the text around the matches, which are between
+	///      [`SNIPPET_MATCH_START`] and [`SNIPPET_MATCH_END`].
+	pub snippet: Option<String>,
+	/// Names of its tags, in order.
+	pub tags: Vec<String>,
+	pub title: Option<String>,
+	pub note: Option<String>,
+	pub is_pinned: bool,
 
 	pub recent_id: usize,
 	pub timestamp: chrono::DateTime<chrono::Utc>,
@@ -496,6 +588,7 @@ pub struct ReturnedItem {
 
 pub enum ReturnedItemType {
 	Text(String),
+	Files(Vec<String>),
 	Thumb(Vec<u8>),
 	ThumbTextureId(u64)
 }
@@ -505,6 +598,7 @@ impl ReturnedItemType {
 		match type_of {
 			0 => Self::Text(text_value.unwrap()),
 			1 => Self::Thumb(thumb_value.unwrap()),
+			2 => Self::Files(text_value.unwrap().lines().map(|v| v.to_string()).collect()),
 			_ => panic!("Invalid Type Of Value Found! Value {}", type_of)
 		}
 	}
@@ -0,0 +1,138 @@
+use std::ops::{Deref, DerefMut};
+use std::path::PathBuf;
+use std::sync::Mutex;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::Duration;
+
+use anyhow::Result;
+use regex::Regex;
+use rusqlite::Connection;
+use rusqlite::functions::FunctionFlags;
+use rusqlite::types::ValueRef;
+
+
+// Connection Pool
+
+// Each caller checks out its own connection so the listener thread and the GUI never share one.
+// The database is in WAL mode so reads don't block the writer. The busy timeout makes a writer wait for the
+//      other one (possibly the tray or app process) instead of failing with "database is locked".
+
+const BUSY_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Connections kept open once they're returned. More are opened when they're all in use.
+const MAX_IDLE_CONNECTIONS: usize = 4;
+
+/// Path which opens a new database in memory instead of a file.
+pub const MEMORY_PATH: &str = ":memory:";
+
+/// Numbers the in-memory databases so each pool gets its own.
+static MEMORY_DATABASES: AtomicUsize = AtomicUsize::new(0);
+
+
+pub struct ConnectionPool {
+	path: PathBuf,
+	idle: Mutex<Vec<Connection>>,
+}
+
+impl ConnectionPool {
+	pub fn new(path: impl Into<PathBuf>) -> Self {
+		Self {
+			path: path.into(),
+			idle: Mutex::new(Vec::new()),
+		}
+	}
+
+	/// Database in memory shared by every connection of the pool. It's gone once they're all closed.
+	pub fn memory() -> Self {
+		Self::new(format!("file:{}?mode=memory&cache=shared", memory_database_name()))
+	}
+
+	pub fn get(&self) -> Result<PooledConnection<'_>> {
+		let conn = self.idle.lock().unwrap().pop();
+
+		let conn = match conn {
+			Some(v) => v,
+			None => self.open()?
+		};
+
+		Ok(PooledConnection {
+			pool: self,
+			conn: Some(conn),
+		})
+	}
+
+	fn open(&self) -> Result<Connection> {
+		let conn = Connection::open(&self.path)?;
+
+		conn.busy_timeout(BUSY_TIMEOUT)?;
+
+		// Returns the new mode.
+		conn.pragma_update_and_check(None, "journal_mode", "WAL", |row| row.get::<_, String>(0))?;
+
+		add_regexp_function(&conn)?;
+
+		Ok(conn)
+	}
+}
+
+
+/// Unique within the process.
+pub fn memory_database_name() -> String {
+	format!("clipboard-memory-{}-{}", std::process::id(), MEMORY_DATABASES.fetch_add(1, Ordering::Relaxed))
+}
+
+
+/// SQLite has the REGEXP operator but no function behind it. `X REGEXP Y` calls `regexp(Y, X)`.
+fn add_regexp_function(conn: &Connection) -> Result<()> {
+	conn.create_scalar_function(
+		"regexp",
+		2,
+		FunctionFlags::SQLITE_UTF8 | FunctionFlags::SQLITE_DETERMINISTIC,
+		|ctx| {
+			// Compiled once for each statement.
+			let regex = ctx.get_or_create_aux(0, |v| -> Result<Regex, Box<dyn std::error::Error + Send + Sync>> {
+				Ok(Regex::new(v.as_str()?)?)
+			})?;
+
+			Ok(match ctx.get_raw(1) {
+				ValueRef::Text(text) => regex.is_match(&String::from_utf8_lossy(text)),
+				_ => false
+			})
+		}
+	)?;
+
+	Ok(())
+}
+
+
+/// Returned to the pool once dropped.
+pub struct PooledConnection<'a> {
+	pool: &'a ConnectionPool,
+	conn: Option<Connection>,
+}
+
+impl<'a> Deref for PooledConnection<'a> {
+	type Target = Connection;
+
+	fn deref(&self) -> &Self::Target {
+		self.conn.as_ref().unwrap()
+	}
+}
+
+impl<'a> DerefMut for PooledConnection<'a> {
+	fn deref_mut(&mut self) -> &mut Self::Target {
+		self.conn.as_mut().unwrap()
+	}
+}
+
+impl<'a> Drop for PooledConnection<'a> {
+	fn drop(&mut self) {
+		if let Some(conn) = self.conn.take() {
+			let mut idle = self.pool.idle.lock().unwrap();
+
+			if idle.len() < MAX_IDLE_CONNECTIONS {
+				idle.push(conn);
+			}
+		}
+	}
+}
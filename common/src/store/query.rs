@@ -0,0 +1,524 @@
+use anyhow::Result;
+use chrono::{DateTime, Utc, TimeZone};
+use rusqlite::{Connection, params_from_iter};
+use rusqlite::types::Value;
+
+use super::{ReturnedItem, ReturnedItemType, SNIPPET_MATCH_END, SNIPPET_MATCH_START};
+use super::fuzzy::Candidates;
+use super::search::{SearchQuery, SearchTerm};
+
+
+// Item Query
+
+// Builds the SELECT for the list of copies. Each condition is added along with its values so user input is
+//      always bound as a parameter and never spliced into the SQL.
+
+
+/// FTS5 query matching every word of `value`, each as a prefix. None if there aren't any words.
+///
+/// Words are quoted so FTS5 operators and punctuation in them are matched literally.
+pub fn fts_query(value: &str) -> Option<String> {
+	let terms = value.split_whitespace()
+		.map(fts_word)
+		.collect::<Vec<_>>();
+
+	if terms.is_empty() {
+		None
+	} else {
+		Some(terms.join(" "))
+	}
+}
+
+fn fts_word(value: &str) -> String {
+	format!("{}*", fts_phrase(value))
+}
+
+fn fts_phrase(value: &str) -> String {
+	format!("\"{}\"", value.replace('"', "\"\""))
+}
+
+/// The FTS5 query limited to the columns masked data can be found by.
+fn masked_fts_query(query: &str) -> String {
+	format!("{{title note}} : ({})", query)
+}
+
+/// The full-text index ignores punctuation. Text without any words has to be found with LIKE.
+pub fn has_words(value: &str) -> bool {
+	value.chars().any(|v| v.is_alphanumeric())
+}
+
+/// Escapes `LIKE` wildcards. Used with `ESCAPE '\'`.
+pub fn escape_like(value: &str) -> String {
+	value.replace('\\', "\\\\")
+		.replace('%', "\\%")
+		.replace('_', "\\_")
+}
+
+
+/// Date of a recent, which is a Unix timestamp (ms).
+fn timestamp(value: i64) -> rusqlite::Result<DateTime<Utc>> {
+	Utc.timestamp_millis_opt(value)
+		.single()
+		.ok_or(rusqlite::Error::IntegralValueOutOfRange(1, value))
+}
+
+/// Tag names from the tags column, sorted ignoring case.
+fn split_tags(value: Option<String>) -> Vec<String> {
+	let mut tags = value.map(|v| v.split(TAG_SEPARATOR).map(String::from).collect::<Vec<_>>()).unwrap_or_default();
+
+	tags.sort_by_key(|v| v.to_lowercase());
+
+	tags
+}
+
+
+/// Words of context around the matches in a snippet.
+const SNIPPET_TOKENS: usize = 12;
+/// Separates the tag names in the tags column. Tag names can't have control characters.
+const TAG_SEPARATOR: char = '\u{1f}';
+
+
+#[derive(Default)]
+pub struct ItemQuery {
+	conditions: Vec<String>,
+	params: Vec<Value>,
+
+	/// Parameter number of the FTS5 query. Results are ranked by it.
+	search: Option<usize>,
+
+	/// Only the newest recent of each data.
+	distinct_data: bool,
+
+	/// Data in the trash instead of everything else.
+	trashed: bool,
+
+	/// Ordered by this before the newest recent.
+	order: Option<&'static str>,
+
+	limit: Option<usize>,
+	skip: usize,
+}
+
+impl ItemQuery {
+	pub fn new() -> Self {
+		Self::default()
+	}
+
+	pub fn starred(mut self) -> Self {
+		self.conditions.push(String::from("data.is_starred = 1"));
+		self
+	}
+
+	/// Text containing `value`. Case-insensitive for ASCII. Never masked text.
+	pub fn text_contains(self, value: &str) -> Self {
+		let pattern = format!("%{}%", escape_like(value));
+
+		self.condition(r#"data.is_masked = 0 AND data.text_data LIKE ? ESCAPE '\'"#, pattern)
+	}
+
+	/// In the order starred data was arranged. Data which was never arranged goes last.
+	pub fn in_star_order(mut self) -> Self {
+		self.order = Some("data.star_position IS NULL, data.star_position ASC");
+		self
+	}
+
+	/// Pinned data, last pinned first.
+	pub fn pinned(mut self) -> Self {
+		self.conditions.push(String::from("data.pinned_at IS NOT NULL"));
+		self.order = Some("data.pinned_at DESC");
+		self
+	}
+
+	pub fn trashed(mut self) -> Self {
+		self.trashed = true;
+		self
+	}
+
+	/// Text or HTML containing every word of `value`. Ordered by relevance (bm25) with a highlighted snippet.
+	///
+	/// Masked data only matches on its title and note, so searching can't be used to find out what it hides.
+	pub fn matching(self, value: &str) -> Self {
+		match fts_query(value) {
+			Some(query) => self.matching_fts(query),
+			None => self
+		}
+	}
+
+	fn matching_fts(mut self, query: String) -> Self {
+		self.params.push(Value::Text(masked_fts_query(&query)));
+		self.conditions.push(format!("(data.is_masked = 0 OR data.id IN (SELECT rowid FROM data_search WHERE data_search MATCH ?{}))", self.params.len()));
+
+		self.params.push(Value::Text(query));
+		self.search = Some(self.params.len());
+		self
+	}
+
+	/// Everything the parsed query asks for. Words and phrases are matched together through the full-text index.
+	pub fn search(mut self, query: &SearchQuery) -> Self {
+		let mut fts_terms = Vec::new();
+
+		for term in &query.terms {
+			match term {
+				SearchTerm::Word(word) if has_words(word) => fts_terms.push(fts_word(word)),
+				SearchTerm::Phrase(phrase) if has_words(phrase) => fts_terms.push(fts_phrase(phrase)),
+				term => self = self.term(term, false)
+			}
+		}
+
+		if fts_terms.is_empty() {
+			self
+		} else {
+			self.matching_fts(fts_terms.join(" "))
+		}
+	}
+
+	fn term(mut self, term: &SearchTerm, negated: bool) -> Self {
+		let sql = match term {
+			SearchTerm::Word(text) | SearchTerm::Phrase(text) if has_words(text) => {
+				let query = if let SearchTerm::Word(_) = term { fts_word(text) } else { fts_phrase(text) };
+
+				// Masked data only matches on its title and note.
+				let masked = self.bind("data.id IN (SELECT rowid FROM data_search WHERE data_search MATCH ?)", masked_fts_query(&query));
+				let unmasked = self.bind("data.id IN (SELECT rowid FROM data_search WHERE data_search MATCH ?)", query);
+
+				format!("IIF(data.is_masked, {}, {})", masked, unmasked)
+			}
+
+			SearchTerm::Word(text) | SearchTerm::Phrase(text) => self.bind(r#"(data.is_masked = 0 AND IFNULL(data.text_data, '') LIKE ? ESCAPE '\')"#, format!("%{}%", escape_like(text))),
+
+			&SearchTerm::Type(clip_type) => self.bind("data.type_of = ?", clip_type.type_of()),
+			&SearchTerm::Starred(starred) => self.bind("data.is_starred = ?", starred),
+			SearchTerm::App(app) => self.bind("IFNULL(recent.source_app, '') = ? COLLATE NOCASE", app.clone()),
+			&SearchTerm::After(date) => self.bind("recent.date >= ?", date as i64),
+			&SearchTerm::Before(date) => self.bind("recent.date < ?", date as i64),
+			&SearchTerm::Size(comparison, size) => self.bind(&format!("COALESCE(data.image_size, data.text_size, 0) {} ?", comparison.as_sql()), i64::try_from(size).unwrap_or(i64::MAX)),
+
+			SearchTerm::Not(term) => return self.term(term, !negated),
+		};
+
+		self.conditions.push(if negated { format!("NOT ({})", sql) } else { sql });
+		self
+	}
+
+	/// Text and files. Not images.
+	pub fn has_text(mut self) -> Self {
+		self.conditions.push(String::from("data.text_data IS NOT NULL"));
+		self
+	}
+
+	/// Text which could fuzzily match. Only narrows down what has to be scored. Never masked text.
+	pub fn fuzzy_candidates(mut self, candidates: &Candidates) -> Self {
+		self.conditions.push(String::from("data.is_masked = 0"));
+
+		if candidates.required == 0 {
+			return self;
+		}
+
+		let mut found = Vec::new();
+
+		for c in &candidates.chars {
+			self.params.push(Value::Text(format!("%{}%", escape_like(&c.to_string()))));
+			found.push(format!(r#"(data.text_data LIKE ?{} ESCAPE '\')"#, self.params.len()));
+		}
+
+		self.params.push(Value::Integer(candidates.required as i64));
+		self.conditions.push(format!("{} >= ?{}", found.join(" + "), self.params.len()));
+		self
+	}
+
+	/// Text matching the regex. Uses the REGEXP function registered on each connection. Never masked text.
+	pub fn regex(self, pattern: &str) -> Self {
+		self.condition("data.is_masked = 0 AND data.text_data REGEXP ?", pattern.to_string())
+	}
+
+	/// Data with the tag. Ignores case.
+	pub fn tagged(self, name: &str) -> Self {
+		self.condition("data.id IN (SELECT data_tags.data_id FROM data_tags INNER JOIN tags ON tags.id = data_tags.tag_id WHERE tags.name = ?)", name.to_string())
+	}
+
+	pub fn source_app(self, app: &str) -> Self {
+		self.condition("recent.source_app = ?", app.to_string())
+	}
+
+	pub fn distinct_data(mut self) -> Self {
+		self.distinct_data = true;
+		self
+	}
+
+	pub fn limit(mut self, limit: usize, skip: usize) -> Self {
+		self.limit = Some(limit);
+		self.skip = skip;
+		self
+	}
+
+	/// Add a condition containing a single `?` for `value`.
+	fn condition(mut self, sql: &str, value: impl Into<Value>) -> Self {
+		let sql = self.bind(sql, value);
+
+		self.conditions.push(sql);
+		self
+	}
+
+	/// Add `value` as the next parameter. Returns `sql` with its single `?` numbered for it.
+	fn bind(&mut self, sql: &str, value: impl Into<Value>) -> String {
+		self.params.push(value.into());
+		sql.replace('?', &format!("?{}", self.params.len()))
+	}
+
+	pub fn to_sql(&self) -> String {
+		// With MAX() SQLite takes the other recent columns from the row with the newest id.
+		let recent_id = if self.distinct_data { "MAX(recent.id)" } else { "recent.id" };
+
+		// Snippets are taken from the text, which masked data mustn't show.
+		let snippet = if self.search.is_some() { "IIF(data.is_masked, NULL, search.snippet)" } else { "NULL" };
+
+		let mut sql = String::new();
+
+		if let Some(param) = self.search {
+			// The FTS5 functions can't be used with GROUP BY. The LIMIT stops SQLite merging this into the outer query.
+			sql += &format!(r#"
+				WITH search AS (
+					SELECT
+						rowid,
+						rank,
+						snippet(data_search, 0, char({}), char({}), '…', {}) AS snippet
+					FROM data_search
+					WHERE data_search MATCH ?{}
+					LIMIT -1
+				)
+			"#, SNIPPET_MATCH_START as u32, SNIPPET_MATCH_END as u32, SNIPPET_TOKENS, param);
+		}
+
+		sql += &format!(r#"
+			SELECT
+				{},
+				recent.date,
+				data.is_starred,
+				data.type_of,
+				data.text_data,
+				data.image_thumb_data,
+				data.id,
+				data.is_masked,
+				recent.source_app,
+				{},
+				(
+					SELECT group_concat(tags.name, char({}))
+					FROM data_tags
+					INNER JOIN tags ON tags.id = data_tags.tag_id
+					WHERE data_tags.data_id = data.id
+				),
+				data.title,
+				data.note,
+				data.pinned_at IS NOT NULL
+			FROM recent
+			INNER JOIN data ON
+				data.id = recent.row_id
+		"#, recent_id, snippet, TAG_SEPARATOR as u32);
+
+		if self.search.is_some() {
+			sql += "INNER JOIN search ON search.rowid = data.id\n";
+		}
+
+		let trashed = if self.trashed { "data.deleted_at IS NOT NULL" } else { "data.deleted_at IS NULL" };
+
+		sql += &format!("WHERE {}\n", std::iter::once(trashed).chain(self.conditions.iter().map(String::as_str)).collect::<Vec<_>>().join(" AND "));
+
+		if self.distinct_data {
+			sql += "GROUP BY recent.row_id\n";
+		}
+
+		if self.search.is_some() {
+			// Lower is more relevant.
+			sql += "ORDER BY search.rank ASC, 1 DESC\n";
+		} else if let Some(order) = self.order {
+			sql += &format!("ORDER BY {}, 1 DESC\n", order);
+		} else {
+			sql += "ORDER BY 1 DESC\n";
+		}
+
+		if self.limit.is_some() {
+			sql += &format!("LIMIT ?{} OFFSET ?{}\n", self.params.len() + 1, self.params.len() + 2);
+		}
+
+		sql
+	}
+
+	/// Values for each parameter in `to_sql`.
+	pub fn params(&self) -> Vec<Value> {
+		let mut params = self.params.clone();
+
+		if let Some(limit) = self.limit {
+			params.push(Value::Integer(limit as i64));
+			params.push(Value::Integer(self.skip as i64));
+		}
+
+		params
+	}
+
+	pub fn query(&self, conn: &Connection) -> Result<Vec<ReturnedItem>> {
+		let mut stmt = conn.prepare(&self.to_sql())?;
+
+		let iter = stmt.query_map(
+			params_from_iter(self.params()),
+			|r| Ok(ReturnedItem {
+				recent_id: r.get(0)?,
+				timestamp: timestamp(r.get(1)?)?,
+				is_favorite: r.get(2)?,
+				value: ReturnedItemType::from_sql(r.get(3)?, r.get(4)?, r.get(5)?),
+				data_id: r.get(6)?,
+				is_masked: r.get(7)?,
+				source_app: r.get(8)?,
+				snippet: r.get(9)?,
+				tags: split_tags(r.get(10)?),
+				title: r.get(11)?,
+				note: r.get(12)?,
+				is_pinned: r.get(13)?,
+		}))?;
+
+		Ok(iter.collect::<std::result::Result<Vec<_>, _>>()?)
+	}
+}
+
+
+#[cfg(test)]
+mod tests {
+	use rusqlite::{Connection, params};
+
+	use super::*;
+	use super::super::sqlite::init_database;
+
+	static TEXTS: [&str; 10] = [
+		"it's here",
+		r#"say "hi" twice"#,
+		"100% sure",
+		"100 percent",
+		"snake_case",
+		"snakeXcase",
+		r"C:\path\file",
+		"?1 placeholder",
+		"-- not a comment",
+		"plain words",
+	];
+
+	fn database() -> Connection {
+		let conn = Connection::open_in_memory().unwrap();
+
+		init_database(&conn).unwrap();
+
+		for (index, text) in TEXTS.iter().enumerate() {
+			conn.execute(
+				r#"INSERT INTO data (hash, type_of, text_size, text_data) VALUES (?1, 0, ?2, ?3)"#,
+				params![ index.to_string(), text.len(), text ]
+			).unwrap();
+
+			conn.execute(
+				r#"INSERT INTO recent (row_id, date) VALUES (?1, ?2)"#,
+				params![ conn.last_insert_rowid(), index ]
+			).unwrap();
+		}
+
+		conn
+	}
+
+	fn texts(conn: &Connection, query: ItemQuery) -> Vec<String> {
+		let mut texts = query.query(conn).unwrap()
+			.into_iter()
+			.filter_map(|v| match v.value {
+				ReturnedItemType::Text(text) => Some(text),
+				_ => None
+			})
+			.collect::<Vec<_>>();
+
+		texts.sort();
+
+		texts
+	}
+
+	fn search(conn: &Connection, term: SearchTerm) -> Vec<String> {
+		texts(conn, ItemQuery::new().search(&SearchQuery { terms: vec![term] }).distinct_data())
+	}
+
+	#[test]
+	fn text_contains_is_literal() {
+		let conn = database();
+
+		let cases = [
+			("'", TEXTS[0]),
+			("\"hi\"", TEXTS[1]),
+			("%", TEXTS[2]),
+			("_", TEXTS[4]),
+			("\\", TEXTS[6]),
+			(r"\path\", TEXTS[6]),
+			("?1", TEXTS[7]),
+			("--", TEXTS[8]),
+		];
+
+		for (value, expected) in cases {
+			assert_eq!(texts(&conn, ItemQuery::new().text_contains(value)), vec![expected.to_string()], "{:?}", value);
+		}
+	}
+
+	#[test]
+	fn text_contains_without_matches() {
+		let conn = database();
+
+		for value in ["%%", "__", "'; DROP TABLE data; --", "\\%"] {
+			assert!(texts(&conn, ItemQuery::new().text_contains(value)).is_empty(), "{:?}", value);
+		}
+
+		assert_eq!(texts(&conn, ItemQuery::new()).len(), TEXTS.len());
+	}
+
+	#[test]
+	fn search_punctuation_is_literal() {
+		let conn = database();
+
+		// Terms without any words are matched with LIKE.
+		let cases = [
+			("'", TEXTS[0]),
+			("\"", TEXTS[1]),
+			("%", TEXTS[2]),
+			("_", TEXTS[4]),
+			("\\", TEXTS[6]),
+		];
+
+		for (value, expected) in cases {
+			assert_eq!(search(&conn, SearchTerm::Word(value.to_string())), vec![expected.to_string()], "{:?}", value);
+		}
+	}
+
+	#[test]
+	fn search_quotes_in_words() {
+		let conn = database();
+
+		assert_eq!(search(&conn, SearchTerm::Word(String::from("it's"))), vec![TEXTS[0].to_string()]);
+		assert_eq!(search(&conn, SearchTerm::Phrase(String::from(r#"say "hi""#))), vec![TEXTS[1].to_string()]);
+
+		// FTS5 syntax is matched as words.
+		assert!(search(&conn, SearchTerm::Word(String::from("NEAR(\"a\" OR *"))).is_empty());
+		assert!(search(&conn, SearchTerm::Not(Box::new(SearchTerm::Word(String::from("'"))))).iter().all(|v| v != TEXTS[0]));
+	}
+
+	#[test]
+	fn fuzzy_candidates_keep_every_match() {
+		let conn = database();
+
+		for pattern in ["snke", "snakecase", "snkaexcse", "100%", r"c:\p", "plain_words", "zzzz", "héré"] {
+			let candidates = Candidates::new(pattern);
+
+			let matching = TEXTS.iter()
+				.filter(|text| super::super::fuzzy::fuzzy_match(pattern, text).is_some())
+				.map(|v| v.to_string())
+				.collect::<Vec<_>>();
+
+			let found = texts(&conn, ItemQuery::new().fuzzy_candidates(&candidates));
+
+			assert!(matching.iter().all(|v| found.contains(v)), "{:?} {:?}", pattern, found);
+
+			let mut expected = TEXTS.iter().filter(|v| candidates.could_match(v)).map(|v| v.to_string()).collect::<Vec<_>>();
+			expected.sort();
+
+			assert_eq!(found, expected, "{:?}", pattern);
+		}
+	}
+}
@@ -0,0 +1,308 @@
+use std::fmt;
+
+use anyhow::Result;
+use chrono::Utc;
+
+use crate::config::ConfigRetention;
+
+use super::Store;
+
+
+// Retention
+
+// Purges the oldest copies which aren't starred or pinned once a limit in `ConfigRetention` is reached.
+// Age and the item limit go by when each was last copied. The size limit deletes the oldest copies until
+//      everything stored fits, though starred and pinned copies still count towards it.
+// Copies in the trash are left for the trash to purge. They don't take up an item and aren't too old, but they're
+//      the first to go for the size limit.
+
+
+/// Stored size of a single copy. Returned newest first by [`Store::get_data_usage`].
+#[derive(Debug, Clone)]
+pub struct DataUsage {
+	pub data_id: usize,
+	pub is_starred: bool,
+	pub is_pinned: bool,
+	/// In the trash.
+	pub is_deleted: bool,
+	/// Unix timestamp (ms) of the newest recent. 0 if there aren't any.
+	pub last_copied: usize,
+	/// Text, HTML, image, thumbnail, other formats and file snapshots (bytes).
+	pub size: usize,
+}
+
+
+/// What a retention pass purged.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct RetentionReport {
+	pub over_max_items: usize,
+	pub over_max_age: usize,
+	pub over_max_size: usize,
+	/// Bytes of the deleted copies.
+	pub bytes_freed: usize,
+}
+
+impl RetentionReport {
+	pub fn removed(&self) -> usize {
+		self.over_max_items + self.over_max_age + self.over_max_size
+	}
+}
+
+impl fmt::Display for RetentionReport {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		write!(
+			f,
+			"Removed {} copies ({} over the item limit, {} too old, {} over the size limit) freeing {} bytes",
+			self.removed(), self.over_max_items, self.over_max_age, self.over_max_size, self.bytes_freed
+		)
+	}
+}
+
+
+/// Which limit a copy is purged for.
+#[derive(Clone, Copy)]
+enum Reason {
+	Items,
+	Age,
+	Size,
+}
+
+
+pub fn apply_retention<S: Store + ?Sized>(store: &S, policy: &ConfigRetention) -> Result<RetentionReport> {
+	let mut report = RetentionReport::default();
+
+	if !policy.is_enabled() {
+		return Ok(report);
+	}
+
+	let usage = store.get_data_usage()?;
+
+	let oldest_allowed = (Utc::now().timestamp_millis() as usize).saturating_sub(policy.max_age_days * 24 * 60 * 60 * 1000);
+
+	let mut total_size = usage.iter().map(|v| v.size).sum::<usize>();
+
+	// Newest first.
+	let mut kept = Vec::new();
+	let mut trashed = Vec::new();
+	let mut removing = Vec::new();
+
+	for item in usage.iter().filter(|v| !v.is_starred && !v.is_pinned) {
+		if item.is_deleted {
+			trashed.push(item);
+		} else if policy.max_items != 0 && kept.len() >= policy.max_items {
+			removing.push((item, Reason::Items));
+		} else if policy.max_age_days != 0 && item.last_copied < oldest_allowed {
+			removing.push((item, Reason::Age));
+		} else {
+			kept.push(item);
+		}
+	}
+
+	total_size -= removing.iter().map(|(v, _)| v.size).sum::<usize>();
+
+	if policy.max_total_size != 0 {
+		let max_total_size = policy.max_total_size * 1000 * 1000; // B -> KB -> MB
+
+		for item in trashed.into_iter().rev().chain(kept.into_iter().rev()) {
+			if total_size <= max_total_size {
+				break;
+			}
+
+			total_size -= item.size;
+			removing.push((item, Reason::Size));
+		}
+	}
+
+	for (item, reason) in removing {
+		store.purge(item.data_id)?;
+
+		match reason {
+			Reason::Items => report.over_max_items += 1,
+			Reason::Age => report.over_max_age += 1,
+			Reason::Size => report.over_max_size += 1,
+		}
+
+		report.bytes_freed += item.size;
+	}
+
+	Ok(report)
+}
+
+
+#[cfg(test)]
+mod tests {
+	use crate::clipboard::Selection;
+	use crate::config::Config;
+
+	use super::*;
+	use super::super::{CopySource, MemoryStore, ReturnedItemType, StorageQuery};
+
+	const DAY: usize = 24 * 60 * 60 * 1000;
+
+	fn add(store: &MemoryStore, text: &str) -> usize {
+		let source = CopySource {
+			selection: Selection::Clipboard,
+			app: None
+		};
+
+		store.add_text(text.to_string(), None, &source, &Config::default()).unwrap().unwrap()
+	}
+
+	/// Text which is `size` bytes, starting with `name`.
+	fn add_sized(store: &MemoryStore, name: &str, size: usize) -> usize {
+		add(store, &format!("{}{}", name, "-".repeat(size - name.len())))
+	}
+
+	fn texts(store: &MemoryStore, query: StorageQuery) -> Vec<String> {
+		store.query(query).unwrap()
+			.into_iter()
+			.filter_map(|v| match v.value {
+				ReturnedItemType::Text(text) => Some(text.trim_end_matches('-').to_string()),
+				_ => None
+			})
+			.collect()
+	}
+
+	fn recent(store: &MemoryStore) -> Vec<String> {
+		texts(store, StorageQuery::Recent { limit: 100, skip: 0 })
+	}
+
+	fn policy(max_items: usize, max_age_days: usize, max_total_size: usize) -> ConfigRetention {
+		ConfigRetention {
+			max_items,
+			max_age_days,
+			max_total_size,
+			..Default::default()
+		}
+	}
+
+
+	#[test]
+	fn disabled_does_nothing() {
+		let store = MemoryStore::default();
+
+		add(&store, "a");
+
+		assert_eq!(apply_retention(&store, &policy(0, 0, 0)).unwrap(), RetentionReport::default());
+		assert_eq!(recent(&store), vec!["a"]);
+	}
+
+	#[test]
+	fn item_limit_keeps_the_newest() {
+		let store = MemoryStore::default();
+
+		for text in ["a", "bb", "c", "d"] {
+			add(&store, text);
+		}
+
+		let report = apply_retention(&store, &policy(2, 0, 0)).unwrap();
+
+		assert_eq!(report, RetentionReport { over_max_items: 2, bytes_freed: 3, ..Default::default() });
+		assert_eq!(recent(&store), vec!["d", "c"]);
+	}
+
+	#[test]
+	fn age_limit_goes_by_the_last_copy() {
+		let store = MemoryStore::default();
+
+		let old = add(&store, "old");
+		add(&store, "new");
+
+		let now = Utc::now().timestamp_millis() as usize;
+
+		store.set_copied_at(old, now - 31 * DAY);
+
+		let report = apply_retention(&store, &policy(0, 30, 0)).unwrap();
+
+		assert_eq!(report, RetentionReport { over_max_age: 1, bytes_freed: 3, ..Default::default() });
+		assert_eq!(recent(&store), vec!["new"]);
+	}
+
+	#[test]
+	fn size_limit_removes_the_oldest() {
+		let store = MemoryStore::default();
+
+		for name in ["a", "b", "c"] {
+			add_sized(&store, name, 400_000);
+		}
+
+		let report = apply_retention(&store, &policy(0, 0, 1)).unwrap();
+
+		assert_eq!(report, RetentionReport { over_max_size: 1, bytes_freed: 400_000, ..Default::default() });
+		assert_eq!(recent(&store), vec!["c", "b"]);
+	}
+
+	#[test]
+	fn limits_are_counted_separately() {
+		let store = MemoryStore::default();
+
+		let old = add_sized(&store, "old", 100_000);
+
+		for name in ["a", "b", "c"] {
+			add_sized(&store, name, 400_000);
+		}
+
+		store.set_copied_at(old, Utc::now().timestamp_millis() as usize - 31 * DAY);
+
+		let report = apply_retention(&store, &policy(0, 30, 1)).unwrap();
+
+		// Removing the old copy isn't enough for the size limit.
+		assert_eq!(report, RetentionReport { over_max_items: 0, over_max_age: 1, over_max_size: 1, bytes_freed: 500_000 });
+		assert_eq!(report.removed(), 2);
+		assert_eq!(recent(&store), vec!["c", "b"]);
+	}
+
+	#[test]
+	fn starred_and_pinned_are_never_removed() {
+		let store = MemoryStore::default();
+
+		let starred = add_sized(&store, "starred", 400_000);
+		let pinned = add_sized(&store, "pinned", 400_000);
+		add_sized(&store, "a", 400_000);
+		add_sized(&store, "b", 400_000);
+
+		store.set_favorite(starred, true).unwrap();
+		store.set_pinned(pinned, true).unwrap();
+		store.set_copied_at(starred, 0);
+
+		let report = apply_retention(&store, &policy(1, 30, 1)).unwrap();
+
+		// Still over the size limit but there's nothing left which can be removed.
+		assert_eq!(report, RetentionReport { over_max_items: 1, over_max_size: 1, bytes_freed: 800_000, ..Default::default() });
+		assert_eq!(texts(&store, StorageQuery::Favorites), vec!["starred"]);
+		assert_eq!(texts(&store, StorageQuery::Pinned), vec!["pinned"]);
+	}
+
+	#[test]
+	fn trash_doesnt_take_up_items() {
+		let store = MemoryStore::default();
+
+		add(&store, "a");
+		add(&store, "b");
+		let trashed = add(&store, "trashed");
+
+		store.delete(trashed).unwrap();
+		store.set_copied_at(trashed, 0);
+
+		assert_eq!(apply_retention(&store, &policy(2, 30, 0)).unwrap(), RetentionReport::default());
+		assert_eq!(recent(&store), vec!["b", "a"]);
+		assert_eq!(texts(&store, StorageQuery::Trash), vec!["trashed"]);
+	}
+
+	#[test]
+	fn trash_goes_first_for_size() {
+		let store = MemoryStore::default();
+
+		add_sized(&store, "a", 400_000);
+		add_sized(&store, "b", 400_000);
+		let trashed = add_sized(&store, "trashed", 400_000);
+
+		store.delete(trashed).unwrap();
+
+		let report = apply_retention(&store, &policy(0, 0, 1)).unwrap();
+
+		assert_eq!(report, RetentionReport { over_max_size: 1, bytes_freed: 400_000, ..Default::default() });
+		assert_eq!(recent(&store), vec!["b", "a"]);
+		assert!(texts(&store, StorageQuery::Trash).is_empty());
+	}
+}
@@ -0,0 +1,369 @@
+use anyhow::{Result, bail};
+use chrono::{Local, NaiveDate, TimeZone};
+
+
+// Search Query Language
+
+// Space separated terms which all have to match:
+//   word              Text containing the word (prefix)
+//   "exact phrase"    Text containing the words in order
+//   type:text         Type of copy. text, image or files
+//   starred:yes       Starred or not. yes/no/true/false
+//   app:firefox       Copied from the application. Quote names with spaces, app:"Google Chrome"
+//   after:2026-01-01  Copied on or after the day (local time)
+//   before:2026-01-01 Copied before the day (local time)
+//   size:>10kb        Size compared with <, <=, =, >=, >. Units are b, kb and mb. No operator means =
+// Any term can be excluded by starting it with a -
+// Unknown keys are searched for as words so things like urls still work.
+
+
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct SearchQuery {
+	pub terms: Vec<SearchTerm>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SearchTerm {
+	Word(String),
+	Phrase(String),
+	Type(ClipType),
+	Starred(bool),
+	App(String),
+	/// Unix timestamp (ms)
+	After(usize),
+	/// Unix timestamp (ms)
+	Before(usize),
+	/// Size in bytes.
+	Size(Comparison, usize),
+	Not(Box<SearchTerm>),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ClipType {
+	Text,
+	Image,
+	Files,
+}
+
+impl ClipType {
+	/// data.type_of
+	pub fn type_of(self) -> u8 {
+		match self {
+			Self::Text => 0,
+			Self::Image => 1,
+			Self::Files => 2,
+		}
+	}
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Comparison {
+	Less,
+	LessOrEqual,
+	Equal,
+	GreaterOrEqual,
+	Greater,
+}
+
+impl Comparison {
+	pub fn as_sql(self) -> &'static str {
+		match self {
+			Self::Less => "<",
+			Self::LessOrEqual => "<=",
+			Self::Equal => "=",
+			Self::GreaterOrEqual => ">=",
+			Self::Greater => ">",
+		}
+	}
+
+	pub fn compare(self, left: usize, right: usize) -> bool {
+		match self {
+			Self::Less => left < right,
+			Self::LessOrEqual => left <= right,
+			Self::Equal => left == right,
+			Self::GreaterOrEqual => left >= right,
+			Self::Greater => left > right,
+		}
+	}
+}
+
+
+impl SearchQuery {
+	pub fn parse(value: &str) -> Result<Self> {
+		let terms = tokenize(value)?
+			.into_iter()
+			.map(parse_term)
+			.collect::<Result<Vec<_>>>()?;
+
+		Ok(Self { terms })
+	}
+
+	pub fn is_empty(&self) -> bool {
+		self.terms.is_empty()
+	}
+}
+
+
+/// A term as it was typed.
+#[derive(Debug, PartialEq, Eq)]
+struct Token {
+	negated: bool,
+	key: Option<String>,
+	value: String,
+	/// The value was in quotes.
+	quoted: bool,
+}
+
+fn tokenize(value: &str) -> Result<Vec<Token>> {
+	let mut tokens = Vec::new();
+	let mut chars = value.chars().peekable();
+
+	loop {
+		while chars.next_if(|v| v.is_whitespace()).is_some() {}
+
+		if chars.peek().is_none() {
+			break;
+		}
+
+		let negated = chars.next_if_eq(&'-').is_some();
+
+		let mut key = None;
+		let mut value = String::new();
+		let mut quoted = false;
+
+		while let Some(c) = chars.next() {
+			match c {
+				'"' => {
+					quoted = true;
+
+					loop {
+						match chars.next() {
+							Some('"') => break,
+							Some(c) => value.push(c),
+							None => bail!("Missing a closing quote"),
+						}
+					}
+				}
+
+				':' if key.is_none() && !quoted && is_key(&value) => key = Some(std::mem::take(&mut value).to_lowercase()),
+
+				c if c.is_whitespace() => break,
+
+				c => value.push(c),
+			}
+		}
+
+		// A lone "-" is just a word.
+		if negated && key.is_none() && value.is_empty() && !quoted {
+			value.push('-');
+			tokens.push(Token { negated: false, key, value, quoted });
+			continue;
+		}
+
+		tokens.push(Token { negated, key, value, quoted });
+	}
+
+	Ok(tokens)
+}
+
+fn is_key(value: &str) -> bool {
+	matches!(value.to_lowercase().as_str(), "type" | "starred" | "app" | "after" | "before" | "size")
+}
+
+fn parse_term(token: Token) -> Result<SearchTerm> {
+	let term = match token.key.as_deref() {
+		None if token.quoted => SearchTerm::Phrase(token.value),
+		None => SearchTerm::Word(token.value),
+
+		Some("type") => SearchTerm::Type(match token.value.to_lowercase().as_str() {
+			"text" => ClipType::Text,
+			"image" | "img" => ClipType::Image,
+			"file" | "files" => ClipType::Files,
+			v => bail!("Unknown type {:?}. Expected text, image or files", v)
+		}),
+
+		Some("starred") => SearchTerm::Starred(match token.value.to_lowercase().as_str() {
+			"yes" | "true" => true,
+			"no" | "false" => false,
+			v => bail!("Unknown starred value {:?}. Expected yes or no", v)
+		}),
+
+		Some("app") => SearchTerm::App(token.value),
+
+		Some("after") => SearchTerm::After(parse_date(&token.value)?),
+		Some("before") => SearchTerm::Before(parse_date(&token.value)?),
+
+		Some("size") => {
+			let (comparison, size) = parse_size(&token.value)?;
+			SearchTerm::Size(comparison, size)
+		}
+
+		Some(key) => bail!("Unknown filter {:?}", key)
+	};
+
+	if token.negated {
+		Ok(SearchTerm::Not(Box::new(term)))
+	} else {
+		Ok(term)
+	}
+}
+
+/// Start of the day in local time.
+fn parse_date(value: &str) -> Result<usize> {
+	let date = match NaiveDate::parse_from_str(value, "%Y-%m-%d") {
+		Ok(v) => v,
+		Err(_) => bail!("Invalid date {:?}. Expected YYYY-MM-DD", value)
+	};
+
+	match date.and_hms_opt(0, 0, 0).and_then(|v| Local.from_local_datetime(&v).earliest()) {
+		Some(v) => Ok(v.timestamp_millis() as usize),
+		None => bail!("Invalid date {:?}", value)
+	}
+}
+
+fn parse_size(value: &str) -> Result<(Comparison, usize)> {
+	let (comparison, value) = [
+		("<=", Comparison::LessOrEqual),
+		(">=", Comparison::GreaterOrEqual),
+		("<", Comparison::Less),
+		(">", Comparison::Greater),
+		("=", Comparison::Equal),
+	].into_iter()
+		.find_map(|(op, comparison)| Some((comparison, value.strip_prefix(op)?)))
+		.unwrap_or((Comparison::Equal, value));
+
+	let value = value.to_lowercase();
+	let split = value.find(|v: char| !v.is_ascii_digit()).unwrap_or(value.len());
+
+	let (number, unit) = value.split_at(split);
+
+	let multiplier = match unit {
+		"" | "b" => 1,
+		"k" | "kb" => 1000,
+		"m" | "mb" => 1000 * 1000,
+		_ => bail!("Unknown size unit {:?}. Expected b, kb or mb", unit)
+	};
+
+	let size = match number.parse::<usize>() {
+		Ok(v) => v,
+		Err(_) => bail!("Invalid size {:?}", value)
+	};
+
+	// Compared as an i64 in SQL.
+	match size.checked_mul(multiplier).filter(|v| i64::try_from(*v).is_ok()) {
+		Some(v) => Ok((comparison, v)),
+		None => bail!("Size {:?} is too large", value)
+	}
+}
+
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	fn parse(value: &str) -> Vec<SearchTerm> {
+		SearchQuery::parse(value).unwrap().terms
+	}
+
+	fn start_of_day(year: i32, month: u32, day: u32) -> usize {
+		Local.with_ymd_and_hms(year, month, day, 0, 0, 0).earliest().unwrap().timestamp_millis() as usize
+	}
+
+	fn word(value: &str) -> SearchTerm {
+		SearchTerm::Word(value.to_string())
+	}
+
+	#[test]
+	fn parses_every_filter() {
+		assert_eq!(
+			parse(r#"type:image starred:yes app:firefox after:2026-01-01 size:>10kb "exact phrase" -exclude"#),
+			vec![
+				SearchTerm::Type(ClipType::Image),
+				SearchTerm::Starred(true),
+				SearchTerm::App(String::from("firefox")),
+				SearchTerm::After(start_of_day(2026, 1, 1)),
+				SearchTerm::Size(Comparison::Greater, 10_000),
+				SearchTerm::Phrase(String::from("exact phrase")),
+				SearchTerm::Not(Box::new(word("exclude"))),
+			]
+		);
+	}
+
+	#[test]
+	fn keys_ignore_case() {
+		assert_eq!(parse("TYPE:Text Starred:NO before:2026-02-01"), vec![
+			SearchTerm::Type(ClipType::Text),
+			SearchTerm::Starred(false),
+			SearchTerm::Before(start_of_day(2026, 2, 1)),
+		]);
+	}
+
+	#[test]
+	fn quoted_values() {
+		assert_eq!(parse(r#"app:"Google Chrome""#), vec![SearchTerm::App(String::from("Google Chrome"))]);
+		assert_eq!(parse(r#"-"two words""#), vec![SearchTerm::Not(Box::new(SearchTerm::Phrase(String::from("two words"))))]);
+	}
+
+	#[test]
+	fn negated_filter() {
+		assert_eq!(parse("-type:image"), vec![SearchTerm::Not(Box::new(SearchTerm::Type(ClipType::Image)))]);
+	}
+
+	#[test]
+	fn lone_dash_is_a_word() {
+		assert_eq!(parse("a - b"), vec![word("a"), word("-"), word("b")]);
+	}
+
+	#[test]
+	fn unknown_keys_are_words() {
+		assert_eq!(parse("http://x"), vec![word("http://x")]);
+		assert_eq!(parse("note:todo"), vec![word("note:todo")]);
+	}
+
+	#[test]
+	fn empty_query() {
+		assert!(SearchQuery::parse("   ").unwrap().is_empty());
+	}
+
+	#[test]
+	fn size_operators_and_units() {
+		let cases = [
+			("size:10", Comparison::Equal, 10),
+			("size:=10b", Comparison::Equal, 10),
+			("size:<10", Comparison::Less, 10),
+			("size:<=10k", Comparison::LessOrEqual, 10_000),
+			("size:>=2kb", Comparison::GreaterOrEqual, 2_000),
+			("size:>3m", Comparison::Greater, 3_000_000),
+			("size:<4MB", Comparison::Less, 4_000_000),
+		];
+
+		for (value, comparison, size) in cases {
+			assert_eq!(parse(value), vec![SearchTerm::Size(comparison, size)], "{}", value);
+		}
+	}
+
+	#[test]
+	fn sizes_past_i64_are_rejected() {
+		assert_eq!(parse("size:<9223372036854775807"), vec![SearchTerm::Size(Comparison::Less, i64::MAX as usize)]);
+
+		for value in ["size:18446744073709551mb", "size:9223372036854775808", "size:9223372036854776kb", "size:99999999999999999999"] {
+			assert!(SearchQuery::parse(value).is_err(), "{}", value);
+		}
+	}
+
+	#[test]
+	fn errors() {
+		for value in [
+			r#""unclosed"#,
+			r#"app:"Google"#,
+			"after:2026-13-01",
+			"before:yesterday",
+			"size:10gb",
+			"size:>kb",
+			"type:video",
+			"starred:maybe",
+		] {
+			assert!(SearchQuery::parse(value).is_err(), "{}", value);
+		}
+	}
+}
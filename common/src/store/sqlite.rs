@@ -0,0 +1,1133 @@
+use std::collections::HashSet;
+use std::io::{Read, Write};
+use std::path::Path;
+
+use anyhow::{Result, bail};
+use chrono::Utc;
+use regex::Regex;
+use rusqlite::{Connection, DatabaseName, params, OptionalExtension, Transaction, TransactionBehavior};
+
+use crate::config::{Config, SensitiveAction};
+
+use super::{BlobStore, CopiedData, CopiedFile, CopiedFormat, CopySource, DataRevision, DataUsage, LastCopied, ReturnedItem, ReturnedItemType, StorageQuery, Store, Tag};
+use super::{check_edited_text, expires_at, fuzzy, hash_data, hash_files, optional_text, privacy_action, regex_snippet, should_record_recopy};
+use super::migrations;
+use super::pool::{ConnectionPool, MEMORY_PATH, memory_database_name};
+use super::query::{has_words, ItemQuery};
+
+
+/// Size of each write when streaming an image into its blob.
+const BLOB_CHUNK_SIZE: usize = 64 * 1024;
+
+
+/// Blob files touched by a write, so the blob directory can follow whether it was committed.
+#[derive(Default)]
+struct BlobChanges {
+	/// Deleted again if the write is rolled back.
+	written: Vec<String>,
+	/// Deleted once the write is committed.
+	released: Vec<String>,
+}
+
+
+pub struct SqliteStore {
+	pool: ConnectionPool,
+	/// Images and file snapshots over the blob threshold.
+	blobs: BlobStore,
+	/// Opened with [`MEMORY_PATH`]. The blob directory is removed along with it.
+	is_memory: bool,
+}
+
+impl Drop for SqliteStore {
+	fn drop(&mut self) {
+		if self.is_memory {
+			if let Err(e) = self.blobs.remove_all() {
+				log::error!(target: "clipboard_listener", "Removing In-Memory Blobs: {:?}", e);
+			}
+		}
+	}
+}
+
+impl SqliteStore {
+	/// [`MEMORY_PATH`] opens an empty database in memory, with its blobs in a temporary directory.
+	pub fn open(path: &str) -> Result<Self> {
+		let (pool, blobs_root, is_memory) = if path == MEMORY_PATH {
+			(ConnectionPool::memory(), std::env::temp_dir().join(memory_database_name()), true)
+		} else {
+			(ConnectionPool::new(path), Path::new(path).with_file_name("blobs"), false)
+		};
+
+		init_database(&*pool.get()?)?;
+
+		Ok(Self {
+			pool,
+			blobs: BlobStore::new(blobs_root),
+			is_memory
+		})
+	}
+
+	/// Delete every file in the blob directory which the database doesn't reference. Returns how many were deleted.
+	pub fn collect_garbage(&self) -> Result<usize> {
+		let mut conn = self.pool.get()?;
+
+		// Holds the write lock so nothing can store a blob in between.
+		let tx = conn.transaction_with_behavior(TransactionBehavior::Immediate)?;
+
+		let referenced = {
+			let mut stmt = tx.prepare(r#"SELECT hash FROM blobs"#)?;
+
+			let iter = stmt.query_map([], |v| v.get::<_, String>(0))?;
+
+			iter.collect::<std::result::Result<HashSet<_>, _>>()?
+		};
+
+		let deleted = self.blobs.remove_unreferenced(&referenced)?;
+
+		tx.commit()?;
+
+		Ok(deleted)
+	}
+
+
+	/// Run `f` in a transaction which takes the write lock up front, so nothing else can write between its reads and writes.
+	/// Blob files are only deleted once it's committed, and the ones it wrote are deleted again if it isn't.
+	fn write<T>(&self, f: impl FnOnce(&Transaction, &mut BlobChanges) -> Result<T>) -> Result<T> {
+		let mut conn = self.pool.get()?;
+
+		let mut changes = BlobChanges::default();
+
+		let result = conn.transaction_with_behavior(TransactionBehavior::Immediate)
+			.map_err(anyhow::Error::from)
+			.and_then(|tx| {
+				let value = f(&tx, &mut changes)?;
+				tx.commit()?;
+				Ok(value)
+			});
+
+		let unreferenced = if result.is_ok() { changes.released } else { changes.written };
+
+		if !unreferenced.is_empty() {
+			if let Err(e) = self.remove_blobs(&mut conn, &unreferenced) {
+				log::error!(target: "clipboard_listener", "Removing Blobs: {:?}", e);
+			}
+		}
+
+		result
+	}
+
+	/// Delete blob files which the database doesn't reference. Checked under the write lock, as another write could
+	///      have stored the same file since.
+	fn remove_blobs(&self, conn: &mut Connection, hashes: &[String]) -> Result<()> {
+		let tx = conn.transaction_with_behavior(TransactionBehavior::Immediate)?;
+
+		for hash in hashes {
+			let referenced: bool = tx.query_row(
+				r#"SELECT EXISTS(SELECT 1 FROM blobs WHERE hash = ?1)"#,
+				params![hash],
+				|v| v.get(0)
+			)?;
+
+			if !referenced {
+				self.blobs.remove(hash)?;
+			}
+		}
+
+		Ok(tx.commit()?)
+	}
+
+	/// Images are written in chunks through incremental blob I/O instead of binding the whole image as a parameter.
+	fn write_image(&self, tx: &Transaction, data_id: usize, image_data: &[u8]) -> Result<()> {
+		tx.execute(
+			r#"INSERT INTO data_images (id, image_data) VALUES (?1, zeroblob(?2))"#,
+			params![ data_id, image_data.len() ]
+		)?;
+
+		let mut blob = tx.blob_open(DatabaseName::Main, "data_images", "image_data", data_id as i64, false)?;
+
+		for chunk in image_data.chunks(BLOB_CHUNK_SIZE) {
+			blob.write_all(chunk)?;
+		}
+
+		Ok(())
+	}
+
+	/// Store `data` in the blob directory, or add a reference to it if it's already there.
+	fn acquire_blob(&self, tx: &Transaction, changes: &mut BlobChanges, hash: &str, data: &[u8]) -> Result<()> {
+		let updated = tx.execute(
+			r#"UPDATE blobs SET ref_count = ref_count + 1 WHERE hash = ?1"#,
+			params![hash]
+		)?;
+
+		if updated == 0 {
+			tx.execute(
+				r#"INSERT INTO blobs (hash, size, ref_count) VALUES (?1, ?2, 1)"#,
+				params![ hash, data.len() ]
+			)?;
+
+			changes.written.push(hash.to_string());
+
+			self.blobs.write(hash, data)?;
+		}
+
+		// Referenced again, so it has to stay.
+		changes.released.retain(|v| v != hash);
+
+		Ok(())
+	}
+
+	/// Remove a reference to a blob. The file is deleted once nothing references it.
+	fn release_blob(&self, tx: &Transaction, changes: &mut BlobChanges, hash: &str) -> Result<()> {
+		tx.execute(
+			r#"UPDATE blobs SET ref_count = ref_count - 1 WHERE hash = ?1"#,
+			params![hash]
+		)?;
+
+		let removed = tx.execute(
+			r#"DELETE FROM blobs WHERE hash = ?1 AND ref_count <= 0"#,
+			params![hash]
+		)?;
+
+		if removed != 0 {
+			changes.released.push(hash.to_string());
+		}
+
+		Ok(())
+	}
+
+	/// Blobs referenced by the image and files of the data.
+	fn get_blob_hashes(&self, conn: &Connection, data_id: usize) -> Result<Vec<String>> {
+		let mut stmt = conn.prepare(r#"
+			SELECT blob_hash FROM data_images WHERE id = ?1 AND blob_hash IS NOT NULL
+			UNION ALL
+			SELECT blob_hash FROM files WHERE row_id = ?1 AND blob_hash IS NOT NULL
+		"#)?;
+
+		let iter = stmt.query_map(params![data_id], |v| v.get(0))?;
+
+		Ok(iter.collect::<std::result::Result<Vec<_>, _>>()?)
+	}
+
+	/// Returns the tag's id, creating it if it doesn't exist yet.
+	fn insert_tag(&self, tx: &Transaction, name: &str) -> Result<usize> {
+		let name = Tag::parse_name(name)?;
+
+		tx.execute(
+			r#"INSERT OR IGNORE INTO tags (name) VALUES (?1)"#,
+			params![name]
+		)?;
+
+		Ok(tx.query_row(
+			r#"SELECT id FROM tags WHERE name = ?1"#,
+			params![name],
+			|v| v.get(0)
+		)?)
+	}
+
+	/// Data ids returned by `sql`, which takes one parameter.
+	fn get_ids(&self, conn: &Connection, sql: &str, value: usize) -> Result<Vec<usize>> {
+		let mut stmt = conn.prepare(sql)?;
+
+		let iter = stmt.query_map(params![value], |v| v.get(0))?;
+
+		Ok(iter.collect::<std::result::Result<Vec<_>, _>>()?)
+	}
+
+	/// Delete the data and everything which belongs to it.
+	fn purge_data(&self, tx: &Transaction, changes: &mut BlobChanges, data_id: usize) -> Result<usize> {
+		for hash in self.get_blob_hashes(tx, data_id)? {
+			self.release_blob(tx, changes, &hash)?;
+		}
+
+		let deleted = tx.execute(
+			r#"DELETE FROM data WHERE id = ?1"#,
+			params![data_id]
+		)?;
+
+		tx.execute(
+			r#"DELETE FROM files WHERE row_id = ?1"#,
+			params![data_id]
+		)?;
+
+		tx.execute(
+			r#"DELETE FROM data_formats WHERE row_id = ?1"#,
+			params![data_id]
+		)?;
+
+		tx.execute(
+			r#"DELETE FROM data_images WHERE id = ?1"#,
+			params![data_id]
+		)?;
+
+		tx.execute(
+			r#"DELETE FROM data_tags WHERE data_id = ?1"#,
+			params![data_id]
+		)?;
+
+		tx.execute(
+			r#"DELETE FROM data_revisions WHERE data_id = ?1"#,
+			params![data_id]
+		)?;
+
+		tx.execute(
+			r#"DELETE FROM recent WHERE row_id = ?1"#,
+			params![data_id]
+		)?;
+
+		Ok(deleted)
+	}
+
+
+	/// Called when something we've already stored is copied again.
+	fn recopied(&self, tx: &Transaction, data_id: usize, source: &CopySource) -> Result<()> {
+		// Copying something in the trash takes it back out.
+		let restored = tx.execute(
+			r#"UPDATE data SET deleted_at = NULL WHERE id = ?1 AND deleted_at IS NOT NULL"#,
+			params![data_id]
+		)? != 0;
+
+		let recent = self.get_most_recent_data(tx, data_id)?;
+
+		let recent_items_after_previous = tx.query_row(
+			r#"SELECT COUNT(*) FROM recent WHERE date > ?1"#,
+			params![recent.date],
+			|v| v.get(0)
+		)?;
+
+		let current_date = Utc::now().timestamp_millis() as usize;
+
+		if restored || should_record_recopy(recent.date, current_date, recent_items_after_previous) {
+			self.insert_recent(tx, data_id, source)?;
+		}
+
+		Ok(())
+	}
+
+	fn insert_recent(&self, tx: &Transaction, data_id: usize, source: &CopySource) -> Result<usize> {
+		Ok(tx.execute(
+			r#"INSERT INTO recent (row_id, date, selection, source_app) VALUES (?1, ?2, ?3, ?4)"#,
+			params![ data_id, Utc::now().timestamp_millis() as usize, source.selection, source.app ]
+		)?)
+	}
+
+	fn get_data_from_hash(&self, conn: &Connection, hash: &str) -> Result<Option<CopiedData>> {
+		let data = conn.query_row(
+			r#"SELECT * FROM data WHERE hash = ?1 LIMIT 1"#,
+			params![hash],
+			CopiedData::from_row
+		).optional()?;
+
+		match data {
+			Some(mut data) => {
+				data.formats = self.get_format_names(conn, data.id)?;
+				Ok(Some(data))
+			}
+
+			None => Ok(None)
+		}
+	}
+
+	fn get_format_names(&self, conn: &Connection, data_id: usize) -> Result<Vec<String>> {
+		let mut stmt = conn.prepare(r#"SELECT format FROM data_formats WHERE row_id = ?1 ORDER BY id ASC"#)?;
+
+		let iter = stmt.query_map(params![data_id], |v| v.get(0))?;
+
+		Ok(iter.collect::<std::result::Result<Vec<_>, _>>()?)
+	}
+
+	fn get_most_recent_data(&self, conn: &Connection, data_id: usize) -> Result<LastCopied> {
+		Ok(conn.query_row(
+			r#"SELECT * FROM recent WHERE row_id = ?1 ORDER BY id DESC LIMIT 1"#,
+			params![data_id],
+			LastCopied::from_row
+		)?)
+	}
+}
+
+
+impl Store for SqliteStore {
+	fn query(&self, value: StorageQuery) -> Result<Vec<ReturnedItem>> {
+		self.delete_expired()?;
+
+		let query = match value {
+			StorageQuery::Favorites => ItemQuery::new().starred().in_star_order().distinct_data(),
+
+			StorageQuery::Pinned => ItemQuery::new().pinned().distinct_data(),
+
+			StorageQuery::Tag(name) => ItemQuery::new().tagged(&name).distinct_data(),
+
+			StorageQuery::Trash => ItemQuery::new().trashed().distinct_data(),
+
+			StorageQuery::Recent { limit, skip } => ItemQuery::new().limit(limit, skip),
+
+			StorageQuery::Advanced(query) => ItemQuery::new().search(&query).distinct_data(),
+
+			StorageQuery::Fuzzy { pattern, source_app } => {
+				let mut query = ItemQuery::new()
+					.has_text()
+					.fuzzy_candidates(&fuzzy::Candidates::new(&pattern))
+					.distinct_data()
+					.limit(fuzzy::CANDIDATE_LIMIT, 0);
+
+				if let Some(app) = source_app {
+					query = query.source_app(&app);
+				}
+
+				// Scored outside of SQLite.
+				return Ok(fuzzy::rank(query.query(&*self.pool.get()?)?, &pattern));
+			}
+
+			StorageQuery::Regex { pattern, source_app } => {
+				// Checked here for a readable error instead of one from inside SQLite.
+				let regex = Regex::new(&pattern)?;
+
+				let mut query = ItemQuery::new().regex(&pattern).distinct_data();
+
+				if let Some(app) = source_app {
+					query = query.source_app(&app);
+				}
+
+				let mut items = query.query(&*self.pool.get()?)?;
+
+				for item in &mut items {
+					if let (ReturnedItemType::Text(text), false) = (&item.value, item.is_masked) {
+						item.snippet = regex_snippet(&regex, text);
+					}
+				}
+
+				return Ok(items);
+			}
+
+			StorageQuery::Search { value, source_app } => {
+				// Searches without any words fall back to a scan.
+				let query = if has_words(&value) {
+					ItemQuery::new().matching(&value)
+				} else {
+					ItemQuery::new().text_contains(&value)
+				};
+
+				let query = query.distinct_data();
+
+				match source_app {
+					Some(app) => query.source_app(&app),
+					None => query
+				}
+			}
+		};
+
+		query.query(&*self.pool.get()?)
+	}
+
+	fn add_text(&self, text_data: String, html_data: Option<String>, source: &CopySource, config: &Config) -> Result<Option<usize>> {
+		if text_data.len() > config.stores.text.max_size * 1000 * 1000 { // B -> KB -> MB
+			log::info!(target: "clipboard_listener", "[add_text]: Text Length {}MB > Max Length {}MB", text_data.len() / 1000 / 1000, config.stores.text.max_size);
+			return Ok(None);
+		}
+
+		let action = privacy_action(&text_data, config);
+
+		if action == Some(SensitiveAction::Drop) {
+			return Ok(None);
+		}
+
+		let is_masked = action == Some(SensitiveAction::Mask);
+		let expires_at = expires_at(action, config);
+
+		// Masked text is only kept so it can be pasted. Its HTML would show it.
+		let html_data = html_data.filter(|_| !is_masked);
+
+		let hash = hash_data(text_data.as_bytes());
+
+		self.write(|tx, _| {
+			if let Some(v) = self.get_data_from_hash(tx, &hash)? {
+				// Already exists?
+				self.recopied(tx, v.id, source)?;
+
+				// Rules may have changed since it was stored.
+				if action.is_some() {
+					tx.execute(
+						r#"UPDATE data SET
+							is_masked = ?1,
+							expires_at = ?2,
+							html_size = IIF(?1, NULL, html_size),
+							html_data = IIF(?1, NULL, html_data)
+						WHERE id = ?3"#,
+						params![ is_masked, expires_at, v.id ]
+					)?;
+				}
+
+				if is_masked {
+					tx.execute(
+						r#"DELETE FROM data_formats WHERE row_id = ?1"#,
+						params![v.id]
+					)?;
+				}
+
+				return Ok(Some(v.id));
+			}
+
+			tx.execute(
+				r#"INSERT INTO data (hash, type_of, text_size, text_data, html_size, html_data, is_masked, expires_at) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8)"#,
+				params![ hash, 0, text_data.len(), text_data, html_data.as_deref().map(|v| v.len()), html_data, is_masked, expires_at ]
+			)?;
+
+			let data_id = tx.last_insert_rowid() as usize;
+
+			self.insert_recent(tx, data_id, source)?;
+
+			Ok(Some(data_id))
+		})
+	}
+
+	fn add_image(&self, image_data: Vec<u8>, image_thumb_data: Option<Vec<u8>>, source: &CopySource, config: &Config) -> Result<Option<usize>> {
+		if image_data.len() > config.stores.image.max_size * 1000 * 1000 { // B -> KB -> MB
+			log::info!(target: "clipboard_listener", "[add_image]: Image Length {}MB > Max Length {}MB", image_data.len() / 1000 / 1000, config.stores.image.max_size);
+			return Ok(None);
+		}
+
+		let hash = hash_data(&image_data);
+
+		// The data row, its image row and the image itself are all or nothing.
+		self.write(|tx, changes| {
+			if let Some(stored_data) = self.get_data_from_hash(tx, &hash)? {
+				// Already exists?
+				self.recopied(tx, stored_data.id, source)?;
+
+				return Ok(Some(stored_data.id));
+			}
+
+			tx.execute(
+				r#"INSERT INTO data (hash, type_of, image_size, image_thumb_size, image_thumb_data) VALUES (?1, ?2, ?3, ?4, ?5)"#,
+				params![ hash, 1, image_data.len(), image_thumb_data.as_deref().map(|v| v.len()), image_thumb_data ]
+			)?;
+
+			let data_id = tx.last_insert_rowid() as usize;
+
+			if config.stores.blobs.is_over_threshold(image_data.len()) {
+				self.acquire_blob(tx, changes, &hash, &image_data)?;
+
+				tx.execute(
+					r#"INSERT INTO data_images (id, blob_hash) VALUES (?1, ?2)"#,
+					params![ data_id, hash ]
+				)?;
+			} else {
+				self.write_image(tx, data_id, &image_data)?;
+			}
+
+			self.insert_recent(tx, data_id, source)?;
+
+			Ok(Some(data_id))
+		})
+	}
+
+	fn add_files(&self, files: Vec<CopiedFile>, source: &CopySource, config: &Config) -> Result<Option<usize>> {
+		// Paths are stored as the text so they show up in the list and in search.
+		let text_data = files.iter().map(|v| v.path.as_str()).collect::<Vec<_>>().join("\n");
+
+		if text_data.len() > config.stores.text.max_size * 1000 * 1000 { // B -> KB -> MB
+			log::info!(target: "clipboard_listener", "[add_files]: Path Length {}MB > Max Length {}MB", text_data.len() / 1000 / 1000, config.stores.text.max_size);
+			return Ok(None);
+		}
+
+		let hash = hash_files(&text_data);
+
+		self.write(|tx, changes| {
+			if let Some(stored_data) = self.get_data_from_hash(tx, &hash)? {
+				// Already exists?
+				self.recopied(tx, stored_data.id, source)?;
+
+				return Ok(Some(stored_data.id));
+			}
+
+			tx.execute(
+				r#"INSERT INTO data (hash, type_of, text_size, text_data) VALUES (?1, ?2, ?3, ?4)"#,
+				params![ hash, 2, text_data.len(), text_data ]
+			)?;
+
+			let data_id = tx.last_insert_rowid() as usize;
+
+			for file in files {
+				let mut file_data = file.file_data;
+				let mut blob_hash = None;
+
+				if let Some(data) = file_data.as_deref().filter(|v| config.stores.blobs.is_over_threshold(v.len())) {
+					let hash = hash_data(data);
+
+					self.acquire_blob(tx, changes, &hash, data)?;
+
+					file_data = None;
+					blob_hash = Some(hash);
+				}
+
+				tx.execute(
+					r#"INSERT INTO files (row_id, path, file_size, file_data, blob_hash) VALUES (?1, ?2, ?3, ?4, ?5)"#,
+					params![ data_id, file.path, file.file_size, file_data, blob_hash ]
+				)?;
+			}
+
+			self.insert_recent(tx, data_id, source)?;
+
+			Ok(Some(data_id))
+		})
+	}
+
+	fn set_formats(&self, data_id: usize, formats: &[(String, Vec<u8>)]) -> Result<()> {
+		self.write(|tx, _| {
+			tx.execute(
+				r#"DELETE FROM data_formats WHERE row_id = ?1"#,
+				params![data_id]
+			)?;
+
+			// Not kept for masked text, like its HTML.
+			for (format, format_data) in formats {
+				tx.execute(
+					r#"INSERT INTO data_formats (row_id, format, format_size, format_data) SELECT ?1, ?2, ?3, ?4 FROM data WHERE id = ?1 AND is_masked = 0"#,
+					params![ data_id, format, format_data.len(), format_data ]
+				)?;
+			}
+
+			Ok(())
+		})
+	}
+
+	fn set_favorite(&self, index: usize, value: bool) -> Result<usize> {
+		let conn = self.pool.get()?;
+
+		Ok(conn.execute(
+			r#"UPDATE data SET
+				is_starred = ?1,
+				star_position = CASE
+					WHEN NOT ?1 THEN NULL
+					WHEN is_starred THEN star_position
+					ELSE (SELECT IFNULL(MIN(star_position), 0) - 1 FROM data WHERE is_starred = 1)
+				END
+			WHERE id = ?2"#,
+			params![value, index]
+		)?)
+	}
+
+	fn move_favorite(&self, data_id: usize, index: usize) -> Result<usize> {
+		self.write(|tx, _| {
+			let mut ids = ItemQuery::new().starred().in_star_order().distinct_data()
+				.query(tx)?
+				.into_iter()
+				.map(|v| v.data_id)
+				.collect::<Vec<_>>();
+
+			let from = match ids.iter().position(|&v| v == data_id) {
+				Some(v) => v,
+				None => return Ok(0)
+			};
+
+			ids.remove(from);
+			ids.insert(index.min(ids.len()), data_id);
+
+			for (position, id) in ids.into_iter().enumerate() {
+				tx.execute(
+					r#"UPDATE data SET star_position = ?1 WHERE id = ?2"#,
+					params![position, id]
+				)?;
+			}
+
+			Ok(1)
+		})
+	}
+
+	fn set_pinned(&self, data_id: usize, value: bool) -> Result<usize> {
+		let conn = self.pool.get()?;
+
+		let pinned_at = if value { Some(Utc::now().timestamp_millis() as usize) } else { None };
+
+		Ok(conn.execute(
+			r#"UPDATE data SET pinned_at = ?1 WHERE id = ?2"#,
+			params![pinned_at, data_id]
+		)?)
+	}
+
+	fn set_title(&self, data_id: usize, title: &str) -> Result<usize> {
+		let conn = self.pool.get()?;
+
+		Ok(conn.execute(
+			r#"UPDATE data SET title = ?1 WHERE id = ?2"#,
+			params![optional_text(title), data_id]
+		)?)
+	}
+
+	fn set_note(&self, data_id: usize, note: &str) -> Result<usize> {
+		let conn = self.pool.get()?;
+
+		Ok(conn.execute(
+			r#"UPDATE data SET note = ?1 WHERE id = ?2"#,
+			params![optional_text(note), data_id]
+		)?)
+	}
+
+	fn edit_text(&self, data_id: usize, text_data: String, html_data: Option<String>, config: &Config) -> Result<usize> {
+		let action = check_edited_text(&text_data, config)?;
+
+		let html_data = html_data.filter(|_| action != Some(SensitiveAction::Mask));
+
+		let hash = hash_data(text_data.as_bytes());
+
+		// Whatever has the text is looked up under the write lock, so a copy can't add it in between.
+		self.write(|tx, changes| {
+			let data = tx.query_row(
+				r#"SELECT * FROM data WHERE id = ?1 LIMIT 1"#,
+				params![data_id],
+				CopiedData::from_row
+			)?;
+
+			if data.type_of != 0 {
+				bail!("Only text can be edited");
+			}
+
+			if hash == data.hash && html_data == data.html_data {
+				return Ok(data_id);
+			}
+
+			tx.execute(
+				r#"INSERT INTO data_revisions (data_id, date, text_data, html_data) VALUES (?1, ?2, ?3, ?4)"#,
+				params![ data_id, Utc::now().timestamp_millis() as usize, data.text_data, data.html_data ]
+			)?;
+
+			match self.get_data_from_hash(tx, &hash)?.filter(|v| v.id != data_id) {
+				// Merge into the data which already has the text.
+				Some(existing) => {
+					tx.execute(
+						r#"UPDATE recent SET row_id = ?1 WHERE row_id = ?2"#,
+						params![ existing.id, data_id ]
+					)?;
+
+					tx.execute(
+						r#"INSERT OR IGNORE INTO data_tags (data_id, tag_id) SELECT ?1, tag_id FROM data_tags WHERE data_id = ?2"#,
+						params![ existing.id, data_id ]
+					)?;
+
+					tx.execute(
+						r#"UPDATE data_revisions SET data_id = ?1 WHERE data_id = ?2"#,
+						params![ existing.id, data_id ]
+					)?;
+
+					tx.execute(
+						r#"UPDATE data SET
+							is_starred = MAX(is_starred, ?1),
+							star_position = IIF(is_starred, star_position, ?2),
+							pinned_at = IFNULL(pinned_at, ?3),
+							title = IFNULL(title, ?4),
+							note = IFNULL(note, ?5),
+							deleted_at = NULL
+						WHERE id = ?6"#,
+						params![ data.is_starred, data.star_position, data.pinned_at, data.title, data.note, existing.id ]
+					)?;
+
+					self.purge_data(tx, changes, data_id)?;
+
+					Ok(existing.id)
+				}
+
+				None => {
+					tx.execute(
+						r#"UPDATE data SET
+							hash = ?1,
+							text_size = ?2,
+							text_data = ?3,
+							html_size = ?4,
+							html_data = ?5,
+							is_masked = ?6,
+							expires_at = ?7
+						WHERE id = ?8"#,
+						params![
+							hash, text_data.len(), text_data, html_data.as_deref().map(|v| v.len()), html_data,
+							action == Some(SensitiveAction::Mask), expires_at(action, config), data_id
+						]
+					)?;
+
+					// They were copied alongside the old text.
+					tx.execute(
+						r#"DELETE FROM data_formats WHERE row_id = ?1"#,
+						params![data_id]
+					)?;
+
+					Ok(data_id)
+				}
+			}
+		})
+	}
+
+	fn get_revisions(&self, data_id: usize) -> Result<Vec<DataRevision>> {
+		let conn = self.pool.get()?;
+
+		let mut stmt = conn.prepare(r#"SELECT * FROM data_revisions WHERE data_id = ?1 ORDER BY date DESC, id DESC"#)?;
+
+		let iter = stmt.query_map(params![data_id], DataRevision::from_row)?;
+
+		Ok(iter.collect::<std::result::Result<Vec<_>, _>>()?)
+	}
+
+	fn create_tag(&self, name: &str) -> Result<usize> {
+		self.write(|tx, _| self.insert_tag(tx, name))
+	}
+
+	fn add_tag(&self, data_id: usize, name: &str) -> Result<usize> {
+		self.write(|tx, _| {
+			let tag_id = self.insert_tag(tx, name)?;
+
+			Ok(tx.execute(
+				r#"INSERT OR IGNORE INTO data_tags (data_id, tag_id) VALUES (?1, ?2)"#,
+				params![data_id, tag_id]
+			)?)
+		})
+	}
+
+	fn remove_tag(&self, data_id: usize, name: &str) -> Result<usize> {
+		let conn = self.pool.get()?;
+
+		Ok(conn.execute(
+			r#"DELETE FROM data_tags WHERE data_id = ?1 AND tag_id IN (SELECT id FROM tags WHERE name = ?2)"#,
+			params![data_id, name.trim()]
+		)?)
+	}
+
+	fn delete_tag(&self, name: &str) -> Result<usize> {
+		self.write(|tx, _| {
+			tx.execute(
+				r#"DELETE FROM data_tags WHERE tag_id IN (SELECT id FROM tags WHERE name = ?1)"#,
+				params![name.trim()]
+			)?;
+
+			Ok(tx.execute(
+				r#"DELETE FROM tags WHERE name = ?1"#,
+				params![name.trim()]
+			)?)
+		})
+	}
+
+	fn get_tags(&self) -> Result<Vec<Tag>> {
+		let conn = self.pool.get()?;
+
+		let mut stmt = conn.prepare(
+			r#"SELECT
+				tags.id,
+				tags.name,
+				COUNT(data.id)
+			FROM tags
+			LEFT JOIN data_tags ON data_tags.tag_id = tags.id
+			LEFT JOIN data ON data.id = data_tags.data_id AND data.deleted_at IS NULL
+			GROUP BY tags.id
+			ORDER BY tags.name ASC"#
+		)?;
+
+		let iter = stmt.query_map([], |v| Ok(Tag {
+			id: v.get(0)?,
+			name: v.get(1)?,
+			data_count: v.get(2)?,
+		}))?;
+
+		Ok(iter.collect::<std::result::Result<Vec<_>, _>>()?)
+	}
+
+	fn get_data_tags(&self, data_id: usize) -> Result<Vec<String>> {
+		let conn = self.pool.get()?;
+
+		let mut stmt = conn.prepare(
+			r#"SELECT tags.name FROM data_tags INNER JOIN tags ON tags.id = data_tags.tag_id WHERE data_tags.data_id = ?1 ORDER BY tags.name ASC"#
+		)?;
+
+		let iter = stmt.query_map(params![data_id], |v| v.get(0))?;
+
+		Ok(iter.collect::<std::result::Result<Vec<_>, _>>()?)
+	}
+
+	fn delete(&self, index: usize) -> Result<usize> {
+		let conn = self.pool.get()?;
+
+		Ok(conn.execute(
+			r#"UPDATE data SET deleted_at = ?1 WHERE id = ?2 AND deleted_at IS NULL"#,
+			params![Utc::now().timestamp_millis() as usize, index]
+		)?)
+	}
+
+	fn restore(&self, index: usize) -> Result<usize> {
+		let conn = self.pool.get()?;
+
+		Ok(conn.execute(
+			r#"UPDATE data SET deleted_at = NULL WHERE id = ?1 AND deleted_at IS NOT NULL"#,
+			params![index]
+		)?)
+	}
+
+	fn purge(&self, index: usize) -> Result<usize> {
+		self.write(|tx, changes| self.purge_data(tx, changes, index))
+	}
+
+	fn delete_expired(&self) -> Result<usize> {
+		let now = Utc::now().timestamp_millis() as usize;
+
+		// Runs before every query, so the write lock is only taken when there's something to delete.
+		let has_expired: bool = self.pool.get()?.query_row(
+			r#"SELECT EXISTS(SELECT 1 FROM data WHERE expires_at IS NOT NULL AND expires_at <= ?1)"#,
+			params![now],
+			|v| v.get(0)
+		)?;
+
+		if !has_expired {
+			return Ok(0);
+		}
+
+		self.write(|tx, changes| {
+			let ids = self.get_ids(tx, r#"SELECT id FROM data WHERE expires_at IS NOT NULL AND expires_at <= ?1"#, now)?;
+
+			let mut deleted = 0;
+
+			for id in ids {
+				deleted += self.purge_data(tx, changes, id)?;
+			}
+
+			Ok(deleted)
+		})
+	}
+
+	fn purge_trash(&self, deleted_before: usize) -> Result<usize> {
+		self.write(|tx, changes| {
+			let ids = self.get_ids(tx, r#"SELECT id FROM data WHERE deleted_at IS NOT NULL AND deleted_at <= ?1"#, deleted_before)?;
+
+			for &id in &ids {
+				self.purge_data(tx, changes, id)?;
+			}
+
+			Ok(ids.len())
+		})
+	}
+
+	fn clear_database(&self, force: bool) -> Result<usize> {
+		if !force {
+			return self.write(|tx, _| Ok(tx.execute(
+				r#"UPDATE data SET deleted_at = ?1 WHERE deleted_at IS NULL"#,
+				params![Utc::now().timestamp_millis() as usize]
+			)?));
+		}
+
+		let deleted = self.write(|tx, _| {
+			let data_deleted = tx.execute(
+				r#"DELETE FROM data WHERE 1"#,
+				[]
+			)?;
+
+			let recent_deleted = tx.execute(
+				r#"DELETE FROM recent WHERE 1"#,
+				[]
+			)?;
+
+			tx.execute(
+				r#"DELETE FROM files WHERE 1"#,
+				[]
+			)?;
+
+			tx.execute(
+				r#"DELETE FROM data_formats WHERE 1"#,
+				[]
+			)?;
+
+			tx.execute(
+				r#"DELETE FROM data_images WHERE 1"#,
+				[]
+			)?;
+
+			tx.execute(
+				r#"DELETE FROM blobs WHERE 1"#,
+				[]
+			)?;
+
+			tx.execute(
+				r#"DELETE FROM data_tags WHERE 1"#,
+				[]
+			)?;
+
+			tx.execute(
+				r#"DELETE FROM tags WHERE 1"#,
+				[]
+			)?;
+
+			tx.execute(
+				r#"DELETE FROM data_revisions WHERE 1"#,
+				[]
+			)?;
+
+			Ok(data_deleted + recent_deleted)
+		})?;
+
+		self.collect_garbage()?;
+
+		Ok(deleted)
+	}
+
+	fn get_data_usage(&self) -> Result<Vec<DataUsage>> {
+		let conn = self.pool.get()?;
+
+		let mut stmt = conn.prepare(
+			r#"SELECT
+				data.id,
+				data.is_starred,
+				data.pinned_at IS NOT NULL,
+				data.deleted_at IS NOT NULL,
+				IFNULL((SELECT MAX(recent.date) FROM recent WHERE recent.row_id = data.id), 0) AS last_copied,
+				IFNULL(data.text_size, 0) + IFNULL(data.html_size, 0) + IFNULL(data.image_size, 0) + IFNULL(data.image_thumb_size, 0)
+					+ IFNULL((SELECT SUM(format_size) FROM data_formats WHERE data_formats.row_id = data.id), 0)
+					+ IFNULL((
+						SELECT SUM(IFNULL(LENGTH(files.file_data), (SELECT size FROM blobs WHERE blobs.hash = files.blob_hash)))
+						FROM files
+						WHERE files.row_id = data.id
+					), 0)
+			FROM data
+			ORDER BY last_copied DESC, data.id DESC"#
+		)?;
+
+		let iter = stmt.query_map([], |v| Ok(DataUsage {
+			data_id: v.get(0)?,
+			is_starred: v.get(1)?,
+			is_pinned: v.get(2)?,
+			is_deleted: v.get(3)?,
+			last_copied: v.get(4)?,
+			size: v.get(5)?,
+		}))?;
+
+		Ok(iter.collect::<std::result::Result<Vec<_>, _>>()?)
+	}
+
+	fn get_data(&self, data_id: usize) -> Result<CopiedData> {
+		let conn = self.pool.get()?;
+
+		let mut data = conn.query_row(
+			r#"SELECT * FROM data WHERE id = ?1 LIMIT 1"#,
+			params![data_id],
+			CopiedData::from_row
+		)?;
+
+		data.formats = self.get_format_names(&conn, data.id)?;
+
+		if data.type_of == 1 {
+			data.image_data = Some(self.get_image(data.id)?);
+		}
+
+		Ok(data)
+	}
+
+	fn get_formats(&self, data_id: usize) -> Result<Vec<CopiedFormat>> {
+		let conn = self.pool.get()?;
+
+		let mut stmt = conn.prepare(r#"SELECT * FROM data_formats WHERE row_id = ?1 ORDER BY id ASC"#)?;
+
+		let iter = stmt.query_map(params![data_id], CopiedFormat::from_row)?;
+
+		Ok(iter.collect::<std::result::Result<Vec<_>, _>>()?)
+	}
+
+	fn get_files(&self, data_id: usize) -> Result<Vec<CopiedFile>> {
+		let conn = self.pool.get()?;
+
+		let mut stmt = conn.prepare(r#"SELECT id, row_id, path, file_size, file_data, blob_hash FROM files WHERE row_id = ?1 ORDER BY id ASC"#)?;
+
+		let iter = stmt.query_map(params![data_id], |row| Ok((CopiedFile::from_row(row)?, row.get::<_, Option<String>>(5)?)))?;
+
+		let mut files = Vec::new();
+
+		for value in iter {
+			let (mut file, blob_hash) = value?;
+
+			if let Some(hash) = blob_hash {
+				file.file_data = Some(self.blobs.read(&hash)?);
+			}
+
+			files.push(file);
+		}
+
+		Ok(files)
+	}
+
+	fn get_source_apps(&self) -> Result<Vec<String>> {
+		let conn = self.pool.get()?;
+
+		let mut stmt = conn.prepare(r#"SELECT DISTINCT source_app FROM recent WHERE source_app IS NOT NULL ORDER BY source_app ASC"#)?;
+
+		let iter = stmt.query_map([], |v| v.get(0))?;
+
+		Ok(iter.collect::<std::result::Result<Vec<_>, _>>()?)
+	}
+
+	fn get_image(&self, data_id: usize) -> Result<Vec<u8>> {
+		let conn = self.pool.get()?;
+
+		let blob_hash: Option<String> = conn.query_row(
+			r#"SELECT blob_hash FROM data_images WHERE id = ?1"#,
+			params![data_id],
+			|v| v.get(0)
+		)?;
+
+		if let Some(hash) = blob_hash {
+			return self.blobs.read(&hash);
+		}
+
+		let mut blob = conn.blob_open(DatabaseName::Main, "data_images", "image_data", data_id as i64, true)?;
+
+		let mut image_data = Vec::with_capacity(blob.len());
+		blob.read_to_end(&mut image_data)?;
+
+		Ok(image_data)
+	}
+
+	fn count_the_recents_newer_than(&self, time: usize) -> Result<usize> {
+		let conn = self.pool.get()?;
+
+		Ok(conn.query_row(
+			r#"SELECT COUNT(*) FROM recent WHERE date > ?1"#,
+			params![time],
+			|v| v.get(0)
+		)?)
+	}
+}
+
+
+pub(super) fn init_database(conn: &Connection) -> Result<()> {
+	conn.execute(r#"
+		CREATE TABLE IF NOT EXISTS data (
+			id					INTEGER NOT NULL,
+			hash				TEXT NOT NULL UNIQUE,
+			is_starred			BOOLEAN NOT NULL DEFAULT 0,
+
+			type_of				TINYINT NOT NULL,
+
+			text_size			INTEGER,
+			text_data			TEXT,
+
+			html_size			INTEGER,
+			html_data			TEXT,
+
+			image_size			INTEGER,
+			image_data			TEXT,
+			image_thumb_size	INTEGER,
+			image_thumb_data	TEXT,
+
+			PRIMARY KEY("id")
+		)
+		"#,
+		[]
+	)?;
+
+	conn.execute(r#"
+		CREATE TABLE IF NOT EXISTS recent (
+			id		INTEGER NOT NULL,
+			row_id	INTEGER NOT NULL,
+			date	INTEGER NOT NULL,
+
+			PRIMARY KEY("id")
+		)
+		"#,
+		[]
+	)?;
+
+	// Everything after the original tables.
+	migrations::migrate(conn)?;
+
+	Ok(())
+}
+
+
@@ -0,0 +1,458 @@
+// Drives the capture pipeline through MockBackend instead of an OS clipboard.
+
+use std::io::Cursor;
+use std::path::PathBuf;
+use std::sync::{Arc, RwLock};
+
+use clipboard_common::*;
+
+
+fn store() -> StorageContainer {
+	StorageContainer::open(MEMORY_PATH).unwrap()
+}
+
+/// Everything enabled.
+fn config() -> Config {
+	let mut config = Config::default();
+	config.stores.image.enabled = true;
+	config.stores.file.enabled = true;
+	config.stores.file.snapshot_contents = true;
+	config
+}
+
+/// Capture a single clipboard change.
+fn copy(store: &StorageContainer, config: &Config, contents: Vec<(&str, Vec<u8>)>) {
+	copy_from(store, config, None, contents);
+}
+
+fn copy_from(store: &StorageContainer, config: &Config, app: Option<&str>, contents: Vec<(&str, Vec<u8>)>) {
+	let mut backend = MockBackend::new();
+	backend.source_app = app.map(String::from);
+	backend.set_contents(contents);
+
+	capture(&mut backend, Selection::Clipboard, store, config).unwrap();
+}
+
+fn text(value: &str) -> (&'static str, Vec<u8>) {
+	(formats::TEXT, value.as_bytes().to_vec())
+}
+
+fn encode(width: u32, height: u32, format: image::ImageOutputFormat) -> Vec<u8> {
+	let image = image::RgbImage::from_fn(width, height, |x, y| image::Rgb([x as u8, y as u8, (x ^ y) as u8]));
+
+	let mut buffer = Cursor::new(Vec::new());
+	image::DynamicImage::ImageRgb8(image).write_to(&mut buffer, format).unwrap();
+
+	buffer.into_inner()
+}
+
+/// Data ids in the recents, newest first.
+fn stored(store: &StorageContainer) -> Vec<usize> {
+	store.query(StorageQuery::Recent { limit: 100, skip: 0 }).unwrap()
+		.into_iter()
+		.map(|v| v.data_id)
+		.collect()
+}
+
+/// The only thing stored.
+fn single(store: &StorageContainer) -> CopiedData {
+	let ids = stored(store);
+
+	assert_eq!(ids.len(), 1);
+
+	store.get_data(ids[0]).unwrap()
+}
+
+/// File in its own temporary directory, which is removed once dropped.
+struct TempFile {
+	path: PathBuf,
+}
+
+impl TempFile {
+	fn new(name: &str, contents: &[u8]) -> Self {
+		let dir = std::env::temp_dir().join(format!("clipboard-capture-{}-{}", std::process::id(), name));
+
+		std::fs::create_dir_all(&dir).unwrap();
+
+		let path = dir.join("copied file.txt");
+		std::fs::write(&path, contents).unwrap();
+
+		Self { path }
+	}
+
+	fn path(&self) -> String {
+		self.path.to_string_lossy().into_owned()
+	}
+}
+
+impl Drop for TempFile {
+	fn drop(&mut self) {
+		let _ = std::fs::remove_dir_all(self.path.parent().unwrap());
+	}
+}
+
+
+#[test]
+fn text_and_html() {
+	let store = store();
+
+	copy(&store, &config(), vec![text("hello"), (formats::HTML, b"<b>hello</b>".to_vec())]);
+
+	let data = single(&store);
+
+	assert_eq!(data.type_of, 0);
+	assert_eq!(data.text_data.as_deref(), Some("hello"));
+	assert_eq!(data.html_data.as_deref(), Some("<b>hello</b>"));
+	assert!(data.formats.is_empty());
+}
+
+#[test]
+fn images() {
+	let store = store();
+	let png = encode(100, 80, image::ImageOutputFormat::Png);
+
+	copy(&store, &config(), vec![(formats::PNG, png.clone())]);
+
+	let data = single(&store);
+
+	assert_eq!(data.type_of, 1);
+	assert_eq!(data.image_data, Some(png));
+	assert!(data.image_thumb_data.is_some());
+}
+
+#[test]
+fn images_are_off_by_default() {
+	let store = store();
+
+	copy(&store, &Config::default(), vec![(formats::PNG, encode(10, 10, image::ImageOutputFormat::Png))]);
+
+	assert!(stored(&store).is_empty());
+}
+
+#[test]
+fn uri_list() {
+	let store = store();
+	let file = TempFile::new("uri-list", b"file contents");
+
+	let uri_list = create_uri_list(&[file.path()]);
+
+	// The text offered alongside is only the path.
+	copy(&store, &config(), vec![(formats::URI_LIST, uri_list.into_bytes()), text(&file.path())]);
+
+	let data = single(&store);
+
+	assert_eq!(data.type_of, 2);
+	assert_eq!(data.text_data, Some(file.path()));
+
+	let files = store.get_files(data.id).unwrap();
+
+	assert_eq!(files.len(), 1);
+	assert_eq!(files[0].path, file.path());
+	assert_eq!(files[0].file_size, Some(13));
+	assert_eq!(files[0].file_data.as_deref(), Some(&b"file contents"[..]));
+}
+
+#[test]
+fn recopy_is_deduplicated() {
+	let store = store();
+
+	let mut backend = MockBackend::new();
+	backend.push_change(Selection::Clipboard, vec![text("first")]);
+	backend.push_change(Selection::Clipboard, vec![text("second")]);
+	backend.push_change(Selection::Clipboard, vec![text("first")]);
+
+	// Fails once there aren't any more changes.
+	assert!(listen(&mut backend, store.clone(), Arc::new(RwLock::new(Config::default()))).is_err());
+
+	let ids = stored(&store);
+
+	assert_eq!(ids.len(), 2);
+	assert_eq!(store.get_data(ids[0]).unwrap().text_data.as_deref(), Some("second"));
+	assert_eq!(store.get_data(ids[1]).unwrap().text_data.as_deref(), Some("first"));
+}
+
+#[test]
+fn concealed_copies_are_skipped() {
+	let store = store();
+	let hint = ("x-kde-passwordManagerHint", b"secret".to_vec());
+
+	copy(&store, &config(), vec![text("hunter2"), hint.clone()]);
+
+	assert!(stored(&store).is_empty());
+
+	let mut config = config();
+	config.privacy.respect_concealed_hints = false;
+
+	copy(&store, &config, vec![text("hunter2"), hint]);
+
+	assert_eq!(single(&store).text_data.as_deref(), Some("hunter2"));
+}
+
+#[test]
+fn ignored_apps_are_skipped() {
+	let store = store();
+
+	let mut config = config();
+	config.apps.ignore = vec![String::from("KeePassXC")];
+
+	copy_from(&store, &config, Some("keepassxc.exe"), vec![text("from the password manager")]);
+
+	assert!(stored(&store).is_empty());
+
+	copy_from(&store, &config, Some("firefox"), vec![text("from the browser")]);
+
+	assert_eq!(single(&store).text_data.as_deref(), Some("from the browser"));
+}
+
+#[test]
+fn text_max_size() {
+	let store = store();
+
+	let mut config = config();
+	config.stores.text.max_size = 1;
+
+	copy(&store, &config, vec![text(&"a".repeat(1_000_001))]);
+
+	assert!(stored(&store).is_empty());
+
+	copy(&store, &config, vec![text(&"a".repeat(1_000_000))]);
+
+	assert_eq!(single(&store).text_size, Some(1_000_000));
+}
+
+#[test]
+fn image_max_size() {
+	let store = store();
+
+	let mut config = config();
+	config.stores.image.max_size = 1;
+
+	// Uncompressed so it's over 1MB.
+	let large = encode(600, 600, image::ImageOutputFormat::Bmp);
+	assert!(large.len() > 1_000_000);
+
+	copy(&store, &config, vec![(formats::BMP, large)]);
+
+	assert!(stored(&store).is_empty());
+
+	copy(&store, &config, vec![(formats::BMP, encode(100, 100, image::ImageOutputFormat::Bmp))]);
+
+	assert_eq!(single(&store).type_of, 1);
+}
+
+#[test]
+fn file_max_size() {
+	let store = store();
+	let file = TempFile::new("file-max-size", &[7; 1_000_001]);
+
+	let mut config = config();
+	config.stores.file.max_size = 1;
+
+	copy(&store, &config, vec![(formats::URI_LIST, create_uri_list(&[file.path()]).into_bytes())]);
+
+	// Still stored, but without its contents.
+	let files = store.get_files(single(&store).id).unwrap();
+
+	assert_eq!(files[0].file_size, Some(1_000_001));
+	assert_eq!(files[0].file_data, None);
+}
+
+#[test]
+fn formats_max_size() {
+	let store = store();
+
+	let mut config = config();
+	config.stores.formats.max_size = 1;
+
+	copy(&store, &config, vec![
+		text("rich text"),
+		("text/rtf", vec![1; 600_000]),
+		// Past the 1MB shared by every other format.
+		("application/x-large", vec![2; 600_000]),
+		("application/x-small", vec![3; 100]),
+	]);
+
+	let data = single(&store);
+
+	assert_eq!(data.formats, vec!["text/rtf", "application/x-small"]);
+
+	let formats = store.get_formats(data.id).unwrap();
+
+	assert_eq!(formats[0].format_data.len(), 600_000);
+	assert_eq!(formats[1].format_data, vec![3; 100]);
+}
+
+#[test]
+fn other_formats_can_be_turned_off() {
+	let store = store();
+
+	let mut config = config();
+	config.stores.formats.enabled = false;
+
+	copy(&store, &config, vec![text("rich text"), ("text/rtf", vec![1; 10])]);
+
+	assert!(single(&store).formats.is_empty());
+}
+
+/// Runs `listen` until the queued changes run out.
+fn listen_to(backend: &mut MockBackend, store: &StorageContainer, config: Config) {
+	assert!(listen(backend, store.clone(), Arc::new(RwLock::new(config))).is_err());
+}
+
+fn synced_config(record_primary: bool) -> Config {
+	let mut config = config();
+	config.selections.record_primary = record_primary;
+	config.selections.sync_primary = true;
+	config
+}
+
+fn written(selection: Selection, value: &str) -> (Selection, Vec<(String, Vec<u8>)>) {
+	(selection, vec![(formats::TEXT.to_string(), value.as_bytes().to_vec())])
+}
+
+#[test]
+fn primary_is_off_by_default() {
+	let store = store();
+
+	let mut backend = MockBackend::new();
+	backend.push_change(Selection::Primary, vec![text("selected")]);
+
+	listen_to(&mut backend, &store, Config::default());
+
+	assert!(stored(&store).is_empty());
+	assert!(backend.written.is_empty());
+}
+
+#[test]
+fn primary_is_stored_and_synced_once() {
+	let store = store();
+
+	let mut backend = MockBackend::new();
+	backend.push_change(Selection::Primary, vec![text("selected")]);
+	// Reported once the sync lands.
+	backend.push_change(Selection::Clipboard, vec![text("selected")]);
+
+	listen_to(&mut backend, &store, synced_config(true));
+
+	assert_eq!(single(&store).text_data.as_deref(), Some("selected"));
+
+	// Not synced back into PRIMARY.
+	assert_eq!(backend.written, vec![written(Selection::Clipboard, "selected")]);
+}
+
+#[test]
+fn synced_write_is_not_captured() {
+	let store = store();
+
+	let mut backend = MockBackend::new();
+	backend.push_change(Selection::Primary, vec![text("selected")]);
+	backend.push_change(Selection::Clipboard, vec![text("selected")]);
+
+	// Only CLIPBOARD is stored, so storing anything means the write was taken for a copy.
+	listen_to(&mut backend, &store, synced_config(false));
+
+	assert!(stored(&store).is_empty());
+	assert_eq!(backend.written, vec![written(Selection::Clipboard, "selected")]);
+}
+
+#[test]
+fn sync_which_never_lands_keeps_the_next_copy() {
+	let store = store();
+
+	let mut backend = MockBackend::new();
+	backend.push_change(Selection::Primary, vec![text("selected")]);
+	backend.push_change(Selection::Clipboard, vec![text("copied")]);
+
+	listen_to(&mut backend, &store, synced_config(false));
+
+	assert_eq!(single(&store).text_data.as_deref(), Some("copied"));
+	assert_eq!(backend.written, vec![written(Selection::Clipboard, "selected"), written(Selection::Primary, "copied")]);
+}
+
+#[test]
+fn failed_sync_keeps_the_next_copy() {
+	let store = store();
+
+	let mut backend = MockBackend::new();
+	backend.fail_writes = true;
+	backend.push_change(Selection::Primary, vec![text("selected")]);
+	backend.push_change(Selection::Clipboard, vec![text("selected")]);
+
+	listen_to(&mut backend, &store, synced_config(false));
+
+	assert_eq!(single(&store).text_data.as_deref(), Some("selected"));
+	assert!(backend.written.is_empty());
+}
+
+/// What `restore` writes back for the data.
+fn restored(store: &StorageContainer, data_id: usize) -> Vec<(String, Vec<u8>)> {
+	let mut backend = MockBackend::new();
+
+	restore(&mut backend, data_id, store).unwrap();
+
+	assert_eq!(backend.written.len(), 1);
+
+	let (selection, formats) = backend.written.remove(0);
+	assert_eq!(selection, Selection::Clipboard);
+
+	formats
+}
+
+fn offered<'a>(formats: &'a [(String, Vec<u8>)], format: &str) -> Option<&'a [u8]> {
+	formats.iter().find(|(v, _)| v == format).map(|(_, data)| data.as_slice())
+}
+
+#[test]
+fn restore_offers_every_stored_format() {
+	let store = store();
+	let file = TempFile::new("restore", b"file contents");
+
+	copy(&store, &config(), vec![text("hello"), (formats::HTML, b"<b>hello</b>".to_vec()), ("text/rtf", b"{\\rtf1 hello}".to_vec())]);
+	let text_id = stored(&store)[0];
+
+	let png = encode(20, 10, image::ImageOutputFormat::Png);
+	copy(&store, &config(), vec![(formats::PNG, png.clone()), ("image/x-extra", vec![1, 2, 3])]);
+	let image_id = stored(&store)[0];
+
+	copy(&store, &config(), vec![(formats::URI_LIST, create_uri_list(&[file.path()]).into_bytes()), text(&file.path())]);
+	let files_id = stored(&store)[0];
+
+	let text_formats = restored(&store, text_id);
+	assert_eq!(text_formats.len(), 3);
+	assert_eq!(offered(&text_formats, formats::TEXT), Some(&b"hello"[..]));
+	assert_eq!(offered(&text_formats, formats::HTML), Some(&b"<b>hello</b>"[..]));
+	assert_eq!(offered(&text_formats, "text/rtf"), Some(&b"{\\rtf1 hello}"[..]));
+
+	let image_formats = restored(&store, image_id);
+	assert_eq!(image_formats.len(), 2);
+	assert_eq!(offered(&image_formats, formats::PNG), Some(png.as_slice()));
+	assert_eq!(offered(&image_formats, "image/x-extra"), Some(&[1, 2, 3][..]));
+
+	let files_formats = restored(&store, files_id);
+	assert_eq!(files_formats.len(), 2);
+	assert_eq!(offered(&files_formats, formats::TEXT), Some(file.path().as_bytes()));
+	assert_eq!(parse_uri_list(&String::from_utf8_lossy(offered(&files_formats, formats::URI_LIST).unwrap())), vec![file.path()]);
+
+	// Copying what was restored finds the same data again instead of storing anything new.
+	for formats in [text_formats, image_formats, files_formats] {
+		let mut backend = MockBackend::new();
+		backend.set_contents(formats);
+
+		capture(&mut backend, Selection::Clipboard, &store, &config()).unwrap();
+
+		assert_eq!(stored(&store), vec![files_id, image_id, text_id]);
+	}
+}
+
+#[test]
+fn restore_gives_bitmaps_back_as_png() {
+	let store = store();
+
+	copy(&store, &config(), vec![(formats::BMP, encode(20, 10, image::ImageOutputFormat::Bmp))]);
+
+	let image_formats = restored(&store, single(&store).id);
+	assert_eq!(image_formats.len(), 1);
+
+	let png = image::load_from_memory_with_format(offered(&image_formats, formats::PNG).unwrap(), image::ImageFormat::Png).unwrap();
+	assert_eq!((png.width(), png.height()), (20, 10));
+}
@@ -0,0 +1,286 @@
+// SqliteStore on disk, for what MemoryStore has no equivalent of: transactions and the blob directory.
+
+use std::path::PathBuf;
+
+use anyhow::Result;
+use clipboard_common::*;
+use rusqlite::Connection;
+
+
+/// Database in its own temporary directory, which is removed once dropped.
+struct TempDatabase {
+	dir: PathBuf,
+}
+
+impl TempDatabase {
+	fn new(name: &str) -> Self {
+		let dir = std::env::temp_dir().join(format!("clipboard-test-{}-{}", std::process::id(), name));
+
+		let _ = std::fs::remove_dir_all(&dir);
+		std::fs::create_dir_all(&dir).unwrap();
+
+		Self { dir }
+	}
+
+	fn path(&self) -> String {
+		self.dir.join("database.db").to_string_lossy().into_owned()
+	}
+
+	fn open(&self) -> StorageContainer {
+		StorageContainer::open(&self.path()).unwrap()
+	}
+
+	/// Separate connection for looking at and tampering with the tables.
+	fn connection(&self) -> Connection {
+		Connection::open(self.path()).unwrap()
+	}
+
+	fn count(&self, table: &str) -> usize {
+		self.connection().query_row(&format!("SELECT COUNT(*) FROM {}", table), [], |v| v.get(0)).unwrap()
+	}
+
+	/// Files in the blob directory.
+	fn blob_files(&self) -> usize {
+		let dirs = match std::fs::read_dir(self.dir.join("blobs")) {
+			Ok(v) => v,
+			Err(_) => return 0
+		};
+
+		dirs.map(|dir| std::fs::read_dir(dir.unwrap().path()).unwrap().count()).sum()
+	}
+}
+
+impl Drop for TempDatabase {
+	fn drop(&mut self) {
+		let _ = std::fs::remove_dir_all(&self.dir);
+	}
+}
+
+
+fn source() -> CopySource {
+	CopySource {
+		selection: Selection::Clipboard,
+		app: None
+	}
+}
+
+/// Large enough to be streamed in more than one chunk.
+fn image(seed: u8) -> Vec<u8> {
+	(0..200_000).map(|v| (v as u8).wrapping_mul(seed)).collect()
+}
+
+fn add_image(store: &StorageContainer, image_data: Vec<u8>, config: &Config) -> Result<Option<usize>> {
+	store.add_image(image_data, Some(vec![0; 4]), &source(), config)
+}
+
+/// Everything goes into the blob directory.
+fn blob_config() -> Config {
+	let mut config = Config::default();
+	config.stores.blobs.threshold = 0;
+	config
+}
+
+/// Schema from before there were any migrations, with a copy in it.
+fn create_baseline(db: &TempDatabase) {
+	db.connection().execute_batch(r#"
+		CREATE TABLE data (
+			id					INTEGER NOT NULL,
+			hash				TEXT NOT NULL UNIQUE,
+			is_starred			BOOLEAN NOT NULL DEFAULT 0,
+
+			type_of				TINYINT NOT NULL,
+
+			text_size			INTEGER,
+			text_data			TEXT,
+
+			html_size			INTEGER,
+			html_data			TEXT,
+
+			image_size			INTEGER,
+			image_data			TEXT,
+			image_thumb_size	INTEGER,
+			image_thumb_data	TEXT,
+
+			PRIMARY KEY("id")
+		);
+
+		CREATE TABLE recent (
+			id		INTEGER NOT NULL,
+			row_id	INTEGER NOT NULL,
+			date	INTEGER NOT NULL,
+
+			PRIMARY KEY("id")
+		);
+
+		INSERT INTO data (id, hash, type_of, text_size, text_data) VALUES (1, 'hash1', 0, 8, 'old copy');
+		INSERT INTO recent (id, row_id, date) VALUES (1, 1, 1000);
+	"#).unwrap();
+}
+
+/// Makes every following copy fail on its last insert.
+fn fail_recent_inserts(db: &TempDatabase) {
+	db.connection().execute_batch(
+		r#"CREATE TRIGGER fail_recent BEFORE INSERT ON recent BEGIN SELECT RAISE(ABORT, 'No recents'); END;"#
+	).unwrap();
+}
+
+
+#[test]
+fn baseline_is_upgraded_by_everything_opening_it_at_once() {
+	let db = TempDatabase::new("baseline-upgraded");
+
+	create_baseline(&db);
+
+	// Like the tray and the app starting together.
+	let opened = (0..4)
+		.map(|_| {
+			let path = db.path();
+			std::thread::spawn(move || StorageContainer::open(&path).map(|_| ()))
+		})
+		.collect::<Vec<_>>();
+
+	for thread in opened {
+		thread.join().unwrap().unwrap();
+	}
+
+	let store = db.open();
+
+	let recent = store.query(StorageQuery::Recent { limit: 10, skip: 0 }).unwrap();
+
+	assert_eq!(recent.len(), 1);
+	assert_eq!(store.get_data(recent[0].data_id).unwrap().text_data.as_deref(), Some("old copy"));
+
+	let version: usize = db.connection().query_row(r#"PRAGMA user_version"#, [], |v| v.get(0)).unwrap();
+	assert!(version > 0);
+}
+
+#[test]
+fn image_is_streamed_into_its_row() {
+	let db = TempDatabase::new("image-streamed");
+	let store = db.open();
+
+	let data_id = add_image(&store, image(3), &Config::default()).unwrap().unwrap();
+
+	assert_eq!(store.get_data(data_id).unwrap().image_data, Some(image(3)));
+	assert_eq!(db.count("data_images"), 1);
+}
+
+#[test]
+fn failed_image_copy_is_rolled_back() {
+	let db = TempDatabase::new("image-rolled-back");
+	let store = db.open();
+
+	fail_recent_inserts(&db);
+
+	assert!(add_image(&store, image(3), &Config::default()).is_err());
+
+	assert_eq!(db.count("data"), 0);
+	assert_eq!(db.count("data_images"), 0);
+}
+
+#[test]
+fn failed_blob_copy_removes_its_file() {
+	let db = TempDatabase::new("blob-rolled-back");
+	let store = db.open();
+
+	fail_recent_inserts(&db);
+
+	assert!(add_image(&store, image(3), &blob_config()).is_err());
+
+	assert_eq!(db.count("blobs"), 0);
+	assert_eq!(db.blob_files(), 0);
+}
+
+#[test]
+fn shared_blob_is_kept_until_every_copy_is_purged() {
+	let db = TempDatabase::new("blob-shared");
+	let store = db.open();
+	let config = blob_config();
+
+	let image_id = add_image(&store, image(3), &config).unwrap().unwrap();
+
+	let file = CopiedFile {
+		id: 0,
+		row_id: 0,
+		path: String::from("/tmp/image.bin"),
+		file_size: Some(200_000),
+		file_data: Some(image(3))
+	};
+
+	let files_id = store.add_files(vec![file], &source(), &config).unwrap().unwrap();
+
+	assert_eq!(db.count("blobs"), 1);
+	assert_eq!(db.blob_files(), 1);
+
+	store.purge(image_id).unwrap();
+
+	assert_eq!(db.blob_files(), 1);
+	assert_eq!(store.get_files(files_id).unwrap()[0].file_data, Some(image(3)));
+
+	store.purge(files_id).unwrap();
+
+	assert_eq!(db.count("blobs"), 0);
+	assert_eq!(db.blob_files(), 0);
+}
+
+#[test]
+fn failed_set_formats_keeps_the_old_ones() {
+	let db = TempDatabase::new("formats-rolled-back");
+	let store = db.open();
+
+	let data_id = store.add_text(String::from("text"), None, &source(), &Config::default()).unwrap().unwrap();
+
+	store.set_formats(data_id, &[(String::from("text/rtf"), vec![1, 2, 3])]).unwrap();
+
+	db.connection().execute_batch(
+		r#"CREATE TRIGGER fail_format BEFORE INSERT ON data_formats WHEN NEW.format = 'image/bmp' BEGIN SELECT RAISE(ABORT, 'No bitmaps'); END;"#
+	).unwrap();
+
+	let formats = [(String::from("text/rtf"), vec![4]), (String::from("image/bmp"), vec![5])];
+
+	assert!(store.set_formats(data_id, &formats).is_err());
+
+	let stored = store.get_formats(data_id).unwrap();
+
+	assert_eq!(stored.len(), 1);
+	assert_eq!(stored[0].format_data, vec![1, 2, 3]);
+}
+
+#[test]
+fn clear_database_removes_blob_files() {
+	let db = TempDatabase::new("blob-cleared");
+	let store = db.open();
+
+	add_image(&store, image(3), &blob_config()).unwrap();
+	add_image(&store, image(5), &blob_config()).unwrap();
+
+	assert_eq!(db.blob_files(), 2);
+
+	store.clear_database(true).unwrap();
+
+	assert_eq!(db.count("data"), 0);
+	assert_eq!(db.count("blobs"), 0);
+	assert_eq!(db.blob_files(), 0);
+}
+
+#[test]
+fn failed_merge_keeps_the_edited_copy() {
+	let db = TempDatabase::new("merge-rolled-back");
+	let store = db.open();
+	let config = Config::default();
+
+	let existing = store.add_text(String::from("merged"), None, &source(), &config).unwrap().unwrap();
+	let edited = store.add_text(String::from("edited"), None, &source(), &config).unwrap().unwrap();
+
+	// Fails at the very end of the merge, once the edited copy is being purged.
+	db.connection().execute_batch(
+		r#"CREATE TRIGGER fail_purge BEFORE DELETE ON data BEGIN SELECT RAISE(ABORT, 'No purging'); END;"#
+	).unwrap();
+
+	assert!(store.edit_text(edited, String::from("merged"), None, &config).is_err());
+
+	assert_eq!(store.get_data(edited).unwrap().text_data.as_deref(), Some("edited"));
+	assert_eq!(store.get_data(existing).unwrap().text_data.as_deref(), Some("merged"));
+	assert_eq!(db.count("recent"), 2);
+	assert_eq!(db.count("data_revisions"), 0);
+}
@@ -0,0 +1,85 @@
+// Needs an X server. Each test is skipped when $DISPLAY isn't set, and CI runs them under Xvfb
+//      (`xvfb-run cargo test --test x11`).
+
+#![cfg(not(windows))]
+
+use std::sync::{Mutex, mpsc};
+use std::thread;
+use std::time::Duration;
+
+use anyhow::Result;
+use clipboard_common::*;
+
+
+/// The tests share the X server's CLIPBOARD, so they take turns.
+static CLIPBOARD: Mutex<()> = Mutex::new(());
+
+fn has_display() -> bool {
+	if std::env::var_os("DISPLAY").is_none() {
+		eprintln!("Skipped: no X server on $DISPLAY");
+		return false;
+	}
+
+	true
+}
+
+/// Copy `text` through one connection and read it out through another. Returns the formats offered and the text read.
+fn copied_through_x11(text: &str) -> (Vec<String>, Vec<u8>) {
+	// Connected first so it sees the owner change.
+	let mut listener = X11Backend::connect().unwrap();
+	let mut owner = X11Backend::connect().unwrap();
+
+	let (sender, receiver) = mpsc::channel();
+
+	thread::spawn(move || {
+		let result = (|| -> Result<(Vec<String>, Vec<u8>)> {
+			// Something else could select text in the meantime.
+			while listener.wait_for_change()? != Selection::Clipboard {}
+
+			Ok((listener.formats()?, listener.read(formats::TEXT)?))
+		})();
+
+		let _ = sender.send(result);
+	});
+
+	// Owns CLIPBOARD from a thread of its own until the next test takes it.
+	owner.write(Selection::Clipboard, &[(formats::TEXT.to_string(), text.as_bytes().to_vec())]).unwrap();
+
+	receiver.recv_timeout(Duration::from_secs(10))
+		.expect("The owner change wasn't reported")
+		.unwrap()
+}
+
+
+#[test]
+fn reads_the_new_clipboard_owner() {
+	if !has_display() {
+		return;
+	}
+
+	let _clipboard = CLIPBOARD.lock().unwrap_or_else(|e| e.into_inner());
+
+	let text = format!("X11 clipboard test {}", std::process::id());
+
+	let (available, read) = copied_through_x11(&text);
+
+	assert!(available.iter().any(|v| v == formats::TEXT), "{:?}", available);
+	assert_eq!(String::from_utf8(read).unwrap(), text);
+}
+
+#[test]
+fn large_copies_are_sent_incrementally() {
+	if !has_display() {
+		return;
+	}
+
+	let _clipboard = CLIPBOARD.lock().unwrap_or_else(|e| e.into_inner());
+
+	// Several INCR chunks.
+	let text = (0..100_000).map(|v| format!("{:x}", v % 16)).collect::<String>().repeat(10);
+
+	let (_, read) = copied_through_x11(&text);
+
+	assert_eq!(read.len(), text.len());
+	assert!(read == text.as_bytes());
+}
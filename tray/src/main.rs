@@ -1,28 +1,49 @@
 #![windows_subsystem = "windows"]
 
 use anyhow::Result;
-use clipboard_common::{Listener, Config, StorageContainer};
-use lazy_static::lazy_static;
+use clipboard_common::{Listener, Config, StorageContainer, config::{ConfigRetention, ConfigTrash}};
 use log::LevelFilter;
 use log4rs::{config::{Root, Appender}, encode::pattern::PatternEncoder, append::file::FileAppender};
+use std::{sync::{Arc, RwLock, atomic::{AtomicBool, Ordering}}, thread, time::Duration};
+
+#[cfg(windows)]
+use lazy_static::lazy_static;
+#[cfg(windows)]
 use core::mem::MaybeUninit;
-use std::{sync::{Mutex, Arc, RwLock}, process::{Command, self}, path::PathBuf, thread};
+#[cfg(windows)]
+use std::{sync::Mutex, process::{Command, self}, path::PathBuf};
+#[cfg(windows)]
 use trayicon::*;
+#[cfg(windows)]
 use winapi::{um::{winuser, processthreadsapi::{TerminateProcess, OpenProcess}, winnt::{HANDLE, PROCESS_QUERY_INFORMATION, PROCESS_TERMINATE}, handleapi::CloseHandle}, shared::minwindef::DWORD};
 
+#[cfg(windows)]
 lazy_static! {
 	pub static ref APPLICATION: Mutex<Option<u32>> = Mutex::new(None);
 }
 
 
+/// How often the config file is checked for changes made in the GUI.
+const CONFIG_CHECK_INTERVAL: Duration = Duration::from_secs(2);
+
+/// Set while the config file has changed but can't be loaded. Cleanup is skipped until it's fixed.
+static CONFIG_IS_BROKEN: AtomicBool = AtomicBool::new(false);
+
+
 
 fn main() {
 	let init = || -> Result<()> {
 		// Logging
 		init_logging()?;
 
+		// Shared with the listener and kept up to date with changes from the GUI.
+		let config = init_config()?;
+
 		// Start Clipboard Listener.
-		init_listener()?;
+		init_listener(config.clone())?;
+
+		// Purge the trash and copies past the retention limits.
+		init_cleanup(config)?;
 
 		// Application
 		init_tray()?;
@@ -54,14 +75,47 @@ fn init_logging() -> Result<()> {
 }
 
 
-fn init_listener() -> Result<()> {
+fn init_config() -> Result<Arc<RwLock<Config>>> {
 	let config = Arc::new(RwLock::new(Config::load()?));
+	let shared = config.clone();
+
+	let mut modified = Config::modified();
+
+	thread::spawn(move || loop {
+		thread::sleep(CONFIG_CHECK_INTERVAL);
+
+		let now_modified = Config::modified();
+
+		if now_modified == modified {
+			continue;
+		}
+
+		modified = now_modified;
+
+		// Only loaded once per change, so rule warnings aren't repeated.
+		match Config::load() {
+			Ok(v) => {
+				*shared.write().unwrap() = v;
+				CONFIG_IS_BROKEN.store(false, Ordering::Relaxed);
+			}
+			Err(e) => {
+				log::error!("Reloading Config: {}", e);
+				CONFIG_IS_BROKEN.store(true, Ordering::Relaxed);
+			}
+		}
+	});
+
+	Ok(config)
+}
+
+
+fn init_listener(config: Arc<RwLock<Config>>) -> Result<()> {
 	let store = StorageContainer::open("userdata.db")?;
 
 	thread::spawn(move || {
 		log::info!("Starting Listener");
 
-		if let Err(e) = clipboard_common::AppListener::default().run(store, config) {
+		if let Err(e) = clipboard_common::AppListener.run(store, config) {
 			log::error!(target: "clipboard_listener", "{}", e);
 		}
 	});
@@ -70,6 +124,50 @@ fn init_listener() -> Result<()> {
 }
 
 
+fn init_cleanup(config: Arc<RwLock<Config>>) -> Result<()> {
+	let store = StorageContainer::open("userdata.db")?;
+
+	thread::spawn(move || loop {
+		// Cloned so the listener isn't held up while copies are deleted.
+		let (retention, trash) = {
+			let config = config.read().unwrap();
+			(config.retention.clone(), config.trash.clone())
+		};
+
+		// Limits from the last good config might be ones the user has since loosened.
+		if CONFIG_IS_BROKEN.load(Ordering::Relaxed) {
+			log::warn!("Skipping cleanup until the config can be loaded");
+		} else {
+			cleanup(&store, &retention, &trash);
+		}
+
+		thread::sleep(Duration::from_secs(retention.interval_minutes.max(1) as u64 * 60));
+	});
+
+	Ok(())
+}
+
+
+fn cleanup(store: &StorageContainer, retention: &ConfigRetention, trash: &ConfigTrash) {
+	if let Some(deleted_before) = trash.purge_before() {
+		match store.purge_trash(deleted_before) {
+			Ok(0) => (),
+			Ok(purged) => log::info!("Purged {} copies from the trash", purged),
+			Err(e) => log::error!("{}", e),
+		}
+	}
+
+	if retention.is_enabled() {
+		match store.apply_retention(retention) {
+			Ok(report) if report.removed() != 0 => log::info!("{}", report),
+			Ok(_) => (),
+			Err(e) => log::error!("{}", e),
+		}
+	}
+}
+
+
+#[cfg(windows)]
 fn init_tray() -> Result<()> {
 	#[derive(Copy, Clone, Eq, PartialEq, Debug)]
 	enum Events {
@@ -133,6 +231,16 @@ fn init_tray() -> Result<()> {
 }
 
 
+/// There's no tray icon outside of Windows yet. Keep the process alive for the listener thread.
+#[cfg(not(windows))]
+fn init_tray() -> Result<()> {
+	loop {
+		thread::park();
+	}
+}
+
+
+#[cfg(windows)]
 pub fn toggle_application() -> Result<()> {
 	let mut app = APPLICATION.lock().unwrap();
 
@@ -163,6 +271,7 @@ pub fn toggle_application() -> Result<()> {
 
 
 /// Application should be in the same folder as the tray
+#[cfg(windows)]
 fn path_to_application() -> Result<PathBuf> {
 	let mut app_path = std::env::current_exe()?;
 	app_path.set_file_name("clipboard-app.exe");
@@ -173,8 +282,10 @@ fn path_to_application() -> Result<PathBuf> {
 
 // https://stackoverflow.com/questions/55230450
 
+#[cfg(windows)]
 struct CommandProcess(HANDLE);
 
+#[cfg(windows)]
 impl CommandProcess {
     fn open(pid: DWORD) -> Result<Self> {
         // https://msdn.microsoft.com/en-us/library/windows/desktop/ms684320%28v=vs.85%29.aspx
@@ -193,6 +304,7 @@ impl CommandProcess {
     }
 }
 
+#[cfg(windows)]
 impl Drop for CommandProcess {
     fn drop(&mut self) {
         unsafe { CloseHandle(self.0) };